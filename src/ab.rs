@@ -0,0 +1,79 @@
+use serde::Serialize;
+
+use crate::output::{self, Render, Style};
+
+/// One of the two commits of an `ab` run.
+#[derive(Serialize)]
+pub struct Side {
+    pub commit: String,
+    pub mean: f64,
+    pub stddev: Option<f64>,
+    /// Id of the stored result
+    pub id: i64,
+}
+
+/// The output of `ab`.
+#[derive(Serialize)]
+pub struct Summary {
+    /// Id the results are stored with as their `ab_group`
+    pub group: String,
+    pub a: Side,
+    pub b: Side,
+}
+
+impl Summary {
+    /// How many times as long `b` took as `a`, below 1 when `b` is faster.
+    pub fn ratio(&self) -> f64 {
+        self.b.mean / self.a.mean
+    }
+}
+
+impl Render for Summary {
+    fn print_human(&self, style: &Style) {
+        let hash_len = output::hash_length(
+            [self.a.commit.as_str(), self.b.commit.as_str()],
+            style.hash_len,
+        );
+        let unit = style.unit_for([self.a.mean, self.b.mean]);
+        println!("A/B group {}", self.group);
+        for (name, side) in [("a", &self.a), ("b", &self.b)] {
+            let mean = match side.stddev {
+                Some(stddev) => format!(
+                    "{} ± {}",
+                    style.duration_in(side.mean, unit),
+                    style.duration_in(stddev, unit)
+                ),
+                None => style.duration_in(side.mean, unit),
+            };
+            println!(
+                "  {}  {}  {}  (id {})",
+                name,
+                output::short_hash(&side.commit, hash_len),
+                mean,
+                side.id
+            );
+        }
+        let ratio = self.ratio();
+        if ratio <= 1.0 {
+            println!("b ran {:.3} times faster than a", 1.0 / ratio);
+        } else {
+            println!("b ran {:.3} times slower than a", ratio);
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = ["ab_group", "side", "commit", "mean", "stddev", "id"];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for (name, side) in [("a", &self.a), ("b", &self.b)] {
+            rows.push(vec![
+                self.group.clone(),
+                name.to_string(),
+                side.commit.clone(),
+                side.mean.to_string(),
+                output::csv_value(side.stddev),
+                side.id.to_string(),
+            ]);
+        }
+        rows
+    }
+}
@@ -0,0 +1,163 @@
+use serde::Serialize;
+
+use crate::db::StoredResult;
+use crate::output::{self, Render, Style};
+
+/// Pool `results`, newest first, into one result per commit, command and snapshot
+/// height, ordered by their newest result. Each keeps the id and details of its
+/// newest result, with the mean and standard deviation of all their timings
+/// combined and `pooled_runs` set. Percentiles can't be pooled from the stored
+/// summaries, so they are left out.
+pub fn pool(results: Vec<StoredResult>) -> Vec<StoredResult> {
+    let mut groups: Vec<Vec<StoredResult>> = Vec::new();
+    for result in results {
+        let group = groups.iter_mut().find(|group| {
+            let newest = &group[0];
+            newest.commit_hash == result.commit_hash
+                && newest.command == result.command
+                && newest.snapshot_height == result.snapshot_height
+        });
+        match group {
+            Some(group) => group.push(result),
+            None => groups.push(vec![result]),
+        }
+    }
+    groups.into_iter().map(pool_group).collect()
+}
+
+fn pool_group(group: Vec<StoredResult>) -> StoredResult {
+    // Results imported without per-run exit codes count as a single timing
+    let samples: Vec<(f64, f64, Option<f64>)> = group
+        .iter()
+        .filter_map(|r| Some((r.exit_codes.len().max(1) as f64, r.mean?, r.stddev)))
+        .collect();
+    let (mean, stddev) = pooled(&samples);
+    let runs = group.len();
+    let exit_codes = group
+        .iter()
+        .flat_map(|r| r.exit_codes.iter().copied())
+        .collect();
+    let newest = group.into_iter().next().expect("groups are never empty");
+    StoredResult {
+        mean,
+        stddev,
+        exit_codes,
+        median: None,
+        min: None,
+        p50: None,
+        p90: None,
+        p99: None,
+        pooled_runs: Some(runs),
+        ..newest
+    }
+}
+
+/// Mean and sample standard deviation of every timing behind `samples`, each a
+/// result's number of timings, mean and standard deviation, as if they had been one
+/// run. The pooled variance adds the spread between the results' means to the
+/// spread within each:
+///
+/// `(Σ (nᵢ - 1) sᵢ² + Σ nᵢ (mᵢ - m)²) / (N - 1)`
+///
+/// The standard deviation is unknown if a result with several timings has none.
+fn pooled(samples: &[(f64, f64, Option<f64>)]) -> (Option<f64>, Option<f64>) {
+    let total: f64 = samples.iter().map(|(n, _, _)| n).sum();
+    if samples.is_empty() {
+        return (None, None);
+    }
+    let mean = samples.iter().map(|(n, mean, _)| n * mean).sum::<f64>() / total;
+    if total < 2.0 {
+        return (Some(mean), None);
+    }
+    let mut squares = 0.0;
+    for &(n, sample_mean, stddev) in samples {
+        let within = match stddev {
+            Some(stddev) => (n - 1.0) * stddev * stddev,
+            None if n <= 1.0 => 0.0,
+            None => return (Some(mean), None),
+        };
+        squares += within + n * (sample_mean - mean).powi(2);
+    }
+    (Some(mean), Some((squares / (total - 1.0)).sqrt()))
+}
+
+/// The output of `list --aggregate`.
+#[derive(Serialize)]
+pub struct Listing<'a> {
+    pub results: &'a [StoredResult],
+}
+
+impl Render for Listing<'_> {
+    fn print_human(&self, style: &Style) {
+        let results = self.results;
+        if results.is_empty() {
+            println!("No results stored");
+            return;
+        }
+
+        let hash_len = output::hash_length(
+            results.iter().map(|r| r.commit_hash.as_str()),
+            style.hash_len,
+        );
+        let unit = style.unit_for(results.iter().filter_map(|r| r.mean));
+        let timing = |secs: Option<f64>| secs.map_or("-".to_string(), |s| style.number(s, unit));
+        let label = |result: &StoredResult| {
+            result
+                .commit_alias
+                .clone()
+                .unwrap_or_else(|| output::short_hash(&result.commit_hash, hash_len).to_string())
+        };
+        let width = results
+            .iter()
+            .map(|r| label(r).len())
+            .max()
+            .unwrap_or(0)
+            .max("commit".len());
+        println!(
+            "{:<width$}  {:>12}  {:>10}  {:>5}  {:>7}  command",
+            "commit",
+            format!("mean ({})", output::suffix(unit)),
+            "stddev",
+            "runs",
+            "timings"
+        );
+        for result in results {
+            println!(
+                "{:<width$}  {:>12}  {:>10}  {:>5}  {:>7}  {}",
+                label(result),
+                timing(result.mean),
+                timing(result.stddev),
+                result.pooled_runs.unwrap_or(1),
+                result.exit_codes.len(),
+                result.command
+            );
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "commit",
+            "command",
+            "snapshot_height",
+            "mean",
+            "stddev",
+            "runs",
+            "timings",
+            "latest_id",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for result in self.results {
+            rows.push(vec![
+                result.commit_hash.clone(),
+                result.command.clone(),
+                output::csv_value(result.snapshot_height),
+                output::csv_value(result.mean),
+                output::csv_value(result.stddev),
+                result.pooled_runs.unwrap_or(1).to_string(),
+                result.exit_codes.len().to_string(),
+                result.id.to_string(),
+            ]);
+        }
+        rows
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Friendly names for commits, read from a `--commit-file` of `alias = "hash"` lines.
+pub struct Aliases(BTreeMap<String, String>);
+
+impl Aliases {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read commit file {}", path.display()))?;
+        let aliases = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse commit file {}", path.display()))?;
+        Ok(Aliases(aliases))
+    }
+
+    /// The commit to benchmark for `rev`, and the alias to store with it. `rev` may
+    /// be an alias, or a hash that an alias is defined for.
+    pub fn resolve(&self, rev: &str) -> (String, Option<String>) {
+        if let Some(hash) = self.0.get(rev) {
+            return (hash.clone(), Some(rev.to_string()));
+        }
+        let alias = self
+            .0
+            .iter()
+            .find(|(_, hash)| same_commit(hash, rev))
+            .map(|(alias, _)| alias.clone());
+        (rev.to_string(), alias)
+    }
+}
+
+/// Whether two possibly abbreviated hashes name the same commit.
+fn same_commit(a: &str, b: &str) -> bool {
+    let len = a.len().min(b.len());
+    len >= 7 && a.as_bytes()[..len].eq_ignore_ascii_case(&b.as_bytes()[..len])
+}
@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// A statistic of a result an assertion checks.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Mean,
+    Stddev,
+    Median,
+    Min,
+    Max,
+    /// Every run's exit code
+    ExitCode,
+    /// The height an IBD run synced to, as read from its `debug.log`
+    ReachedHeight,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("mean", Field::Mean),
+    ("stddev", Field::Stddev),
+    ("median", Field::Median),
+    ("min", Field::Min),
+    ("max", Field::Max),
+    ("exit_code", Field::ExitCode),
+    ("reached_height", Field::ReachedHeight),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+const OPS: &[(&str, Op)] = &[
+    ("<", Op::Lt),
+    ("<=", Op::Le),
+    (">", Op::Gt),
+    (">=", Op::Ge),
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+];
+
+impl Op {
+    fn holds(self, value: f64, bound: f64) -> bool {
+        match self {
+            Op::Lt => value < bound,
+            Op::Le => value <= bound,
+            Op::Gt => value > bound,
+            Op::Ge => value >= bound,
+            Op::Eq => value == bound,
+            Op::Ne => value != bound,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    Number(f64),
+    /// The height the run was to stop at
+    StopHeight,
+}
+
+/// An `assertions` rule of the form `field op value`, e.g. `mean < 3600`. The value
+/// is a number, or `stopatheight` for the height the run was to stop at.
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    field: Field,
+    field_name: &'static str,
+    op: Op,
+    bound: Bound,
+    text: String,
+}
+
+impl FromStr for Assertion {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let [field, op, bound] = text.split_whitespace().collect::<Vec<_>>()[..] else {
+            anyhow::bail!("Assertion `{}` is not of the form `field op value`", text);
+        };
+        let (field_name, field) =
+            *FIELDS
+                .iter()
+                .find(|(name, _)| *name == field)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unknown field `{}` in assertion `{}`, expected one of: {}",
+                        field,
+                        text,
+                        names(FIELDS)
+                    )
+                })?;
+        let op = OPS
+            .iter()
+            .find_map(|(name, o)| (*name == op).then_some(*o))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown operator `{}` in assertion `{}`, expected one of: {}",
+                    op,
+                    text,
+                    names(OPS)
+                )
+            })?;
+        let bound = match bound {
+            "stopatheight" => Bound::StopHeight,
+            number => Bound::Number(number.parse().with_context(|| {
+                format!(
+                    "`{}` in assertion `{}` is neither a number nor stopatheight",
+                    number, text
+                )
+            })?),
+        };
+        Ok(Assertion {
+            field,
+            field_name,
+            op,
+            bound,
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+        })
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// A result as assertions check it.
+pub struct Subject<'a> {
+    pub mean: f64,
+    pub stddev: Option<f64>,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    /// `None` where a run was killed by a signal
+    pub exit_codes: &'a [Option<i32>],
+    pub reached_height: Option<u64>,
+    pub stop_height: u64,
+}
+
+impl Assertion {
+    /// Why `subject` violates the assertion, if it does. A value that wasn't
+    /// recorded violates it, as it can't be shown to hold.
+    pub fn violation(&self, subject: &Subject) -> Option<String> {
+        let bound = match self.bound {
+            Bound::Number(number) => number,
+            Bound::StopHeight => subject.stop_height as f64,
+        };
+        let value = match self.field {
+            Field::Mean => Some(subject.mean),
+            Field::Stddev => subject.stddev,
+            Field::Median => Some(subject.median),
+            Field::Min => Some(subject.min),
+            Field::Max => Some(subject.max),
+            Field::ReachedHeight => subject.reached_height.map(|height| height as f64),
+            Field::ExitCode => {
+                return subject.exit_codes.iter().find_map(|code| match code {
+                    None => Some("a run was killed by a signal".to_string()),
+                    Some(code) if !self.op.holds(*code as f64, bound) => {
+                        Some(format!("a run exited with {}", code))
+                    }
+                    Some(_) => None,
+                });
+            }
+        };
+        let name = self.field_name;
+        match value {
+            None => Some(format!("{} is unknown", name)),
+            Some(value) if !self.op.holds(value, bound) => Some(format!("{} is {}", name, value)),
+            Some(_) => None,
+        }
+    }
+}
+
+/// The names in a table of fields or operators, for error messages.
+fn names<T>(table: &[(&str, T)]) -> String {
+    table
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parse every rule of `assertions`.
+pub fn parse_all(assertions: &[String]) -> Result<Vec<Assertion>> {
+    assertions.iter().map(|text| text.parse()).collect()
+}
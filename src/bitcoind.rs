@@ -0,0 +1,315 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::runner::Runner;
+use crate::seed;
+
+pub const BITCOIND: &str = "./build/src/bitcoind";
+pub const BITCOIN_CLI: &str = "./build/src/bitcoin-cli";
+pub const RPC_PORT: u16 = 8445;
+/// Most script verification threads bitcoind's `-par` accepts.
+pub const MAX_SCRIPT_CHECK_THREADS: i32 = 15;
+/// Smallest `-dbcache` bitcoind accepts, in megabytes.
+pub const MIN_DBCACHE: u64 = 4;
+
+/// Magic bytes every UTXO snapshot file starts with.
+const SNAPSHOT_MAGIC: &[u8] = b"utxo\xff";
+
+/// The version the built bitcoind reports, e.g. `v28.0.0rc1` from "Bitcoin Core
+/// version v28.0.0rc1". `None` if there is no binary to ask, as after a failed build.
+pub fn version(runner: &dyn Runner) -> Option<String> {
+    let output = runner.command(BITCOIND, &["--version"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, version) = stdout.lines().next()?.split_once(" version ")?;
+    Some(version.trim().to_string())
+}
+
+/// The bitcoind invocation timed by hyperfine.
+pub fn command(config: &Config) -> String {
+    command_using(config, BITCOIND)
+}
+
+/// The invocation timed by hyperfine of the bitcoind at `bitcoind`, e.g. a build
+/// copied aside for an A/B run.
+pub fn command_using(config: &Config, bitcoind: &str) -> String {
+    wrapped_command(config, bitcoind, &[])
+}
+
+/// The bitcoind invocation [`command`] times, run under `rr record` to save a
+/// replayable trace to `trace_dir`, which must not exist yet.
+pub fn record_command(config: &Config, trace_dir: &Path) -> String {
+    let rr = [
+        "rr".to_string(),
+        "record".to_string(),
+        format!("--output-trace-dir={}", trace_dir.display()),
+    ];
+    wrapped_command(config, BITCOIND, &rr)
+}
+
+/// The invocation of the bitcoind at `bitcoind`, run by `wrapper` inside the
+/// configured cgroup and CPU pinning.
+fn wrapped_command(config: &Config, bitcoind: &str, wrapper: &[String]) -> String {
+    let mut args = cgroup_prefix(config);
+    args.extend(taskset_prefix(config));
+    args.extend(wrapper.iter().cloned());
+    args.extend(node_args(config, bitcoind));
+    args.push(format!("-stopatheight={}", config.stopatheight));
+    args.extend(validation_args(config));
+    args.join(" ")
+}
+
+/// The shell run before every timed run: wipe the datadir, then restore the
+/// scenario's seed datadir or load the configured assumeutxo snapshot into it so
+/// the benchmark only syncs the blocks after it. The result of `loadtxoutset` is
+/// written to `snapshot_result`.
+pub fn prepare_command(config: &Config, snapshot_result: &Path) -> Result<String> {
+    prepare_command_using(config, snapshot_result, BITCOIND)
+}
+
+/// [`prepare_command`] for the bitcoind at `bitcoind`.
+pub fn prepare_command_using(
+    config: &Config,
+    snapshot_result: &Path,
+    bitcoind: &str,
+) -> Result<String> {
+    let datadir = config.datadir.display();
+    let mut steps = vec!["sync".to_string(), format!("rm -Rf {}/*", datadir)];
+    steps.extend(seed::restore_command(config));
+
+    if let Some(snapshot) = &config.assumeutxo_snapshot {
+        let base_hash = snapshot_base_hash(snapshot)?;
+        let cli = cli(config);
+        let mut daemon = node_args(config, bitcoind);
+        daemon.extend(validation_args(config));
+        daemon.push("-daemon".to_string());
+
+        steps.push(daemon.join(" "));
+        // loadtxoutset needs the snapshot's base block in the header chain
+        steps.push(format!(
+            "until {} getblockheader {} >/dev/null 2>&1; do sleep 1; done",
+            cli, base_hash
+        ));
+        steps.push(format!(
+            "{} -rpcclienttimeout=0 loadtxoutset {} > {}",
+            cli,
+            snapshot.display(),
+            snapshot_result.display()
+        ));
+        steps.push(format!("{} stop", cli));
+        steps.push(format!(
+            "while [ -e {}/bitcoind.pid ]; do sleep 1; done",
+            datadir
+        ));
+    }
+
+    Ok(steps.join(" && "))
+}
+
+/// The `bitcoin-cli` invocation timed by the `rpc` benchmark.
+pub fn rpc_command(config: &Config, rpc: &str) -> String {
+    format!("{} -rpcclienttimeout=0 {}", cli(config), rpc)
+}
+
+/// Flags for the node the `rpc` benchmark queries. It has no peers, so the chain
+/// stays the same across runs.
+pub const RPC_NODE_FLAGS: &[&str] = &[
+    "-connect=0",
+    "-listen=0",
+    "-dbcache=16385",
+    "-printtoconsole=0",
+];
+
+fn cli(config: &Config) -> String {
+    format!(
+        "{} -datadir={} -rpcport={}",
+        BITCOIN_CLI,
+        config.datadir.display(),
+        RPC_PORT
+    )
+}
+
+/// File in the datadir recording the height an `--append-to-existing` run left it at.
+const APPEND_HEIGHT_FILE: &str = "bench_append_height";
+
+/// The height the next appending run continues from: where the previous one
+/// stopped, or 0 for an empty datadir.
+pub fn appended_height(config: &Config) -> Result<u64> {
+    let marker = config.datadir.join(APPEND_HEIGHT_FILE);
+    match fs::read_to_string(&marker) {
+        Ok(height) => height
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid height in {}", marker.display())),
+        Err(_) => {
+            let empty =
+                fs::read_dir(&config.datadir).map_or(true, |mut entries| entries.next().is_none());
+            if empty {
+                Ok(0)
+            } else {
+                Err(anyhow::anyhow!(
+                    "{} is not empty but was not left by an appending run, so its height \
+                     is unknown; wipe it to start appending from genesis",
+                    config.datadir.display()
+                ))
+            }
+        }
+    }
+}
+
+/// Record that an appending run synced the datadir up to `height`.
+pub fn record_appended_height(config: &Config, height: u64) -> Result<()> {
+    let marker = config.datadir.join(APPEND_HEIGHT_FILE);
+    fs::write(&marker, height.to_string())
+        .with_context(|| format!("Failed to write {}", marker.display()))
+}
+
+/// The shell run after every timed run: record the size of the synced datadir in
+/// `size_file`, append the run's `debug.log` to `log_file` if given, then with
+/// `wipe` free the space it takes.
+pub fn cleanup_command(
+    config: &Config,
+    size_file: &Path,
+    log_file: Option<&Path>,
+    wipe: bool,
+) -> String {
+    let datadir = config.datadir.display();
+    let mut steps = vec![format!(
+        "du -sk {} | cut -f1 > {}",
+        datadir,
+        size_file.display()
+    )];
+    if let Some(log_file) = log_file {
+        // A run that died before writing a log must still be cleaned up after
+        steps.push(format!(
+            "{{ cat {}/debug.log >> {} || true; }}",
+            datadir,
+            log_file.display()
+        ));
+    }
+    if wipe {
+        steps.push(format!("rm -Rf {}/*", datadir));
+    }
+    steps.join(" && ")
+}
+
+/// The datadir's size on disk in bytes, as measured by the cleanup step into
+/// `size_file`, which is removed afterwards.
+pub fn datadir_size(size_file: &Path) -> Result<u64> {
+    let kib = fs::read_to_string(size_file)
+        .with_context(|| format!("Failed to read datadir size from {}", size_file.display()))?;
+    let kib: u64 = kib
+        .trim()
+        .parse()
+        .with_context(|| format!("Unexpected datadir size `{}`", kib.trim()))?;
+    fs::remove_file(size_file)
+        .with_context(|| format!("Failed to remove {}", size_file.display()))?;
+    Ok(kib * 1024)
+}
+
+/// The height of the block the snapshot loaded by the prepare step was taken at.
+pub fn snapshot_height(snapshot_result: &Path) -> Result<u64> {
+    let data = fs::read_to_string(snapshot_result).with_context(|| {
+        format!(
+            "Failed to read loadtxoutset result at {}",
+            snapshot_result.display()
+        )
+    })?;
+    let result: serde_json::Value =
+        serde_json::from_str(&data).with_context(|| "Failed to parse loadtxoutset result")?;
+    result["base_height"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("loadtxoutset result has no base_height"))
+}
+
+/// Arguments shared by the timed run and any node started while preparing it.
+fn node_args(config: &Config, bitcoind: &str) -> Vec<String> {
+    vec![
+        bitcoind.to_string(),
+        format!("-datadir={}", config.datadir.display()),
+        "-connect=127.0.0.1:8333".to_string(),
+        "-port=8444".to_string(),
+        format!("-rpcport={}", RPC_PORT),
+        format!("-dbcache={}", config.dbcache),
+        "-printtoconsole=0".to_string(),
+    ]
+}
+
+/// Overrides of bitcoind's validation defaults, appended last so commands run with
+/// the defaults match those stored before these settings existed.
+fn validation_args(config: &Config) -> Vec<String> {
+    let mut args: Vec<String> = config
+        .assumevalid
+        .iter()
+        .map(|assumevalid| format!("-assumevalid={}", assumevalid))
+        .collect();
+    if let Some(par) = config.par {
+        args.push(format!("-par={}", par));
+    }
+    args
+}
+
+/// Pin the node to `isolate_cpus`.
+fn taskset_prefix(config: &Config) -> Vec<String> {
+    match &config.isolate_cpus {
+        Some(cpus) => vec!["taskset".to_string(), "-c".to_string(), cpus.clone()],
+        None => Vec::new(),
+    }
+}
+
+/// Run the node in a transient cgroup limited to the configured memory and CPU.
+fn cgroup_prefix(config: &Config) -> Vec<String> {
+    let mut properties = Vec::new();
+    if let Some(memory) = &config.cgroup_memory {
+        properties.push(format!("MemoryMax={}", memory));
+    }
+    if let Some(cpu) = &config.cgroup_cpu {
+        properties.push(format!("CPUQuota={}", cpu));
+    }
+    if properties.is_empty() {
+        return Vec::new();
+    }
+    // Needs the cpuset controller, which the kernel has but systemd may not delegate
+    if let Some(cpus) = &config.isolate_cpus {
+        properties.push(format!("AllowedCPUs={}", cpus));
+    }
+
+    let mut args = vec![
+        "systemd-run".to_string(),
+        "--scope".to_string(),
+        "--quiet".to_string(),
+    ];
+    for property in properties {
+        args.push("-p".to_string());
+        args.push(property);
+    }
+    args
+}
+
+/// Read the hash of the block a UTXO snapshot was taken at from its metadata.
+fn snapshot_base_hash(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open UTXO snapshot at {}", path.display()))?;
+    // magic, 2-byte version, 4-byte network magic, then the base block hash
+    let mut header = [0u8; 43];
+    file.read_exact(&mut header)
+        .with_context(|| format!("Failed to read UTXO snapshot at {}", path.display()))?;
+    if !header.starts_with(SNAPSHOT_MAGIC) {
+        return Err(anyhow::anyhow!(
+            "{} is not a UTXO snapshot file",
+            path.display()
+        ));
+    }
+
+    // Hashes are serialized little-endian but displayed big-endian
+    Ok(header[11..]
+        .iter()
+        .rev()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
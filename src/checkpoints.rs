@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::progress;
+
+/// Logged once by every bitcoind start, before anything else of interest.
+const STARTUP_LINE: &str = "Bitcoin Core version";
+/// Logged once bitcoind has loaded its indexes and wallets and starts connecting.
+const LOADED_LINE: &str = "init message: Done loading";
+/// Logged once bitcoind has finished shutting down.
+const SHUTDOWN_LINE: &str = "Shutdown: done";
+
+/// How long an IBD run took to reach one of the configured `checkpoints`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Checkpoint {
+    pub height: u64,
+    /// Seconds from bitcoind's start
+    pub cumulative_secs: f64,
+    /// Seconds from the previous checkpoint, or from the start for the first
+    pub segment_secs: f64,
+}
+
+/// How long a bitcoind start spent in one of these phases of IBD, each ending where
+/// the next begins:
+/// - `load`: from the start to `Done loading`
+/// - `headers`: from then to the first `UpdateTip`, mostly syncing headers
+/// - `blocks`: from the first `UpdateTip` to the last, downloading and validating
+///   blocks, which overlap and are not told apart by the log
+/// - `shutdown`: from the last `UpdateTip` to `Shutdown: done`
+///
+/// Phases whose boundaries weren't logged are left out.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPhase {
+    pub name: &'static str,
+    pub secs: f64,
+}
+
+/// The phases of each bitcoind start in the part of `debug_log` from `offset` on,
+/// in the order bitcoind ran.
+pub fn phases_from_log(debug_log: &Path, offset: u64) -> Result<Vec<Vec<SyncPhase>>> {
+    let mut file =
+        File::open(debug_log).with_context(|| format!("Failed to open {}", debug_log.display()))?;
+    file.seek(SeekFrom::Start(offset))
+        .with_context(|| format!("Failed to read {}", debug_log.display()))?;
+
+    /// When each phase boundary of one start was logged
+    #[derive(Default)]
+    struct Marks {
+        start: Option<DateTime<FixedOffset>>,
+        loaded: Option<DateTime<FixedOffset>>,
+        first_tip: Option<DateTime<FixedOffset>>,
+        last_tip: Option<DateTime<FixedOffset>>,
+        shutdown: Option<DateTime<FixedOffset>>,
+    }
+    let mut starts: Vec<Marks> = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read {}", debug_log.display()))?;
+        let Some(time) = line
+            .split_whitespace()
+            .next()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        else {
+            continue;
+        };
+        if line.contains(STARTUP_LINE) {
+            starts.push(Marks {
+                start: Some(time),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(marks) = starts.last_mut() else {
+            continue;
+        };
+        if line.contains(LOADED_LINE) {
+            marks.loaded.get_or_insert(time);
+        } else if line.contains(SHUTDOWN_LINE) {
+            marks.shutdown = Some(time);
+        } else if progress::update_tip_height(&line).is_some() {
+            marks.first_tip.get_or_insert(time);
+            marks.last_tip = Some(time);
+        }
+    }
+
+    Ok(starts
+        .into_iter()
+        .map(|marks| {
+            [
+                ("load", marks.start, marks.loaded),
+                ("headers", marks.loaded, marks.first_tip),
+                ("blocks", marks.first_tip, marks.last_tip),
+                ("shutdown", marks.last_tip, marks.shutdown),
+            ]
+            .into_iter()
+            .filter_map(|(name, from, to)| {
+                Some(SyncPhase {
+                    name,
+                    secs: (to? - from?).as_seconds_f64(),
+                })
+            })
+            .collect()
+        })
+        .collect())
+}
+
+/// Seconds from a bitcoind start to its first `UpdateTip`, the sum of the `load`
+/// and `headers` phases, if both were logged.
+pub fn time_to_first_block(phases: &[SyncPhase]) -> Option<f64> {
+    let secs = |name| phases.iter().find(|p| p.name == name).map(|p| p.secs);
+    Some(secs("load")? + secs("headers")?)
+}
+
+/// Time each of `heights` in the part of `debug_log` from `offset` on, one list per
+/// bitcoind start found there, in the order bitcoind ran. A height is reached at the
+/// first `UpdateTip` at or above it. Heights a run never reached are left out.
+pub fn from_log(debug_log: &Path, offset: u64, heights: &[u64]) -> Result<Vec<Vec<Checkpoint>>> {
+    let mut heights = heights.to_vec();
+    heights.sort_unstable();
+    heights.dedup();
+
+    let mut file =
+        File::open(debug_log).with_context(|| format!("Failed to open {}", debug_log.display()))?;
+    file.seek(SeekFrom::Start(offset))
+        .with_context(|| format!("Failed to read {}", debug_log.display()))?;
+
+    let mut runs = Vec::new();
+    let mut start: Option<DateTime<FixedOffset>> = None;
+    let mut checkpoints: Vec<Checkpoint> = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read {}", debug_log.display()))?;
+        // Lines are prefixed with a timestamp unless -logtimestamps=0
+        let Some(time) = line
+            .split_whitespace()
+            .next()
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        else {
+            continue;
+        };
+        if line.contains(STARTUP_LINE) {
+            if start.is_some() {
+                runs.push(std::mem::take(&mut checkpoints));
+            }
+            start = Some(time);
+            continue;
+        }
+        let (Some(start), Some(height)) = (start, progress::update_tip_height(&line)) else {
+            continue;
+        };
+        let Some(&next) = heights.get(checkpoints.len()) else {
+            continue;
+        };
+        if height < next {
+            continue;
+        }
+        let cumulative_secs = (time - start).as_seconds_f64();
+        let reached = heights[checkpoints.len()..]
+            .iter()
+            .take_while(|&&checkpoint| checkpoint <= height);
+        // One tip can pass several checkpoints when they are close together
+        for &height in reached {
+            let previous = checkpoints.last().map_or(0.0, |c| c.cumulative_secs);
+            checkpoints.push(Checkpoint {
+                height,
+                cumulative_secs,
+                segment_secs: cumulative_secs - previous,
+            });
+        }
+    }
+    if start.is_some() {
+        runs.push(checkpoints);
+    }
+    Ok(runs)
+}
+
+/// The highest `UpdateTip` height each bitcoind start in the part of `debug_log`
+/// from `offset` on synced to, in the order bitcoind ran. `None` for starts that
+/// never updated the tip.
+pub fn final_heights(debug_log: &Path, offset: u64) -> Result<Vec<Option<u64>>> {
+    let mut file =
+        File::open(debug_log).with_context(|| format!("Failed to open {}", debug_log.display()))?;
+    file.seek(SeekFrom::Start(offset))
+        .with_context(|| format!("Failed to read {}", debug_log.display()))?;
+
+    let mut heights: Vec<Option<u64>> = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read {}", debug_log.display()))?;
+        if line.contains(STARTUP_LINE) {
+            heights.push(None);
+        } else if let (Some(last), Some(height)) =
+            (heights.last_mut(), progress::update_tip_height(&line))
+        {
+            *last = Some(last.map_or(height, |last| last.max(height)));
+        }
+    }
+    Ok(heights)
+}
@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::git;
+use crate::runner::Local;
+
+/// Which commits are worth benchmarking, by their subject and the paths they change.
+/// A commit must match every pattern that is set; with none set every commit does.
+pub struct CommitFilter {
+    subject: Option<Regex>,
+    paths: Option<Regex>,
+}
+
+impl CommitFilter {
+    /// The filter of `commit_subject_filter` and `commit_path_filter`, either
+    /// replaced by the given pattern if set.
+    pub fn new(config: &Config, subject: Option<&str>, paths: Option<&str>) -> Result<Self> {
+        let compile = |name: &str, pattern: Option<&str>| {
+            pattern
+                .map(|pattern| {
+                    Regex::new(pattern).with_context(|| format!("Invalid {} `{}`", name, pattern))
+                })
+                .transpose()
+        };
+        Ok(CommitFilter {
+            subject: compile(
+                "commit_subject_filter",
+                subject.or(config.commit_subject_filter.as_deref()),
+            )?,
+            paths: compile(
+                "commit_path_filter",
+                paths.or(config.commit_path_filter.as_deref()),
+            )?,
+        })
+    }
+
+    /// Why `commit` in `repo_path` should not be benchmarked, if it shouldn't.
+    pub fn skip_reason(&self, repo_path: &Path, commit: &str) -> Result<Option<String>> {
+        let runner = &Local::new(repo_path);
+        if let Some(subject_filter) = &self.subject {
+            let subject = git::subject(runner, commit)?;
+            if !subject_filter.is_match(&subject) {
+                return Ok(Some(format!(
+                    "subject does not match `{}`",
+                    subject_filter.as_str()
+                )));
+            }
+        }
+        if let Some(path_filter) = &self.paths {
+            let paths = git::changed_paths(runner, commit)?;
+            if !paths.iter().any(|path| path_filter.is_match(path)) {
+                return Ok(Some(format!(
+                    "no changed path matches `{}`",
+                    path_filter.as_str()
+                )));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,455 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::config::Metric;
+use crate::db;
+use crate::output::{self, Render, Style, TimeUnit};
+use crate::regression;
+
+/// The commit a comparison is made against.
+pub enum Base {
+    /// A commit with stored results
+    Stored(String),
+    /// The parent of the compared commit, which has not been benchmarked yet
+    Unbenchmarked(String),
+}
+
+/// Pick the base for comparing `commit_hash`: the explicitly requested commit, or
+/// the commit's recorded parent when none is given.
+pub fn resolve_base(conn: &Connection, commit_hash: &str, base: Option<&str>) -> Result<Base> {
+    if let Some(base) = base {
+        let base_hash = db::resolve_commit(conn, base)?
+            .ok_or_else(|| anyhow::anyhow!("No results stored for commit {}", base))?;
+        return Ok(Base::Stored(base_hash));
+    }
+
+    let parent = db::parent_hash(conn, commit_hash)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "No parent recorded for commit {}, pass --base explicitly",
+            commit_hash
+        )
+    })?;
+    if db::latest_results(conn, &parent)?.is_empty() {
+        Ok(Base::Unbenchmarked(parent))
+    } else {
+        Ok(Base::Stored(parent))
+    }
+}
+
+/// The change between two results for a command benchmarked in both.
+#[derive(Serialize)]
+pub struct Comparison<'a> {
+    pub command: &'a str,
+    pub base: &'a db::StoredResult,
+    pub commit: &'a db::StoredResult,
+    /// Change in `metric` relative to the base, in percent
+    pub delta_pct: f64,
+    /// Confidence in the less reliable of the two results, from 0 to 1
+    pub confidence: f64,
+    /// The regression threshold, widened for low confidence
+    pub threshold_pct: f64,
+    /// Whether `delta_pct` exceeds `threshold_pct`
+    pub regression: bool,
+}
+
+impl<'a> Comparison<'a> {
+    /// Compare `result` against `base` by `metric`, if both have it.
+    fn new(
+        base: &'a db::StoredResult,
+        result: &'a db::StoredResult,
+        metric: Metric,
+        threshold_pct: f64,
+    ) -> Option<Self> {
+        let (value, base_value) = (result.value(metric)?, base.value(metric)?);
+        let delta_pct = (value - base_value) / base_value * 100.0;
+        let confidence = confidence(result, value).min(confidence(base, base_value));
+        let widened_pct = regression::threshold_for(threshold_pct, confidence);
+        Some(Comparison {
+            command: &result.command,
+            base,
+            commit: result,
+            delta_pct,
+            confidence,
+            threshold_pct: widened_pct,
+            regression: delta_pct > widened_pct,
+        })
+    }
+}
+
+/// The output of `compare`, as printed in JSON mode.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    pub commit: &'a str,
+    pub commit_alias: Option<&'a str>,
+    /// Base commit, or the path of the baseline file
+    pub base: &'a str,
+    pub base_alias: Option<&'a str>,
+    /// Statistic the results were compared by
+    pub metric: Metric,
+    pub threshold_pct: f64,
+    pub comparisons: Vec<Comparison<'a>>,
+    /// Number of comparisons flagged as regressions
+    pub regressions: usize,
+}
+
+impl<'a> Report<'a> {
+    /// Compare every command present in both sets of results by `metric`.
+    pub fn new(
+        commit: &'a str,
+        results: &'a [db::StoredResult],
+        base: &'a str,
+        base_results: &'a [db::StoredResult],
+        metric: Metric,
+        threshold_pct: f64,
+    ) -> Self {
+        let mut comparisons = Vec::new();
+        for result in results {
+            let Some(base) = base_results.iter().find(|b| {
+                b.command == result.command && b.snapshot_height == result.snapshot_height
+            }) else {
+                continue;
+            };
+            comparisons.extend(Comparison::new(base, result, metric, threshold_pct));
+        }
+
+        let regressions = comparisons.iter().filter(|c| c.regression).count();
+        Report {
+            commit,
+            commit_alias: results.iter().find_map(|r| r.commit_alias.as_deref()),
+            base,
+            base_alias: base_results.iter().find_map(|r| r.commit_alias.as_deref()),
+            metric,
+            threshold_pct,
+            comparisons,
+            regressions,
+        }
+    }
+}
+
+impl Render for Report<'_> {
+    fn print_human(&self, style: &Style) {
+        let hash_len = output::hash_length([self.commit, self.base], style.hash_len);
+        println!(
+            "Comparing {} against {}{}",
+            self.commit_alias
+                .unwrap_or_else(|| output::short_hash(self.commit, hash_len)),
+            self.base_alias
+                .unwrap_or_else(|| output::short_hash(self.base, hash_len)),
+            match self.metric {
+                Metric::Mean => String::new(),
+                metric => format!(" by {}", metric.column()),
+            }
+        );
+        for comparison in &self.comparisons {
+            let (base, result) = (comparison.base, comparison.commit);
+            let unit = style.unit_for([base, result].iter().filter_map(|r| r.mean));
+            println!("{}", comparison.command);
+            println!("  base   {}", format_result(base, self.metric, style, unit));
+            if let Some(note) = &base.note {
+                println!("         note: {}", note);
+            }
+            println!(
+                "  commit {} ({:+.1}%, confidence {:.2}){}",
+                format_result(result, self.metric, style, unit),
+                comparison.delta_pct,
+                comparison.confidence,
+                if comparison.regression {
+                    " REGRESSION"
+                } else {
+                    ""
+                }
+            );
+            if let Some(note) = &result.note {
+                println!("         note: {}", note);
+            }
+        }
+
+        if self.comparisons.is_empty() {
+            println!("No commands were benchmarked in both");
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "command",
+            "base",
+            "base_mean",
+            "commit",
+            "commit_mean",
+            "metric",
+            "base_value",
+            "commit_value",
+            "delta_pct",
+            "confidence",
+            "threshold_pct",
+            "regression",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for c in &self.comparisons {
+            rows.push(vec![
+                c.command.to_string(),
+                self.base.to_string(),
+                output::csv_value(c.base.mean),
+                self.commit.to_string(),
+                output::csv_value(c.commit.mean),
+                self.metric.column().to_string(),
+                output::csv_value(c.base.value(self.metric)),
+                output::csv_value(c.commit.value(self.metric)),
+                c.delta_pct.to_string(),
+                c.confidence.to_string(),
+                c.threshold_pct.to_string(),
+                c.regression.to_string(),
+            ]);
+        }
+        rows
+    }
+}
+
+/// The output of `compare --compare-commands`: two commands benchmarked at the same
+/// commit, the second compared against the first.
+#[derive(Serialize)]
+pub struct CommandReport<'a> {
+    pub commit: &'a str,
+    pub commit_alias: Option<&'a str>,
+    /// Statistic the results were compared by
+    pub metric: Metric,
+    pub threshold_pct: f64,
+    /// `command` is the second command, the base's is the first
+    pub comparison: Comparison<'a>,
+    /// The command with the lower `metric`
+    pub faster: &'a str,
+}
+
+impl<'a> CommandReport<'a> {
+    /// Compare `result` against `base`, both stored for `commit`, by `metric`.
+    pub fn new(
+        commit: &'a str,
+        base: &'a db::StoredResult,
+        result: &'a db::StoredResult,
+        metric: Metric,
+        threshold_pct: f64,
+    ) -> Result<Self> {
+        let comparison = Comparison::new(base, result, metric, threshold_pct).ok_or_else(|| {
+            anyhow::anyhow!("Both commands need a stored {} to compare", metric.column())
+        })?;
+        let faster = if comparison.delta_pct < 0.0 {
+            &result.command
+        } else {
+            &base.command
+        };
+        Ok(CommandReport {
+            commit,
+            commit_alias: base.commit_alias.as_deref(),
+            metric,
+            threshold_pct,
+            comparison,
+            faster,
+        })
+    }
+}
+
+impl Render for CommandReport<'_> {
+    fn print_human(&self, style: &Style) {
+        let comparison = &self.comparison;
+        let (base, result) = (comparison.base, comparison.commit);
+        let unit = style.unit_for([base, result].iter().filter_map(|r| r.mean));
+        let hash_len = output::hash_length([self.commit], style.hash_len);
+        println!(
+            "Comparing commands at {}{}",
+            self.commit_alias
+                .unwrap_or_else(|| output::short_hash(self.commit, hash_len)),
+            match self.metric {
+                Metric::Mean => String::new(),
+                metric => format!(" by {}", metric.column()),
+            }
+        );
+        println!("base    {}", base.command);
+        println!("        {}", format_result(base, self.metric, style, unit));
+        println!("command {}", result.command);
+        println!(
+            "        {} ({:+.1}%, confidence {:.2}){}",
+            format_result(result, self.metric, style, unit),
+            comparison.delta_pct,
+            comparison.confidence,
+            if comparison.regression {
+                " REGRESSION"
+            } else {
+                ""
+            }
+        );
+        if comparison.delta_pct == 0.0 {
+            println!("Neither command is faster");
+        } else {
+            println!(
+                "Faster by {:.1}%: {}",
+                comparison.delta_pct.abs(),
+                self.faster
+            );
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "commit",
+            "base_command",
+            "command",
+            "metric",
+            "base_value",
+            "value",
+            "delta_pct",
+            "confidence",
+            "threshold_pct",
+            "regression",
+            "faster",
+        ];
+        let c = &self.comparison;
+        vec![
+            header.map(String::from).to_vec(),
+            vec![
+                self.commit.to_string(),
+                c.base.command.clone(),
+                c.command.to_string(),
+                self.metric.column().to_string(),
+                output::csv_value(c.base.value(self.metric)),
+                output::csv_value(c.commit.value(self.metric)),
+                c.delta_pct.to_string(),
+                c.confidence.to_string(),
+                c.threshold_pct.to_string(),
+                c.regression.to_string(),
+                self.faster.to_string(),
+            ],
+        ]
+    }
+}
+
+/// The result in `results` of the command `wanted`, matched exactly or else by
+/// being the only command containing it.
+pub fn find_command<'a>(
+    results: &'a [db::StoredResult],
+    wanted: &str,
+) -> Result<&'a db::StoredResult> {
+    if let Some(result) = results.iter().find(|r| r.command == wanted) {
+        return Ok(result);
+    }
+    let matching: Vec<&db::StoredResult> = results
+        .iter()
+        .filter(|r| r.command.contains(wanted))
+        .collect();
+    match matching.as_slice() {
+        [result] => Ok(result),
+        [] => Err(anyhow::anyhow!("No stored command contains `{}`", wanted)),
+        _ => Err(anyhow::anyhow!(
+            "{} stored commands contain `{}`, give more of one:\n{}",
+            matching.len(),
+            wanted,
+            matching
+                .iter()
+                .map(|r| format!("  {}", r.command))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )),
+    }
+}
+
+/// The distinct hyperfine versions behind `results`, if there is more than one.
+/// hyperfine's behaviour changes between versions, so such timings may differ for
+/// reasons other than the commits.
+pub fn mixed_hyperfine_versions<'a>(
+    results: impl IntoIterator<Item = &'a db::StoredResult>,
+) -> Option<Vec<&'a str>> {
+    let mut versions: Vec<&str> = results
+        .into_iter()
+        .filter_map(|r| r.hyperfine_version.as_deref())
+        .collect();
+    versions.sort_unstable();
+    versions.dedup();
+    (versions.len() > 1).then_some(versions)
+}
+
+/// Load a hyperfine results.json export to compare against, e.g. a CI artifact.
+pub fn baseline_from_file(path: &Path) -> Result<Vec<db::StoredResult>> {
+    let results = db::read_results_file(path)?;
+    Ok(results
+        .results
+        .into_iter()
+        .map(|r| db::StoredResult {
+            id: 0,
+            commit_hash: r.parameters.map(|p| p.commit).unwrap_or_default(),
+            commit_alias: None,
+            command: r.command,
+            mean: Some(r.mean),
+            stddev: r.stddev,
+            median: Some(r.median),
+            min: Some(r.min),
+            p50: None,
+            p90: None,
+            p99: None,
+            blocks_per_sec: None,
+            status: None,
+            note: None,
+            assumeutxo_snapshot: None,
+            snapshot_height: None,
+            exit_codes: r.exit_codes,
+            is_baseline: false,
+            throttled: None,
+            pr_number: None,
+            hyperfine_version: None,
+            toolchain: None,
+            run_label: None,
+            stop_height: None,
+            reached_height: None,
+            rr_trace: None,
+            failed_assertions: Vec::new(),
+            guix_output_hash: None,
+            pooled_runs: None,
+        })
+        .collect())
+}
+
+/// Confidence in `result`, compared by `value`, from its run count and stddev.
+fn confidence(result: &db::StoredResult, value: f64) -> f64 {
+    regression::confidence(result.exit_codes.len(), value, result.stddev)
+}
+
+/// `result`'s mean and spread, followed by `metric` when that is what was compared.
+fn format_result(
+    result: &db::StoredResult,
+    metric: Metric,
+    style: &Style,
+    unit: TimeUnit,
+) -> String {
+    let mut formatted = format_mean(result, style, unit);
+    if metric != Metric::Mean {
+        if let Some(value) = result.value(metric) {
+            formatted.push_str(&format!(
+                ", {} {}",
+                metric.column(),
+                style.duration_in(value, unit)
+            ));
+        }
+    }
+    formatted
+}
+
+fn format_mean(result: &db::StoredResult, style: &Style, unit: TimeUnit) -> String {
+    let duration = |secs: f64| style.duration_in(secs, unit);
+    let mean = result.mean.unwrap_or_default();
+    let mut formatted = match result.stddev {
+        Some(stddev) => format!("{} ± {}", duration(mean), duration(stddev)),
+        None => duration(mean),
+    };
+    if let (Some(p90), Some(p99)) = (result.p90, result.p99) {
+        formatted.push_str(&format!(" (p90 {}, p99 {})", duration(p90), duration(p99)));
+    } else if let Some(p90) = result.p90 {
+        formatted.push_str(&format!(" (p90 {})", duration(p90)));
+    }
+    if let Some(blocks_per_sec) = result.blocks_per_sec {
+        formatted.push_str(&format!(" [{:.1} blocks/s]", blocks_per_sec));
+    }
+    if let Some(runs) = result.pooled_runs {
+        formatted.push_str(&format!(" pooled over {} runs", runs));
+    }
+    formatted
+}
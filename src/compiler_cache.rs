@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A compiler cache that may be wrapping the build.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CompilerCache {
+    Ccache,
+    Sccache,
+}
+
+/// Cumulative hit/miss counters reported by a compiler cache.
+#[derive(Clone, Copy)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+}
+
+impl CompilerCache {
+    /// Detect the compiler cache the build will use. An explicit CMake launcher wins,
+    /// otherwise Bitcoin Core's CMake picks up ccache automatically when installed.
+    pub fn detect() -> Option<Self> {
+        if let Ok(launcher) = std::env::var("CMAKE_CXX_COMPILER_LAUNCHER") {
+            let name = Path::new(&launcher).file_name()?.to_str()?;
+            return match name {
+                "ccache" => Some(Self::Ccache),
+                "sccache" => Some(Self::Sccache),
+                _ => None,
+            };
+        }
+        is_installed("ccache").then_some(Self::Ccache)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ccache => "ccache",
+            Self::Sccache => "sccache",
+        }
+    }
+
+    pub fn stats(self) -> Option<CacheStats> {
+        match self {
+            Self::Ccache => {
+                let output = Command::new("ccache").arg("--print-stats").output().ok()?;
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let mut stats = CacheStats { hits: 0, misses: 0 };
+                for line in stdout.lines() {
+                    let Some((key, value)) = line.split_once('\t') else {
+                        continue;
+                    };
+                    let value: u64 = value.trim().parse().unwrap_or(0);
+                    match key {
+                        "direct_cache_hit" | "preprocessed_cache_hit" => stats.hits += value,
+                        "cache_miss" => stats.misses += value,
+                        _ => {}
+                    }
+                }
+                Some(stats)
+            }
+            Self::Sccache => {
+                let output = Command::new("sccache")
+                    .args(["--show-stats", "--stats-format", "json"])
+                    .output()
+                    .ok()?;
+                let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+                let sum = |key: &str| -> u64 {
+                    json["stats"][key]["counts"]
+                        .as_object()
+                        .map(|counts| counts.values().filter_map(|v| v.as_u64()).sum())
+                        .unwrap_or(0)
+                };
+                Some(CacheStats {
+                    hits: sum("cache_hits"),
+                    misses: sum("cache_misses"),
+                })
+            }
+        }
+    }
+
+    /// Empty the cache so the next build is cold.
+    pub fn clear(self) -> Result<()> {
+        match self {
+            Self::Ccache => {
+                let status = Command::new("ccache")
+                    .arg("--clear")
+                    .status()
+                    .with_context(|| "Failed to execute ccache --clear")?;
+                if !status.success() {
+                    return Err(anyhow::anyhow!(
+                        "ccache --clear failed with status {}",
+                        status
+                    ));
+                }
+            }
+            Self::Sccache => {
+                eprintln!(
+                    "Warning: sccache does not support clearing its cache, build may be warm"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fraction of compilations served from the cache between two stat snapshots.
+pub fn hit_rate(before: CacheStats, after: CacheStats) -> Option<f64> {
+    let hits = after.hits.saturating_sub(before.hits);
+    let misses = after.misses.saturating_sub(before.misses);
+    let total = hits + misses;
+    (total > 0).then(|| hits as f64 / total as f64)
+}
+
+fn is_installed(binary: &str) -> bool {
+    Command::new(binary)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
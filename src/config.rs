@@ -0,0 +1,913 @@
+use anyhow::{Context, Result};
+use chrono_tz::Tz;
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::{Table, Value};
+
+use crate::assertions;
+use crate::bitcoind;
+use crate::commit_filter::CommitFilter;
+use crate::git::DirtyPolicy;
+use crate::notify::NotifyOn;
+use crate::output::TimeUnit;
+use crate::system;
+use crate::Phase;
+
+/// Decimal places beyond nanoseconds only show measurement noise.
+const MAX_PRECISION: usize = 9;
+
+/// Runtime configuration, optionally loaded from a TOML file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory holding the tool's own state, created if missing. Paths under it,
+    /// such as `db_path`, are defaults that can still be set individually.
+    pub data_root: Option<PathBuf>,
+    /// Path to the bitcoin source checkout
+    pub repo_path: PathBuf,
+    /// Host, as `ssh` accepts it, e.g. `bench@10.0.0.2`, that fetches, builds and
+    /// benchmarks instead of this machine. `datadir` is then a path on that host,
+    /// results are copied back and stored here.
+    pub ssh_host: Option<String>,
+    /// Bitcoin source checkout on `ssh_host`, defaults to `repo_path`
+    pub remote_repo_path: Option<PathBuf>,
+    /// Path to the SQLite results database
+    pub db_path: PathBuf,
+    /// Data directory bitcoind syncs into, wiped before every IBD run. The `rpc`
+    /// benchmark instead expects it to hold an already synced chain.
+    pub datadir: PathBuf,
+    /// Leave the datadir as the IBD run left it instead of wiping it afterwards,
+    /// e.g. to inspect the chainstate
+    pub keep_datadir: bool,
+    /// Named alternative datadirs on different storage media, e.g. `ssd = "/mnt/ssd/.bitcoin"`
+    pub storage: BTreeMap<String, PathBuf>,
+    /// What each run times
+    pub benchmark: Benchmark,
+    /// Arguments appended to the timed command, which may use `{name}` placeholders
+    /// for `--param` lists, e.g. `-par={par}`
+    pub extra_args: Vec<String>,
+    /// Blocks each `--append-to-existing` run syncs beyond the previous one
+    pub append_chunk_blocks: u64,
+    /// RPC timed by the `rpc` benchmark, with its arguments, e.g. `gettxoutsetinfo`
+    pub rpc_command: Option<String>,
+    /// Seconds the node the `rpc` benchmark starts may take to answer RPCs before
+    /// it is killed, e.g. while it verifies the chainstate on startup
+    pub node_ready_timeout_secs: u64,
+    /// Shell that runs the build and hyperfine's prepare, cleanup and timed commands,
+    /// e.g. `bash` for commands using bashisms. Defaults to `sh` for portability.
+    pub shell: String,
+    /// Height bitcoind stops syncing at, via `-stopatheight`
+    pub stopatheight: u64,
+    /// Heights, below `stopatheight`, an IBD run is also timed at, from the
+    /// `UpdateTip` lines of its `debug.log`
+    pub checkpoints: Vec<u64>,
+    /// Height of a short, untimed sync each IBD run starts with, so a broken binary
+    /// or config fails in seconds rather than after hours. The full run only goes
+    /// ahead if it succeeds, and nothing is stored for it.
+    pub smoke_height: Option<u64>,
+    /// Exit codes of the timed command that count as success besides 0, e.g. for
+    /// bitcoind versions exiting nonzero when stopped by `-stopatheight`. hyperfine
+    /// then keeps runs ending with them, and the codes are stored as they were.
+    pub expected_exit_codes: Vec<i32>,
+    /// Block hash passed as `-assumevalid`, `0` validates every script
+    pub assumevalid: Option<String>,
+    /// Script verification threads, passed as `-par`. 0 lets bitcoind choose, and
+    /// negative values leave that many cores free. `--param par=1,2,4` sweeps it.
+    pub par: Option<i32>,
+    /// Megabytes of UTXO cache bitcoind gets, via `-dbcache`
+    pub dbcache: u64,
+    /// UTXO snapshot loaded with `loadtxoutset` before every run
+    pub assumeutxo_snapshot: Option<PathBuf>,
+    /// cgroup memory limit for bitcoind, e.g. `4G`, applied with `systemd-run`
+    pub cgroup_memory: Option<String>,
+    /// cgroup CPU quota for bitcoind, e.g. `200%` for two cores
+    pub cgroup_cpu: Option<String>,
+    /// CPUs bitcoind is pinned to with `taskset`, e.g. `2-5`. With a cgroup limit
+    /// they also become its cpuset. Best combined with booting with `isolcpus` set
+    /// to them, so nothing else is scheduled there.
+    pub isolate_cpus: Option<String>,
+    /// CPU frequency governor to switch to for the benchmark, e.g. `performance`.
+    /// The previous governors are restored afterwards.
+    pub cpu_governor: Option<String>,
+    /// Seconds between CPU temperature and frequency samples taken to detect thermal
+    /// throttling during the benchmark, `0` disables sampling. Linux only.
+    pub thermal_sample_secs: u64,
+    /// How many times to rerun a benchmark whose results look flaky
+    pub flaky_retries: u32,
+    /// Stddev, as a percentage of the mean, above which results count as flaky.
+    /// Runs with a nonzero exit code always do.
+    pub flaky_stddev_pct: Option<f64>,
+    /// Maximum 1-minute load average tolerated at the start of a run
+    pub max_load: Option<f64>,
+    /// What to do when the load at the start of a run exceeds `max_load`
+    pub load_policy: LoadPolicy,
+    /// Maximum megabytes of swap tolerated in use at the start of a run, as a
+    /// machine already swapping makes IBD slow and noisy
+    pub max_swap_used_mb: Option<u64>,
+    /// What to do when the swap in use at the start of a run exceeds
+    /// `max_swap_used_mb`
+    pub swap_policy: LoadPolicy,
+    /// Which steps each run goes through, and in what order
+    pub pipeline: PipelineStrategy,
+    /// Check out with `--force`, discarding local changes to tracked files, e.g.
+    /// build artifacts that would otherwise make the checkout fail
+    pub force_checkout: bool,
+    /// Initialize and update submodules after checkout, for forks that have them
+    pub update_submodules: bool,
+    /// What to do with local changes and untracked files found before checkout:
+    /// "abort", "stash" or "clean"
+    pub dirty_policy: DirtyPolicy,
+    /// Parallel build jobs, defaults to every available core
+    pub build_jobs: Option<usize>,
+    /// CMake generator to build with, CMake's platform default when unset
+    pub cmake_generator: Option<CmakeGenerator>,
+    /// Hosts the `guix` benchmark builds for, passed to `guix-build` as `HOSTS`,
+    /// e.g. `["x86_64-linux-gnu"]`; every release host when empty
+    pub guix_hosts: Vec<String>,
+    /// Clear ccache/sccache before building so build timings are from a cold cache
+    pub clear_compiler_cache: bool,
+    /// Script run before each run, e.g. to set up the machine; its failure aborts the run
+    pub pre_run_hook: Option<PathBuf>,
+    /// Script run after each run, whether or not it succeeded
+    pub post_run_hook: Option<PathBuf>,
+    /// URL that receives a JSON payload describing each run
+    pub webhook_url: Option<String>,
+    /// Slack incoming webhook URL
+    pub slack_webhook_url: Option<String>,
+    /// Which run outcomes trigger notifications
+    pub notify_on: NotifyOn,
+    /// Percentage slowdown versus the previous run that counts as a regression
+    pub regression_threshold_pct: f64,
+    /// Statistic of the runs `compare`, `report` and regression detection compare
+    /// results by: "mean", "median", or "min" for the least interfered-with run,
+    /// which is often steadier for noisy IBD benchmarks
+    pub comparison_metric: Metric,
+    /// Characters commit hashes are shortened to in tables, unless `--full-hash`
+    /// is given. Longer prefixes are shown where needed to keep them unambiguous.
+    pub hash_length: usize,
+    /// Decimal places timings are shown to in human output
+    pub precision: usize,
+    /// Unit timings are shown in by human output: "s", "ms", or "auto" to use
+    /// milliseconds when every timing shown together is under a second
+    pub time_unit: TimeUnit,
+    /// URL pull request numbers are appended to when linking results to them
+    pub pr_url: String,
+    /// Seconds to sleep between consecutive runs
+    pub cooldown_secs: u64,
+    /// Drop the page cache of the machine benchmarking, `ssh_host` if set, during the
+    /// cooldown, when permitted
+    pub drop_caches: bool,
+    /// Directory bitcoind's `debug.log` of each IBD run is kept in, as
+    /// `<run_id>.log`, `<data_root>/logs` by default. Logs are not kept when unset.
+    pub log_dir: Option<PathBuf>,
+    /// Directory `ab` keeps each side's copy of bitcoind in, `<data_root>/builds` by
+    /// default, or `<datadir>/ab` without a `data_root` or with `ssh_host`
+    pub builds_dir: Option<PathBuf>,
+    /// Directory runs write scratch files such as hyperfine's results to,
+    /// `<data_root>/tmp` by default, or the system's temporary directory without a
+    /// `data_root` or with `ssh_host`. Set with `ssh_host`, it must exist on both
+    /// machines.
+    pub temp_dir: Option<PathBuf>,
+    /// Days after which the daemon gzips kept logs
+    pub log_compress_after_days: u64,
+    /// Days after which the daemon deletes kept logs
+    pub log_retention_days: u64,
+    /// Megabytes the results database may grow to. Past it, the daemon deletes the
+    /// oldest results, never baselines, and compacts the file until it fits.
+    pub max_db_size_mb: Option<u64>,
+    /// Also keep what each benchmarked command printed to stdout, via hyperfine's
+    /// `--output`, in `log_dir` as `<run_id>.output`. hyperfine then discards the
+    /// command's stderr. Ignored with hyperfine older than 1.14.
+    pub keep_command_output: bool,
+    /// Directory the daemon renders a static results site into after each job: a
+    /// chart of each command's mean over time and an `index.html` listing them
+    pub site_dir: Option<PathBuf>,
+    /// Where to mirror `site_dir` after rendering it, e.g. `s3://bucket/bench`,
+    /// with the `aws` CLI and its usual credentials
+    pub site_s3_url: Option<String>,
+    /// IANA time zone, e.g. `Europe/London`, the daemon's schedules are evaluated
+    /// in and timestamps are shown in. Timestamps are always stored in UTC.
+    pub timezone: Tz,
+    /// Most commits `daemon --since-last-release` benchmarks per scheduled job; the
+    /// rest are left for later jobs. Unlimited when unset.
+    pub max_backfill: Option<usize>,
+    /// Regex a commit's subject must match for `run-batch` and `daemon
+    /// --since-last-release` to benchmark it, e.g. `^(validation|net|txdb)`
+    pub commit_subject_filter: Option<String>,
+    /// Regex at least one path a commit changes must match for `run-batch` and
+    /// `daemon --since-last-release` to benchmark it, e.g. `^src/(validation|txdb)`
+    pub commit_path_filter: Option<String>,
+    /// Directory `record` saves `rr` traces in, one per recording. With `ssh_host`
+    /// set, a path on that host.
+    pub rr_trace_dir: Option<PathBuf>,
+    /// Sync an IBD run that stopped short of `stopatheight` again under `rr record`,
+    /// untimed, to keep a trace of the failure. Needs `rr_trace_dir`.
+    pub rr_on_incomplete: bool,
+    /// Rules every result must satisfy, each `field op value`, e.g. `mean < 3600`,
+    /// `exit_code == 0` or `reached_height >= stopatheight`. Fields: mean, stddev,
+    /// median, min, max, exit_code, reached_height. Violations are warned about and
+    /// stored with the result.
+    pub assertions: Vec<String>,
+    /// Fail the run when a result violates an assertion, e.g. to gate CI
+    pub fail_on_assertion: bool,
+    /// Starting chainstate of each scenario, under `[scenarios.<benchmark>]`
+    pub scenarios: BTreeMap<Benchmark, Scenario>,
+    /// Jobs the daemon runs, each on its own cron schedule
+    pub schedule: Vec<ScheduleEntry>,
+}
+
+/// A daemon job: when to run, and what to benchmark.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    /// Cron expression with a leading seconds field, e.g. `0 0 * * * *` for hourly
+    pub cron: String,
+    /// What to benchmark, `benchmark` when unset
+    pub scenario: Option<Benchmark>,
+    /// Overrides `stopatheight`, e.g. for a quick sync between full ones
+    pub stopatheight: Option<u64>,
+}
+
+impl ScheduleEntry {
+    pub fn parse_cron(&self) -> Result<Schedule> {
+        Schedule::from_str(&self.cron)
+            .with_context(|| format!("Invalid schedule cron expression `{}`", self.cron))
+    }
+
+    /// `config` with this job's overrides applied, failing if they make it invalid.
+    pub fn apply(&self, config: &Config) -> Result<Config> {
+        config
+            .with_overrides(self.scenario, self.stopatheight)
+            .with_context(|| format!("Invalid scheduled job `{}`", self.cron))
+    }
+}
+
+/// The chainstate a scenario's runs start from instead of genesis.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// Datadir copied into `datadir` before every run. With `ssh_host` set, a path
+    /// on that host.
+    pub seed_datadir: Option<PathBuf>,
+    /// Height to keep `seed_datadir` synced to. When it was synced to another
+    /// height, or is still empty, the built bitcoind syncs it there before the run.
+    /// Unset, the seed is used as it is, e.g. a datadir prepared for `-reindex`.
+    pub require_sync_height: Option<u64>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Benchmark {
+    /// bitcoind syncing from a local peer up to `stopatheight`
+    Ibd,
+    /// The whole functional test suite, via `test_runner.py`
+    FunctionalTests,
+    /// `rpc_command` via `bitcoin-cli`, against a node started on the synced `datadir`
+    Rpc,
+    /// Bitcoin Core's reproducible release build via `contrib/guix/guix-build` in
+    /// place of CMake, storing its build time and output hash instead of timings
+    Guix,
+}
+
+impl Benchmark {
+    /// The name as written in the config file, e.g. `functional_tests`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Benchmark::Ibd => "ibd",
+            Benchmark::FunctionalTests => "functional_tests",
+            Benchmark::Rpc => "rpc",
+            Benchmark::Guix => "guix",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CmakeGenerator {
+    Ninja,
+    Make,
+}
+
+impl CmakeGenerator {
+    /// The generator's name as passed to `cmake -G`
+    pub fn cmake_name(self) -> &'static str {
+        match self {
+            CmakeGenerator::Ninja => "Ninja",
+            CmakeGenerator::Make => "Unix Makefiles",
+        }
+    }
+
+    /// The build tool the generator's output is driven by
+    pub fn tool(self) -> &'static str {
+        match self {
+            CmakeGenerator::Ninja => "ninja",
+            CmakeGenerator::Make => "make",
+        }
+    }
+}
+
+/// How a run gets from a commit to stored results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStrategy {
+    /// Fetch and check out the commit in `repo_path`, then build and benchmark it
+    FetchThenBuild,
+    /// Build and benchmark `repo_path` as it is checked out, which must be the
+    /// requested commit, e.g. a checkout prepared by hand
+    BuildFromCheckout,
+}
+
+impl PipelineStrategy {
+    /// The steps a run goes through, in order
+    pub fn steps(self) -> &'static [Phase] {
+        match self {
+            Self::FetchThenBuild => &[Phase::Fetch, Phase::Build, Phase::Bench, Phase::Save],
+            Self::BuildFromCheckout => &[Phase::Build, Phase::Bench, Phase::Save],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadPolicy {
+    /// Print a warning and benchmark anyway
+    Warn,
+    /// Skip the run entirely
+    Skip,
+}
+
+/// Statistic of a result's runs that results are compared by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    Mean,
+    Median,
+    Min,
+}
+
+impl Metric {
+    /// The `benchmarks` column holding the statistic
+    pub fn column(self) -> &'static str {
+        match self {
+            Metric::Mean => "mean",
+            Metric::Median => "median",
+            Metric::Min => "min",
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_root: None,
+            repo_path: PathBuf::from("/home/will/src/bitcoin"),
+            ssh_host: None,
+            remote_repo_path: None,
+            db_path: PathBuf::from("/home/will/src/bitcoin_benchmark/results.db"),
+            datadir: PathBuf::from("/mnt/bench/.bitcoin"),
+            keep_datadir: false,
+            storage: BTreeMap::new(),
+            benchmark: Benchmark::Ibd,
+            extra_args: Vec::new(),
+            append_chunk_blocks: 10000,
+            rpc_command: None,
+            node_ready_timeout_secs: 3600,
+            shell: "sh".to_string(),
+            stopatheight: 100000,
+            checkpoints: Vec::new(),
+            smoke_height: None,
+            expected_exit_codes: Vec::new(),
+            assumevalid: None,
+            par: None,
+            dbcache: 16385,
+            assumeutxo_snapshot: None,
+            cgroup_memory: None,
+            cgroup_cpu: None,
+            isolate_cpus: None,
+            cpu_governor: None,
+            thermal_sample_secs: 10,
+            flaky_retries: 0,
+            flaky_stddev_pct: None,
+            max_load: None,
+            load_policy: LoadPolicy::Warn,
+            max_swap_used_mb: None,
+            swap_policy: LoadPolicy::Warn,
+            pipeline: PipelineStrategy::FetchThenBuild,
+            force_checkout: false,
+            update_submodules: false,
+            dirty_policy: DirtyPolicy::Abort,
+            build_jobs: None,
+            cmake_generator: None,
+            guix_hosts: Vec::new(),
+            clear_compiler_cache: false,
+            pre_run_hook: None,
+            post_run_hook: None,
+            webhook_url: None,
+            slack_webhook_url: None,
+            notify_on: NotifyOn::Always,
+            regression_threshold_pct: 5.0,
+            comparison_metric: Metric::Mean,
+            hash_length: 12,
+            precision: 3,
+            time_unit: TimeUnit::Auto,
+            pr_url: "https://github.com/bitcoin/bitcoin/pull/".to_string(),
+            cooldown_secs: 0,
+            drop_caches: false,
+            log_dir: None,
+            builds_dir: None,
+            temp_dir: None,
+            log_compress_after_days: 7,
+            log_retention_days: 30,
+            max_db_size_mb: None,
+            keep_command_output: false,
+            site_dir: None,
+            site_s3_url: None,
+            timezone: Tz::UTC,
+            max_backfill: None,
+            commit_subject_filter: None,
+            commit_path_filter: None,
+            rr_trace_dir: None,
+            rr_on_incomplete: false,
+            assertions: Vec::new(),
+            fail_on_assertion: false,
+            scenarios: BTreeMap::new(),
+            schedule: vec![ScheduleEntry {
+                // Every day at midnight
+                cron: "0 0 0 * * * *".to_string(),
+                scenario: None,
+                stopatheight: None,
+            }],
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from `path`, falling back to defaults when no path is given,
+    /// then apply any `key=value` overrides on top.
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> Result<Self> {
+        Self::load_with_sources(path, overrides).map(|(config, _)| config)
+    }
+
+    /// Load as `load` does, also returning where each top-level value came from.
+    pub fn load_with_sources(
+        path: Option<&Path>,
+        overrides: &[String],
+    ) -> Result<(Self, BTreeMap<String, Source>)> {
+        let mut table = match path {
+            Some(path) => {
+                let data = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file at {}", path.display()))?;
+                data.parse::<Table>()
+                    .with_context(|| format!("Failed to parse config file at {}", path.display()))?
+            }
+            None => Table::new(),
+        };
+        let mut sources: BTreeMap<String, Source> = table
+            .keys()
+            .map(|key| (key.clone(), Source::File))
+            .collect();
+
+        for assignment in overrides {
+            let key = apply_override(&mut table, assignment)?;
+            sources.insert(key, Source::Flag);
+        }
+        for (key, value) in table.iter_mut() {
+            if references_env(value) {
+                sources.insert(key.clone(), Source::Env);
+            }
+            expand_env(value)?;
+        }
+        if let Some(data_root) = table.get("data_root").and_then(Value::as_str) {
+            let data_root = PathBuf::from(data_root);
+            let mut defaults = vec![("db_path", "results.db"), ("log_dir", "logs")];
+            // Builds and scratch files are then on the SSH host, and data_root is not
+            if !table.contains_key("ssh_host") {
+                defaults.extend([("builds_dir", "builds"), ("temp_dir", "tmp")]);
+            }
+            for (key, name) in defaults {
+                let path = data_root.join(name).display().to_string();
+                table.entry(key).or_insert(Value::String(path));
+            }
+        }
+
+        let config: Self = Value::Table(table)
+            .try_into()
+            .with_context(|| "Invalid configuration")?;
+        config.validate()?;
+        Ok((config, sources))
+    }
+
+    /// This configuration benchmarking `scenario` up to `stopatheight` where given,
+    /// as a scheduled job or queued commit asks, failing if that makes it invalid.
+    pub fn with_overrides(
+        &self,
+        scenario: Option<Benchmark>,
+        stopatheight: Option<u64>,
+    ) -> Result<Config> {
+        let mut config = self.clone();
+        if let Some(scenario) = scenario {
+            config.benchmark = scenario;
+        }
+        if let Some(stopatheight) = stopatheight {
+            config.stopatheight = stopatheight;
+        }
+        config.validate_run()?;
+        Ok(config)
+    }
+
+    /// The seeded starting chainstate of the configured benchmark, if it has one.
+    pub fn scenario(&self) -> Option<&Scenario> {
+        self.scenarios
+            .get(&self.benchmark)
+            .filter(|scenario| scenario.seed_datadir.is_some())
+    }
+
+    /// Benchmark on the datadir of the named storage backend instead of `datadir`.
+    pub fn select_storage(&mut self, name: &str) -> Result<()> {
+        let datadir = self.storage.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.storage.keys().map(String::as_str).collect();
+            anyhow::anyhow!(
+                "Unknown storage `{}`, configured storage: [{}]",
+                name,
+                known.join(", ")
+            )
+        })?;
+        self.datadir = datadir.clone();
+        Ok(())
+    }
+
+    /// Whether a run of the timed command exiting with `code` succeeded.
+    pub fn exit_expected(&self, code: Option<i32>) -> bool {
+        code.is_some_and(|code| code == 0 || self.expected_exit_codes.contains(&code))
+    }
+
+    /// Where `ab` keeps each side's copy of bitcoind.
+    pub fn builds_dir(&self) -> PathBuf {
+        self.builds_dir
+            .clone()
+            .unwrap_or_else(|| self.datadir.join("ab"))
+    }
+
+    /// Where runs write their scratch files.
+    pub fn temp_dir(&self) -> PathBuf {
+        self.temp_dir.clone().unwrap_or_else(std::env::temp_dir)
+    }
+
+    /// Create `data_root`, the database's directory, `log_dir` and `temp_dir` if they
+    /// don't exist yet, and `builds_dir` unless it is on `ssh_host`.
+    pub fn create_dirs(&self) -> Result<()> {
+        if let Some(data_root) = &self.data_root {
+            fs::create_dir_all(data_root).with_context(|| {
+                format!("Failed to create data_root at {}", data_root.display())
+            })?;
+            let probe = data_root.join(".write-test");
+            fs::write(&probe, b"")
+                .and_then(|()| fs::remove_file(&probe))
+                .with_context(|| format!("data_root {} is not writable", data_root.display()))?;
+        }
+        if let Some(db_dir) = self
+            .db_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(db_dir).with_context(|| {
+                format!("Failed to create database directory {}", db_dir.display())
+            })?;
+        }
+        if let Some(log_dir) = &self.log_dir {
+            fs::create_dir_all(log_dir)
+                .with_context(|| format!("Failed to create log_dir at {}", log_dir.display()))?;
+        }
+        if let Some(temp_dir) = &self.temp_dir {
+            fs::create_dir_all(temp_dir)
+                .with_context(|| format!("Failed to create temp_dir at {}", temp_dir.display()))?;
+        }
+        if let Some(builds_dir) = self.builds_dir.as_ref().filter(|_| self.ssh_host.is_none()) {
+            fs::create_dir_all(builds_dir).with_context(|| {
+                format!("Failed to create builds_dir at {}", builds_dir.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Check the configuration, including that every scheduled job's overrides leave
+    /// it valid, so a bad job fails at startup instead of when it first runs.
+    fn validate(&self) -> Result<()> {
+        self.validate_run()?;
+        for entry in &self.schedule {
+            entry.parse_cron()?;
+            entry.apply(self)?;
+        }
+        Ok(())
+    }
+
+    /// Check the settings a run uses, i.e. all but `schedule`.
+    fn validate_run(&self) -> Result<()> {
+        if self.dbcache < bitcoind::MIN_DBCACHE {
+            return Err(anyhow::anyhow!(
+                "dbcache must be at least {} MB",
+                bitcoind::MIN_DBCACHE
+            ));
+        }
+        if let Some(par) = self.par {
+            validate_par(par)?;
+        }
+        if self.build_jobs == Some(0) {
+            return Err(anyhow::anyhow!("build_jobs must be at least 1"));
+        }
+        if let Some(cpus) = &self.isolate_cpus {
+            let parsed = system::parse_cpu_list(cpus)
+                .with_context(|| format!("isolate_cpus `{}` is not a CPU list", cpus))?;
+            if let Some(cpu) = parsed.iter().find(|&&cpu| cpu >= system::cpu_count()) {
+                return Err(anyhow::anyhow!(
+                    "isolate_cpus names CPU {}, but there are only {}",
+                    cpu,
+                    system::cpu_count()
+                ));
+            }
+        }
+        if self.max_backfill == Some(0) {
+            return Err(anyhow::anyhow!("max_backfill must be at least 1"));
+        }
+        CommitFilter::new(self, None, None)?;
+        assertions::parse_all(&self.assertions)?;
+        if self.rr_on_incomplete && self.rr_trace_dir.is_none() {
+            return Err(anyhow::anyhow!(
+                "rr_on_incomplete needs an rr_trace_dir to keep traces in"
+            ));
+        }
+        for (benchmark, scenario) in &self.scenarios {
+            validate_scenario(self, *benchmark, scenario)?;
+        }
+        if self.ssh_host.is_some() {
+            if self.benchmark == Benchmark::Rpc {
+                return Err(anyhow::anyhow!(
+                    "The rpc benchmark starts its node locally and cannot run over ssh_host"
+                ));
+            }
+            if self.assumeutxo_snapshot.is_some() {
+                return Err(anyhow::anyhow!(
+                    "assumeutxo_snapshot is read locally and cannot be used with ssh_host"
+                ));
+            }
+            if self.pipeline == PipelineStrategy::BuildFromCheckout {
+                return Err(anyhow::anyhow!(
+                    "The build_from_checkout pipeline checks the local checkout and cannot run over ssh_host"
+                ));
+            }
+        }
+        if self.benchmark == Benchmark::Rpc && self.rpc_command.is_none() {
+            return Err(anyhow::anyhow!("The rpc benchmark requires rpc_command"));
+        }
+        if let Some(smoke_height) = self.smoke_height {
+            if smoke_height == 0 || smoke_height >= self.stopatheight {
+                return Err(anyhow::anyhow!(
+                    "smoke_height must be between 1 and stopatheight ({})",
+                    self.stopatheight
+                ));
+            }
+        }
+        if self.append_chunk_blocks == 0 {
+            return Err(anyhow::anyhow!("append_chunk_blocks must be at least 1"));
+        }
+        let shell_works = std::process::Command::new(&self.shell)
+            .args(["-c", "exit 0"])
+            .status()
+            .is_ok_and(|status| status.success());
+        if !shell_works {
+            return Err(anyhow::anyhow!(
+                "shell `{}` was not found or cannot run commands",
+                self.shell
+            ));
+        }
+        if self.log_retention_days < self.log_compress_after_days {
+            return Err(anyhow::anyhow!(
+                "log_retention_days must be at least log_compress_after_days"
+            ));
+        }
+        if self.max_db_size_mb == Some(0) {
+            return Err(anyhow::anyhow!("max_db_size_mb must be at least 1"));
+        }
+        if self.keep_command_output && self.log_dir.is_none() {
+            return Err(anyhow::anyhow!(
+                "keep_command_output needs a log_dir to keep the output in"
+            ));
+        }
+        if self.site_s3_url.is_some() && self.site_dir.is_none() {
+            return Err(anyhow::anyhow!(
+                "site_s3_url needs a site_dir to render the site into"
+            ));
+        }
+        if self.hash_length == 0 {
+            return Err(anyhow::anyhow!("hash_length must be at least 1"));
+        }
+        if self.precision > MAX_PRECISION {
+            return Err(anyhow::anyhow!(
+                "precision must be at most {} decimal places",
+                MAX_PRECISION
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Check the `scenarios` entry for `benchmark` fits the rest of `config`: only
+/// benchmarks running a node take a `seed_datadir`, `require_sync_height` needs one,
+/// and an `ibd` seed must leave blocks below `stopatheight` to sync.
+fn validate_scenario(config: &Config, benchmark: Benchmark, scenario: &Scenario) -> Result<()> {
+    if scenario.seed_datadir.is_none() {
+        if scenario.require_sync_height.is_some() {
+            return Err(anyhow::anyhow!(
+                "scenarios.{}.require_sync_height needs a seed_datadir to sync",
+                benchmark.name()
+            ));
+        }
+        return Ok(());
+    }
+    match benchmark {
+        Benchmark::FunctionalTests => Err(anyhow::anyhow!(
+            "The functional tests create their own datadirs and take no seed_datadir"
+        )),
+        Benchmark::Guix => Err(anyhow::anyhow!(
+            "The guix benchmark runs no node and takes no seed_datadir"
+        )),
+        Benchmark::Ibd if config.assumeutxo_snapshot.is_some() => Err(anyhow::anyhow!(
+            "scenarios.ibd.seed_datadir and assumeutxo_snapshot both set where runs start from"
+        )),
+        Benchmark::Ibd => match scenario.require_sync_height {
+            Some(height) if height >= config.stopatheight => Err(anyhow::anyhow!(
+                "scenarios.ibd.require_sync_height must be below stopatheight {}",
+                config.stopatheight
+            )),
+            Some(height) if config.smoke_height.is_some_and(|smoke| smoke <= height) => {
+                Err(anyhow::anyhow!(
+                    "smoke_height must be above scenarios.ibd.require_sync_height {}",
+                    height
+                ))
+            }
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Check `par` is a `-par` value bitcoind accepts: at most its script check thread
+/// limit, and leaving at least one core when negative.
+pub fn validate_par(par: i32) -> Result<()> {
+    let cores = crate::system::cpu_count() as i32;
+    if par > bitcoind::MAX_SCRIPT_CHECK_THREADS || par <= -cores {
+        return Err(anyhow::anyhow!(
+            "par must be between {} and {}, not {}",
+            1 - cores,
+            bitcoind::MAX_SCRIPT_CHECK_THREADS,
+            par
+        ));
+    }
+    Ok(())
+}
+
+/// Replace `${VAR}` in every string in `value` with the environment variable `VAR`,
+/// so secrets can be kept out of the config file. `$${` is a literal `${`.
+fn expand_env(value: &mut Value) -> Result<()> {
+    match value {
+        Value::String(s) => *s = expand_vars(s)?,
+        Value::Array(values) => {
+            for value in values {
+                expand_env(value)?;
+            }
+        }
+        Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                expand_env(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand_vars(s: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unterminated `${{` in config value `{}`", s))?;
+        let name = &rest[start + 2..start + end];
+        let value = std::env::var(name).with_context(|| {
+            format!(
+                "Config value `{}` references unset environment variable {}",
+                s, name
+            )
+        })?;
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Where a configuration value came from, as shown by `show-config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Not set anywhere, or derived from another value
+    Default,
+    /// The `--config` file
+    File,
+    /// An environment variable referenced as `${VAR}`, possibly loaded from `.env`
+    Env,
+    /// A `--set` override
+    Flag,
+}
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::File => "file",
+            Source::Env => "env",
+            Source::Flag => "flag",
+        }
+    }
+}
+
+/// Whether `value` references an environment variable that `expand_env` replaces.
+fn references_env(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.replace("$${", "").contains("${"),
+        Value::Array(values) => values.iter().any(references_env),
+        Value::Table(table) => table.iter().any(|(_, value)| references_env(value)),
+        _ => false,
+    }
+}
+
+/// Apply a `key=value` override, where `key` may be a dotted path into nested
+/// tables. The value is parsed as TOML, falling back to a plain string. Returns the
+/// top-level key that was changed.
+fn apply_override(table: &mut Table, assignment: &str) -> Result<String> {
+    let (key, raw) = assignment
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Override `{}` is not of the form key=value", assignment))?;
+    let value = format!("value = {}", raw)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut parsed| parsed.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()));
+
+    let mut path: Vec<&str> = key.trim().split('.').collect();
+    let last = path.pop().filter(|k| !k.is_empty());
+    let last = last.ok_or_else(|| anyhow::anyhow!("Override `{}` has an empty key", assignment))?;
+    let top = path.first().copied().unwrap_or(last).to_string();
+
+    let mut current = table;
+    for segment in path {
+        let entry = current
+            .entry(segment)
+            .or_insert_with(|| Value::Table(Table::new()));
+        current = entry.as_table_mut().ok_or_else(|| {
+            anyhow::anyhow!("Override `{}`: `{}` is not a table", assignment, segment)
+        })?;
+    }
+    current.insert(last.to_string(), value);
+    Ok(top)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetch_then_build_runs_every_step_in_order() {
+        assert_eq!(
+            PipelineStrategy::FetchThenBuild.steps(),
+            [Phase::Fetch, Phase::Build, Phase::Bench, Phase::Save]
+        );
+    }
+
+    #[test]
+    fn build_from_checkout_skips_the_fetch() {
+        assert_eq!(
+            PipelineStrategy::BuildFromCheckout.steps(),
+            [Phase::Build, Phase::Bench, Phase::Save]
+        );
+    }
+
+    #[test]
+    fn escaped_variable_keeps_one_dollar() {
+        assert_eq!(expand_vars("a$${B}").unwrap(), "a${B}");
+    }
+}
@@ -0,0 +1,1708 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::backup::Progress;
+use rusqlite::types::Value;
+use rusqlite::{
+    params, params_from_iter, Connection, DatabaseName, OpenFlags, OptionalExtension, ToSql,
+};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::checkpoints::{Checkpoint, SyncPhase};
+use crate::config::Metric;
+use crate::HyperfineResults;
+
+/// Schema changes applied on top of the original `benchmarks` table, in order.
+/// The number of applied migrations is tracked in SQLite's `user_version`.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE benchmarks ADD COLUMN load_start REAL",
+    "ALTER TABLE benchmarks ADD COLUMN load_end REAL",
+    "ALTER TABLE benchmarks ADD COLUMN steal_pct REAL",
+    "ALTER TABLE benchmarks ADD COLUMN build_secs REAL",
+    "ALTER TABLE benchmarks ADD COLUMN compiler_cache TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN cache_hit_rate REAL",
+    "ALTER TABLE benchmarks ADD COLUMN parent_hash TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN build_jobs INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN start_height INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN stop_height INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN blocks_per_sec REAL",
+    "ALTER TABLE benchmarks ADD COLUMN status TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN error TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN storage TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN note TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN assumevalid TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN assumeutxo_snapshot TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN cgroup_memory TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN cgroup_cpu TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN cpu_governor TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN repo_url TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN snapshot_height INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN p50 REAL",
+    "ALTER TABLE benchmarks ADD COLUMN p90 REAL",
+    "ALTER TABLE benchmarks ADD COLUMN p99 REAL",
+    "ALTER TABLE benchmarks ADD COLUMN cmake_generator TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN is_baseline INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE benchmarks ADD COLUMN retries INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN rpc_command TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN src_files_changed INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN src_insertions INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN src_deletions INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN commit_alias TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN throttled INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN max_temp_c REAL",
+    "ALTER TABLE benchmarks ADD COLUMN min_cpu_mhz REAL",
+    "ALTER TABLE benchmarks ADD COLUMN height_delta INTEGER",
+    // Rows stored before this column existed have no timestamp
+    "ALTER TABLE benchmarks ADD COLUMN created_at TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN bitcoind_version TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN datadir_size_bytes INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN run_id TEXT",
+    "CREATE TABLE notifications (
+        run_id TEXT NOT NULL,
+        sink TEXT NOT NULL,
+        delivered_at TEXT NOT NULL,
+        PRIMARY KEY (run_id, sink)
+    )",
+    "ALTER TABLE benchmarks ADD COLUMN par INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN batch_id TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN batch_position INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN commit_subject TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN pr_number INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN hyperfine_version TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN dbcache INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN dirty_action TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN log_path TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN machine TEXT",
+    "CREATE TABLE checkpoints (
+        benchmark_id INTEGER NOT NULL REFERENCES benchmarks (id),
+        height INTEGER NOT NULL,
+        cumulative_secs REAL NOT NULL,
+        segment_secs REAL NOT NULL,
+        PRIMARY KEY (benchmark_id, height)
+    )",
+    "ALTER TABLE benchmarks ADD COLUMN toolchain TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN run_label TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN output_path TEXT",
+    "CREATE TABLE queue (
+        id INTEGER PRIMARY KEY,
+        commit_hash TEXT NOT NULL,
+        scenario TEXT,
+        priority INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        enqueued_at TEXT NOT NULL
+    )",
+    "ALTER TABLE benchmarks ADD COLUMN isolate_cpus TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN kernel_isolated INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN ab_group TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN reached_height INTEGER",
+    "CREATE TABLE filtered_commits (
+        id INTEGER PRIMARY KEY,
+        commit_hash TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        batch_id TEXT,
+        created_at TEXT NOT NULL
+    )",
+    "CREATE TABLE phases (
+        benchmark_id INTEGER NOT NULL REFERENCES benchmarks (id),
+        phase TEXT NOT NULL,
+        secs REAL NOT NULL,
+        PRIMARY KEY (benchmark_id, phase)
+    )",
+    "ALTER TABLE benchmarks ADD COLUMN rr_trace TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN swap_in_pages INTEGER",
+    "ALTER TABLE benchmarks ADD COLUMN swap_out_pages INTEGER",
+    "ALTER TABLE queue ADD COLUMN stopatheight INTEGER",
+    "ALTER TABLE queue ADD COLUMN job TEXT",
+    // Read commands look results up by commit and by command, which would
+    // otherwise scan the whole table
+    "CREATE INDEX IF NOT EXISTS benchmarks_commit_hash ON benchmarks (commit_hash);
+     CREATE INDEX IF NOT EXISTS benchmarks_commit_alias ON benchmarks (commit_alias);
+     CREATE INDEX IF NOT EXISTS benchmarks_command ON benchmarks (command, snapshot_height);
+     CREATE INDEX IF NOT EXISTS benchmarks_created_at ON benchmarks (created_at)",
+    "ALTER TABLE benchmarks ADD COLUMN failed_assertions TEXT",
+    "ALTER TABLE benchmarks ADD COLUMN guix_output_hash TEXT",
+    // Pruning counts and deletes the oldest results that aren't baselines
+    "CREATE INDEX IF NOT EXISTS benchmarks_is_baseline ON benchmarks (is_baseline)",
+];
+
+/// `status` of a row whose benchmark ran to completion.
+pub const STATUS_OK: &str = "ok";
+/// `status` of a row recording that the commit failed to build.
+pub const STATUS_BUILD_FAILED: &str = "build_failed";
+/// `status` of a row recording that the commit built, from a `build` preflight
+/// that did not benchmark it.
+pub const STATUS_BUILT: &str = "built";
+/// `status` of a row recording that bitcoind ran out of memory and was killed.
+pub const STATUS_OOM: &str = "oom";
+/// `status` of an IBD run whose bitcoind stopped short of `stopatheight`, e.g. for
+/// crashing or losing its peer, so its timing is left out of comparisons.
+pub const STATUS_INCOMPLETE: &str = "incomplete";
+/// `status` of an untimed run recorded under `rr record`, whose trace is in `rr_trace`.
+pub const STATUS_RECORDED: &str = "recorded";
+
+/// Per-run information stored alongside every result row.
+#[derive(Clone, Default)]
+pub struct RunMetadata {
+    /// Unique id of the run, shared by all its rows and sent with its notifications
+    pub run_id: Option<String>,
+    pub load_start: Option<f64>,
+    pub load_end: Option<f64>,
+    pub steal_pct: Option<f64>,
+    /// Pages the machine swapped in and out during the benchmark
+    pub swap_in_pages: Option<u64>,
+    pub swap_out_pages: Option<u64>,
+    pub build_secs: Option<f64>,
+    pub build_jobs: Option<usize>,
+    /// Name of the compiler cache wrapping the build, if any
+    pub compiler_cache: Option<String>,
+    pub cache_hit_rate: Option<f64>,
+    /// CMake generator the build used, if one was configured
+    pub cmake_generator: Option<String>,
+    pub parent_hash: Option<String>,
+    /// Friendly name the commit was benchmarked under
+    pub commit_alias: Option<String>,
+    /// What the run was for, as given with `run --label`
+    pub run_label: Option<String>,
+    /// Id shared by the two sides of an `ab` run, timed in one hyperfine invocation
+    pub ab_group: Option<String>,
+    /// Chain height the datadir started the run at, if known
+    pub start_height: Option<u64>,
+    /// Height bitcoind was told to stop at, if known
+    pub stop_height: Option<u64>,
+    /// Blocks synced by an incremental run continuing from the previous one's
+    /// datadir; `None` for runs syncing from scratch
+    pub height_delta: Option<u64>,
+    /// Named storage backend the datadir was on
+    pub storage: Option<String>,
+    /// Repository the commit was fetched from, when not the checkout's own remotes
+    pub repo_url: Option<String>,
+    /// `-assumevalid` block the run was made with, if overridden
+    pub assumevalid: Option<String>,
+    /// Path of the UTXO snapshot loaded before the run, if any
+    pub assumeutxo_snapshot: Option<String>,
+    /// Height of the block that snapshot was taken at
+    pub snapshot_height: Option<u64>,
+    /// cgroup limits bitcoind ran under
+    pub cgroup_memory: Option<String>,
+    pub cgroup_cpu: Option<String>,
+    /// CPUs bitcoind was pinned to, and whether the kernel kept everything else off
+    /// them with `isolcpus`, if that could be read
+    pub isolate_cpus: Option<String>,
+    pub kernel_isolated: Option<bool>,
+    /// CPU governors in effect during the benchmark
+    pub cpu_governor: Option<String>,
+    /// How many times the benchmark was rerun for looking flaky
+    pub retries: Option<u32>,
+    /// Whether the CPU throttled during the benchmark, if it could be told
+    pub throttled: Option<bool>,
+    pub max_temp_c: Option<f64>,
+    pub min_cpu_mhz: Option<f64>,
+    /// RPC timed by the `rpc` benchmark
+    pub rpc_command: Option<String>,
+    /// `git diff --shortstat` of `src/` against the parent commit
+    pub src_files_changed: Option<u64>,
+    pub src_insertions: Option<u64>,
+    pub src_deletions: Option<u64>,
+    /// Version the built bitcoind reports, e.g. `v28.0.0rc1`
+    pub bitcoind_version: Option<String>,
+    /// Compiler and CMake versions the build used, as `Toolchain` JSON
+    pub toolchain: Option<String>,
+    /// `run-batch` the run was part of, and when in the batch it ran
+    pub batch_id: Option<String>,
+    pub batch_position: Option<usize>,
+    /// First line of the commit message, and the pull request it merged, if any
+    pub commit_subject: Option<String>,
+    pub pr_number: Option<u64>,
+    /// Version of hyperfine that timed the run, e.g. `1.18.0`
+    pub hyperfine_version: Option<String>,
+    /// Script verification threads bitcoind ran with, if set
+    pub par: Option<i32>,
+    /// Megabytes of UTXO cache bitcoind ran with, via `-dbcache`
+    pub dbcache: Option<u64>,
+    /// "stashed" or "cleaned" if the checkout had local changes put aside first
+    pub dirty_action: Option<String>,
+    /// Where bitcoind's `debug.log` of the run was kept, if `log_dir` is set
+    pub log_path: Option<String>,
+    /// Where the stdout of each benchmarked command was kept, if
+    /// `keep_command_output` is set
+    pub output_path: Option<String>,
+    /// Hostname of the machine that ran the benchmark
+    pub machine: Option<String>,
+    /// Size on disk of the datadir the IBD run left behind
+    pub datadir_size_bytes: Option<u64>,
+    /// Hash of the `SHA256SUMS.part` files a `guix` build wrote
+    pub guix_output_hash: Option<String>,
+}
+
+pub fn open_db(db_path: &Path) -> Result<Connection> {
+    let conn = Connection::open(db_path).with_context(|| "Failed to connect to SQLite database")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS benchmarks (
+            id INTEGER PRIMARY KEY,
+            commit_hash TEXT NOT NULL,
+            command TEXT NOT NULL,
+            mean REAL,
+            stddev REAL,
+            median REAL,
+            user REAL,
+            system REAL,
+            min REAL,
+            max REAL,
+            times TEXT,
+            exit_codes TEXT,
+            parameters TEXT
+        )",
+        [],
+    )
+    .with_context(|| "Failed to create benchmarks table")?;
+
+    migrate(&conn)?;
+    Ok(conn)
+}
+
+/// Copy the database to `out` with SQLite's online backup API, which gives a
+/// consistent copy even while the daemon is writing to it.
+pub fn backup(db_path: &Path, out: &Path) -> Result<()> {
+    let conn = open_db(db_path)?;
+    conn.backup(DatabaseName::Main, out, None)
+        .with_context(|| format!("Failed to back up database to {}", out.display()))
+}
+
+/// Replace the database with the backup at `path`, once it is known to be a results
+/// database this version can read. Backups from older versions are migrated.
+pub fn restore(db_path: &Path, path: &Path) -> Result<()> {
+    check_results_db(path)?;
+    let mut conn =
+        Connection::open(db_path).with_context(|| "Failed to connect to SQLite database")?;
+    conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)
+        .with_context(|| format!("Failed to restore database from {}", path.display()))?;
+    drop(conn);
+    open_db(db_path).map(drop)
+}
+
+/// Fail unless `path` is a results database with a schema this version can migrate.
+fn check_results_db(path: &Path) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let has_table: bool = conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'benchmarks')",
+            [],
+            |row| row.get(0),
+        )
+        .with_context(|| format!("{} is not a SQLite database", path.display()))?;
+    if !has_table {
+        return Err(anyhow::anyhow!(
+            "{} has no benchmarks table, it is not a results database",
+            path.display()
+        ));
+    }
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .with_context(|| format!("Failed to read the schema version of {}", path.display()))?;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow::anyhow!(
+            "{} has schema version {}, newer than the {} this version supports",
+            path.display(),
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Rows `merge` copied from one source database.
+pub struct MergeSummary {
+    pub merged: usize,
+    /// Rows already in the database
+    pub skipped: usize,
+}
+
+/// Tables of rows belonging to a result, by their `benchmark_id`, with their other
+/// columns.
+const CHILD_TABLES: &[(&str, &[&str])] = &[
+    (
+        "checkpoints",
+        &["height", "cumulative_secs", "segment_secs"],
+    ),
+    ("phases", &["phase", "secs"]),
+];
+
+/// Copy every result from the results database at `source`, e.g. another runner's,
+/// into `conn`, along with their rows in `CHILD_TABLES`. The source is migrated to
+/// the current schema in memory, leaving the file untouched. Rows that don't record
+/// their machine are attributed to `fallback_machine`. A row is skipped when one
+/// from the same run, commit, command, parameters and machine is already stored, so
+/// merging again is harmless; stored rows that predate machine attribution match any
+/// machine. Merged rows never become the baseline.
+pub fn merge(conn: &Connection, source: &Path, fallback_machine: &str) -> Result<MergeSummary> {
+    check_results_db(source)?;
+    let mut source_conn =
+        Connection::open_in_memory().with_context(|| "Failed to open an in-memory database")?;
+    source_conn
+        .restore(DatabaseName::Main, source, None::<fn(Progress)>)
+        .with_context(|| format!("Failed to read {}", source.display()))?;
+    migrate(&source_conn)?;
+
+    let columns: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('benchmarks') WHERE name NOT IN ('id', 'is_baseline')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read the benchmarks table's columns")?;
+    let index = |name: &str| {
+        columns
+            .iter()
+            .position(|column| column == name)
+            .expect("migrations add every key column")
+    };
+    let key = [
+        "run_id",
+        "created_at",
+        "commit_hash",
+        "command",
+        "parameters",
+        "machine",
+    ]
+    .map(index);
+    let machine = index("machine");
+
+    let tx = conn
+        .unchecked_transaction()
+        .with_context(|| "Failed to start transaction")?;
+    let mut summary = MergeSummary {
+        merged: 0,
+        skipped: 0,
+    };
+    // Child rows are keyed by the id each result had in the source
+    let mut merged_ids = Vec::new();
+    let mut stmt = source_conn.prepare(&format!(
+        "SELECT id, {} FROM benchmarks ORDER BY id",
+        columns.join(", ")
+    ))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let source_id: i64 = row.get(0)?;
+        let mut values = (1..=columns.len())
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<rusqlite::Result<Vec<Value>>>()?;
+        if values[machine] == Value::Null {
+            values[machine] = Value::Text(fallback_machine.to_string());
+        }
+        let exists: bool = tx.query_row(
+            "SELECT EXISTS (
+                 SELECT 1 FROM benchmarks
+                 WHERE run_id IS ?1 AND created_at IS ?2 AND commit_hash IS ?3
+                     AND command IS ?4 AND parameters IS ?5
+                     AND (machine IS ?6 OR machine IS NULL)
+             )",
+            params_from_iter(key.iter().map(|&i| &values[i])),
+            |row| row.get(0),
+        )?;
+        if exists {
+            summary.skipped += 1;
+            continue;
+        }
+        let row: Vec<(&str, &dyn ToSql)> = columns
+            .iter()
+            .zip(&values)
+            .map(|(column, value)| (column.as_str(), value as &dyn ToSql))
+            .collect();
+        insert_row(&tx, "benchmarks", &row)
+            .with_context(|| format!("Failed to merge a result from {}", source.display()))?;
+        merged_ids.push((source_id, tx.last_insert_rowid()));
+        summary.merged += 1;
+    }
+    for (table, columns) in CHILD_TABLES {
+        let mut select = source_conn.prepare(&format!(
+            "SELECT {} FROM {} WHERE benchmark_id = ?1",
+            columns.join(", "),
+            table
+        ))?;
+        for &(source_id, id) in &merged_ids {
+            let mut rows = select.query([source_id])?;
+            while let Some(row) = rows.next()? {
+                let values = (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<rusqlite::Result<Vec<Value>>>()?;
+                let mut child: Vec<(&str, &dyn ToSql)> = vec![("benchmark_id", &id)];
+                child.extend(
+                    columns
+                        .iter()
+                        .zip(&values)
+                        .map(|(column, value)| (*column, value as &dyn ToSql)),
+                );
+                insert_row(&tx, table, &child).with_context(|| {
+                    format!(
+                        "Failed to merge {} of a result from {}",
+                        table,
+                        source.display()
+                    )
+                })?;
+            }
+        }
+    }
+    tx.commit()
+        .with_context(|| "Failed to commit merged results")?;
+    Ok(summary)
+}
+
+fn migrate(conn: &Connection) -> Result<()> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .with_context(|| "Failed to read schema version")?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(migration)
+            .with_context(|| format!("Failed to apply schema migration {}", i + 1))?;
+        conn.pragma_update(None, "user_version", i + 1)
+            .with_context(|| "Failed to update schema version")?;
+    }
+
+    Ok(())
+}
+
+pub fn read_results_file(results_json_path: &Path) -> Result<HyperfineResults> {
+    let data = fs::read_to_string(results_json_path).with_context(|| {
+        format!(
+            "Failed to read results.json file at {}",
+            results_json_path.display()
+        )
+    })?;
+    let results: HyperfineResults =
+        serde_json::from_str(&data).with_context(|| "Failed to parse JSON from results.json")?;
+
+    let mut missing: Vec<&str> = results
+        .results
+        .iter()
+        .flat_map(|r| r.missing_fields.iter().copied())
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+    if !missing.is_empty() {
+        eprintln!(
+            "Warning: {} lacks {}, using fallback values",
+            results_json_path.display(),
+            missing.join(", ")
+        );
+    }
+    Ok(results)
+}
+
+/// What a run measured of each of its results beyond hyperfine's export, each list
+/// in the order of the results. Empty lists store nothing.
+#[derive(Default)]
+pub struct ResultDetails<'a> {
+    pub checkpoints: &'a [Vec<Checkpoint>],
+    pub reached_heights: &'a [Option<u64>],
+    /// Height results that synced to less are marked incomplete
+    pub stop_height: u64,
+    pub phases: &'a [Vec<SyncPhase>],
+    pub failed_assertions: &'a [Vec<String>],
+}
+
+/// Insert every result from a hyperfine export, returning the new row ids. Either
+/// all of the results are stored or, on error, none are.
+pub fn insert_results(
+    conn: &Connection,
+    commit: &str,
+    results: HyperfineResults,
+    metadata: &RunMetadata,
+) -> Result<Vec<i64>> {
+    insert_results_with(conn, commit, results, metadata, &ResultDetails::default())
+}
+
+/// [`insert_results`], storing `details` of the results in the same transaction.
+pub fn insert_results_with(
+    conn: &Connection,
+    commit: &str,
+    results: HyperfineResults,
+    metadata: &RunMetadata,
+    details: &ResultDetails,
+) -> Result<Vec<i64>> {
+    let created_at = Utc::now().to_rfc3339();
+    // Rolled back when dropped without committing
+    let tx = conn
+        .unchecked_transaction()
+        .with_context(|| "Failed to start transaction")?;
+
+    // Insert the benchmark results
+    let mut ids = Vec::new();
+    for result in results.results {
+        // Extract commit from parameters if available
+        let commit_value = if let Some(ref params) = result.parameters {
+            &params.commit
+        } else {
+            commit
+        };
+
+        let times =
+            serde_json::to_string(&result.times).with_context(|| "Failed to serialize times")?;
+        let exit_codes = serde_json::to_string(&result.exit_codes)
+            .with_context(|| "Failed to serialize exit_codes")?;
+        // A swept `par` is only known per result
+        let par: Option<i32> = result
+            .parameters
+            .as_ref()
+            .and_then(|params| params.extra.get("par"))
+            .and_then(|par| par.parse().ok())
+            .or(metadata.par);
+        let parameters = serde_json::to_string(&result.parameters)
+            .with_context(|| "Failed to serialize parameters")?;
+        let blocks_per_sec = match (metadata.start_height, metadata.stop_height) {
+            (Some(start), Some(stop)) if result.mean > 0.0 => {
+                Some(stop.saturating_sub(start) as f64 / result.mean)
+            }
+            _ => None,
+        };
+        let p50 = percentile(&result.times, 50.0, 1);
+        let p90 = percentile(&result.times, 90.0, 10);
+        let p99 = percentile(&result.times, 99.0, 100);
+
+        insert_row(
+            &tx,
+            "benchmarks",
+            &[
+                ("commit_hash", &commit_value),
+                ("created_at", &created_at),
+                ("command", &result.command),
+                ("mean", &result.mean),
+                ("stddev", &result.stddev),
+                ("median", &result.median),
+                ("user", &result.user),
+                ("system", &result.system),
+                ("min", &result.min),
+                ("max", &result.max),
+                ("times", &times),
+                ("exit_codes", &exit_codes),
+                ("parameters", &parameters),
+                ("load_start", &metadata.load_start),
+                ("load_end", &metadata.load_end),
+                ("steal_pct", &metadata.steal_pct),
+                ("swap_in_pages", &metadata.swap_in_pages),
+                ("swap_out_pages", &metadata.swap_out_pages),
+                ("build_secs", &metadata.build_secs),
+                ("build_jobs", &metadata.build_jobs),
+                ("compiler_cache", &metadata.compiler_cache),
+                ("cache_hit_rate", &metadata.cache_hit_rate),
+                ("cmake_generator", &metadata.cmake_generator),
+                ("parent_hash", &metadata.parent_hash),
+                ("commit_alias", &metadata.commit_alias),
+                ("run_label", &metadata.run_label),
+                ("ab_group", &metadata.ab_group),
+                ("start_height", &metadata.start_height),
+                ("stop_height", &metadata.stop_height),
+                ("height_delta", &metadata.height_delta),
+                ("blocks_per_sec", &blocks_per_sec),
+                ("p50", &p50),
+                ("p90", &p90),
+                ("p99", &p99),
+                ("status", &STATUS_OK),
+                ("storage", &metadata.storage),
+                ("repo_url", &metadata.repo_url),
+                ("assumevalid", &metadata.assumevalid),
+                ("assumeutxo_snapshot", &metadata.assumeutxo_snapshot),
+                ("snapshot_height", &metadata.snapshot_height),
+                ("cgroup_memory", &metadata.cgroup_memory),
+                ("cgroup_cpu", &metadata.cgroup_cpu),
+                ("isolate_cpus", &metadata.isolate_cpus),
+                ("kernel_isolated", &metadata.kernel_isolated),
+                ("cpu_governor", &metadata.cpu_governor),
+                ("retries", &metadata.retries),
+                ("throttled", &metadata.throttled),
+                ("max_temp_c", &metadata.max_temp_c),
+                ("min_cpu_mhz", &metadata.min_cpu_mhz),
+                ("rpc_command", &metadata.rpc_command),
+                ("src_files_changed", &metadata.src_files_changed),
+                ("src_insertions", &metadata.src_insertions),
+                ("src_deletions", &metadata.src_deletions),
+                ("bitcoind_version", &metadata.bitcoind_version),
+                ("toolchain", &metadata.toolchain),
+                ("datadir_size_bytes", &metadata.datadir_size_bytes),
+                ("run_id", &metadata.run_id),
+                ("par", &par),
+                ("batch_id", &metadata.batch_id),
+                ("batch_position", &metadata.batch_position),
+                ("commit_subject", &metadata.commit_subject),
+                ("pr_number", &metadata.pr_number),
+                ("hyperfine_version", &metadata.hyperfine_version),
+                ("dbcache", &metadata.dbcache),
+                ("dirty_action", &metadata.dirty_action),
+                ("machine", &metadata.machine),
+                ("log_path", &metadata.log_path),
+                ("output_path", &metadata.output_path),
+            ],
+        )
+        .with_context(|| "Failed to insert benchmark result into database")?;
+        ids.push(tx.last_insert_rowid());
+    }
+    record_checkpoints(&tx, &ids, details.checkpoints)?;
+    record_reached_heights(&tx, &ids, details.reached_heights, details.stop_height)?;
+    record_phases(&tx, &ids, details.phases)?;
+    record_failed_assertions(&tx, &ids, details.failed_assertions)?;
+
+    tx.commit()
+        .with_context(|| "Failed to commit benchmark results")?;
+    Ok(ids)
+}
+
+/// The `pct`th percentile of `times`, interpolating between the closest ranks.
+/// `None` with fewer than `min_runs` times, too few for the percentile to say more
+/// than the maximum does.
+fn percentile(times: &[f64], pct: f64, min_runs: usize) -> Option<f64> {
+    if times.is_empty() || times.len() < min_runs {
+        return None;
+    }
+    let mut sorted = times.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let rank = pct / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    let weight = rank - lower as f64;
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * weight)
+}
+
+/// Store a row recording that `commit` built with `build_command` without being
+/// benchmarked.
+pub fn record_build(
+    db_path: &Path,
+    commit: &str,
+    build_command: &str,
+    metadata: &RunMetadata,
+) -> Result<i64> {
+    let conn = open_db(db_path)?;
+    insert_row(
+        &conn,
+        "benchmarks",
+        &[
+            ("commit_hash", &commit),
+            ("created_at", &Utc::now().to_rfc3339()),
+            ("command", &build_command),
+            ("parent_hash", &metadata.parent_hash),
+            ("commit_alias", &metadata.commit_alias),
+            ("run_label", &metadata.run_label),
+            ("repo_url", &metadata.repo_url),
+            ("build_secs", &metadata.build_secs),
+            ("build_jobs", &metadata.build_jobs),
+            ("compiler_cache", &metadata.compiler_cache),
+            ("cache_hit_rate", &metadata.cache_hit_rate),
+            ("cmake_generator", &metadata.cmake_generator),
+            ("bitcoind_version", &metadata.bitcoind_version),
+            ("toolchain", &metadata.toolchain),
+            ("run_id", &metadata.run_id),
+            ("commit_subject", &metadata.commit_subject),
+            ("pr_number", &metadata.pr_number),
+            ("dirty_action", &metadata.dirty_action),
+            ("machine", &metadata.machine),
+            ("guix_output_hash", &metadata.guix_output_hash),
+            ("status", &STATUS_BUILT),
+        ],
+    )
+    .with_context(|| "Failed to insert build result into database")?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Store a row marking that `commit` failed to build, so it can be told apart from
+/// a benchmark that crashed.
+pub fn record_build_failure(
+    db_path: &Path,
+    commit: &str,
+    build_command: &str,
+    output_tail: &str,
+    metadata: &RunMetadata,
+) -> Result<i64> {
+    let conn = open_db(db_path)?;
+    insert_row(
+        &conn,
+        "benchmarks",
+        &[
+            ("commit_hash", &commit),
+            ("created_at", &Utc::now().to_rfc3339()),
+            ("command", &build_command),
+            ("parent_hash", &metadata.parent_hash),
+            ("commit_alias", &metadata.commit_alias),
+            ("run_label", &metadata.run_label),
+            ("storage", &metadata.storage),
+            ("repo_url", &metadata.repo_url),
+            ("assumevalid", &metadata.assumevalid),
+            ("assumeutxo_snapshot", &metadata.assumeutxo_snapshot),
+            ("toolchain", &metadata.toolchain),
+            ("run_id", &metadata.run_id),
+            ("dirty_action", &metadata.dirty_action),
+            ("machine", &metadata.machine),
+            ("status", &STATUS_BUILD_FAILED),
+            ("error", &output_tail),
+        ],
+    )
+    .with_context(|| "Failed to insert build failure into database")?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Store a row marking that bitcoind was killed for running out of memory while
+/// running `command`, so the settings it ran with can be told apart from those that fit.
+pub fn record_oom(
+    db_path: &Path,
+    commit: &str,
+    command: &str,
+    output_tail: &str,
+    metadata: &RunMetadata,
+) -> Result<i64> {
+    let conn = open_db(db_path)?;
+    insert_row(
+        &conn,
+        "benchmarks",
+        &[
+            ("commit_hash", &commit),
+            ("created_at", &Utc::now().to_rfc3339()),
+            ("command", &command),
+            ("parent_hash", &metadata.parent_hash),
+            ("commit_alias", &metadata.commit_alias),
+            ("run_label", &metadata.run_label),
+            ("storage", &metadata.storage),
+            ("repo_url", &metadata.repo_url),
+            ("assumevalid", &metadata.assumevalid),
+            ("assumeutxo_snapshot", &metadata.assumeutxo_snapshot),
+            ("cgroup_memory", &metadata.cgroup_memory),
+            ("run_id", &metadata.run_id),
+            ("par", &metadata.par),
+            ("dbcache", &metadata.dbcache),
+            ("dirty_action", &metadata.dirty_action),
+            ("machine", &metadata.machine),
+            ("log_path", &metadata.log_path),
+            ("output_path", &metadata.output_path),
+            ("status", &STATUS_OOM),
+            ("error", &output_tail),
+        ],
+    )
+    .with_context(|| "Failed to insert out of memory result into database")?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Store a row pointing at the `rr` trace of `command`, which recorded `commit`
+/// syncing, and the failure it exited with, if any.
+pub fn record_rr_trace(
+    db_path: &Path,
+    commit: &str,
+    command: &str,
+    trace_dir: &Path,
+    error: Option<&str>,
+    metadata: &RunMetadata,
+) -> Result<i64> {
+    let conn = open_db(db_path)?;
+    insert_row(
+        &conn,
+        "benchmarks",
+        &[
+            ("commit_hash", &commit),
+            ("created_at", &Utc::now().to_rfc3339()),
+            ("command", &command),
+            ("parent_hash", &metadata.parent_hash),
+            ("commit_alias", &metadata.commit_alias),
+            ("run_label", &metadata.run_label),
+            ("storage", &metadata.storage),
+            ("repo_url", &metadata.repo_url),
+            ("assumevalid", &metadata.assumevalid),
+            ("assumeutxo_snapshot", &metadata.assumeutxo_snapshot),
+            ("bitcoind_version", &metadata.bitcoind_version),
+            ("toolchain", &metadata.toolchain),
+            ("run_id", &metadata.run_id),
+            ("commit_subject", &metadata.commit_subject),
+            ("pr_number", &metadata.pr_number),
+            ("dirty_action", &metadata.dirty_action),
+            ("machine", &metadata.machine),
+            ("stop_height", &metadata.stop_height),
+            ("rr_trace", &trace_dir.display().to_string()),
+            ("status", &STATUS_RECORDED),
+            ("error", &error),
+        ],
+    )
+    .with_context(|| "Failed to insert rr recording into database")?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// What [`prune_to_size`] removed to shrink the database.
+pub struct PruneSummary {
+    pub deleted: usize,
+    /// Ids of the oldest and newest deleted results
+    pub first_id: i64,
+    pub last_id: i64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Delete the oldest results, except baselines, and `VACUUM` until the database at
+/// `db_path` is at most `max_bytes`. `None` if it already was. Stops short when only
+/// baselines are left.
+pub fn prune_to_size(
+    conn: &Connection,
+    db_path: &Path,
+    max_bytes: u64,
+) -> Result<Option<PruneSummary>> {
+    let size = || {
+        fs::metadata(db_path)
+            .map(|metadata| metadata.len())
+            .with_context(|| format!("Failed to read the size of {}", db_path.display()))
+    };
+    let bytes_before = size()?;
+    let mut summary: Option<PruneSummary> = None;
+    let mut bytes = bytes_before;
+    while bytes > max_bytes {
+        let prunable: u64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM benchmarks WHERE is_baseline = 0",
+                [],
+                |row| row.get(0),
+            )
+            .with_context(|| "Failed to count results")?;
+        if prunable == 0 {
+            break;
+        }
+        // Delete the share of results the excess is, as rows are about even in size
+        let excess = (bytes - max_bytes) as f64 / bytes as f64;
+        let count = ((prunable as f64 * excess).ceil() as u64).clamp(1, prunable);
+        let ids: Vec<i64> = conn
+            .prepare("SELECT id FROM benchmarks WHERE is_baseline = 0 ORDER BY id LIMIT ?1")?
+            .query_map([count], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()
+            .with_context(|| "Failed to read the oldest results")?;
+
+        let tx = conn
+            .unchecked_transaction()
+            .with_context(|| "Failed to start transaction")?;
+        let last = ids[ids.len() - 1];
+        tx.execute(
+            "DELETE FROM checkpoints WHERE benchmark_id IN
+             (SELECT id FROM benchmarks WHERE is_baseline = 0 AND id <= ?1)",
+            [last],
+        )
+        .with_context(|| "Failed to delete checkpoints of pruned results")?;
+        tx.execute(
+            "DELETE FROM phases WHERE benchmark_id IN
+             (SELECT id FROM benchmarks WHERE is_baseline = 0 AND id <= ?1)",
+            [last],
+        )
+        .with_context(|| "Failed to delete phases of pruned results")?;
+        tx.execute(
+            "DELETE FROM benchmarks WHERE is_baseline = 0 AND id <= ?1",
+            [last],
+        )
+        .with_context(|| "Failed to delete the oldest results")?;
+        tx.commit().with_context(|| "Failed to commit pruning")?;
+        // Deleted rows only give space back once the file is rebuilt
+        conn.execute_batch("VACUUM")
+            .with_context(|| "Failed to vacuum the database")?;
+
+        bytes = size()?;
+        let pruned = summary.get_or_insert(PruneSummary {
+            deleted: 0,
+            first_id: ids[0],
+            last_id: last,
+            bytes_before,
+            bytes_after: bytes,
+        });
+        pruned.deleted += ids.len();
+        pruned.last_id = last;
+        pruned.bytes_after = bytes;
+    }
+    Ok(summary)
+}
+
+/// Insert one result mapped from another tool's export, given as `benchmarks`
+/// columns and their values. It is stored as completed, and as stored now unless
+/// its `created_at` is given.
+pub fn insert_mapped(conn: &Connection, fields: &[(&str, Value)]) -> Result<i64> {
+    let created_at = Utc::now().to_rfc3339();
+    let mut columns: Vec<(&str, &dyn ToSql)> = fields
+        .iter()
+        .map(|(name, value)| (*name, value as &dyn ToSql))
+        .collect();
+    if !fields.iter().any(|(name, _)| *name == "created_at") {
+        columns.push(("created_at", &created_at));
+    }
+    columns.push(("status", &STATUS_OK));
+    insert_row(conn, "benchmarks", &columns).with_context(|| "Failed to insert imported result")?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Insert a single row given as `(column, value)` pairs.
+fn insert_row(
+    conn: &Connection,
+    table: &str,
+    columns: &[(&str, &dyn ToSql)],
+) -> rusqlite::Result<usize> {
+    let names: Vec<&str> = columns.iter().map(|(name, _)| *name).collect();
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+    let values: Vec<&dyn ToSql> = columns.iter().map(|(_, value)| *value).collect();
+    conn.execute(
+        &format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            names.join(", "),
+            placeholders.join(", ")
+        ),
+        values.as_slice(),
+    )
+}
+
+/// A stored benchmark row, as read back for reporting.
+#[derive(Serialize)]
+pub struct StoredResult {
+    pub id: i64,
+    pub commit_hash: String,
+    /// Friendly name shown in place of the hash, if the commit was given one
+    pub commit_alias: Option<String>,
+    pub command: String,
+    /// `None` for rows that never produced a timing, e.g. build failures
+    pub mean: Option<f64>,
+    pub stddev: Option<f64>,
+    pub median: Option<f64>,
+    pub min: Option<f64>,
+    /// Percentiles of the individual run times, `None` with too few runs
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+    pub blocks_per_sec: Option<f64>,
+    pub status: Option<String>,
+    pub note: Option<String>,
+    pub assumeutxo_snapshot: Option<String>,
+    /// Results are only comparable between runs from the same snapshot height
+    pub snapshot_height: Option<u64>,
+    /// Raw exit code of each run, `None` where it was killed by a signal
+    pub exit_codes: Vec<Option<i32>>,
+    /// Whether this is the promoted baseline for its command
+    pub is_baseline: bool,
+    /// Whether the CPU throttled during the run, making its timings suspect
+    pub throttled: Option<bool>,
+    /// Pull request the commit merged, if it was a merge commit
+    pub pr_number: Option<u64>,
+    pub hyperfine_version: Option<String>,
+    /// `Toolchain` JSON of the build, if it was recorded
+    pub toolchain: Option<String>,
+    /// What the run was for, as given with `run --label`
+    pub run_label: Option<String>,
+    /// Height an IBD run was to stop at, and the height it synced to, if known
+    pub stop_height: Option<u64>,
+    pub reached_height: Option<u64>,
+    /// Trace directory of a run recorded under `rr record`
+    pub rr_trace: Option<String>,
+    /// The configured assertions the result violated when it was stored
+    pub failed_assertions: Vec<String>,
+    /// Hash of the outputs of a `guix` build
+    pub guix_output_hash: Option<String>,
+    /// How many stored results this one pools, if it combines several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pooled_runs: Option<usize>,
+}
+
+/// Columns selected by queries returning `StoredResult`, in `stored_result` order.
+const STORED_COLUMNS: &str =
+    "id, commit_hash, command, mean, stddev, blocks_per_sec, status, note, \
+    assumeutxo_snapshot, snapshot_height, exit_codes, p50, p90, p99, is_baseline, commit_alias, throttled, \
+    pr_number, hyperfine_version, toolchain, median, min, run_label, stop_height, reached_height, rr_trace, \
+    failed_assertions, guix_output_hash";
+
+fn stored_result(row: &rusqlite::Row) -> rusqlite::Result<StoredResult> {
+    Ok(StoredResult {
+        id: row.get(0)?,
+        commit_hash: row.get(1)?,
+        command: row.get(2)?,
+        mean: row.get(3)?,
+        stddev: row.get(4)?,
+        blocks_per_sec: row.get(5)?,
+        status: row.get(6)?,
+        note: row.get(7)?,
+        assumeutxo_snapshot: row.get(8)?,
+        snapshot_height: row.get(9)?,
+        // Unparseable codes are reported by `verify`, not here
+        exit_codes: row
+            .get::<_, Option<String>>(10)?
+            .and_then(|codes| serde_json::from_str(&codes).ok())
+            .unwrap_or_default(),
+        p50: row.get(11)?,
+        p90: row.get(12)?,
+        p99: row.get(13)?,
+        is_baseline: row.get(14)?,
+        commit_alias: row.get(15)?,
+        throttled: row.get(16)?,
+        pr_number: row.get(17)?,
+        hyperfine_version: row.get(18)?,
+        toolchain: row.get(19)?,
+        median: row.get(20)?,
+        min: row.get(21)?,
+        run_label: row.get(22)?,
+        stop_height: row.get(23)?,
+        reached_height: row.get(24)?,
+        rr_trace: row.get(25)?,
+        failed_assertions: row
+            .get::<_, Option<String>>(26)?
+            .and_then(|failed| serde_json::from_str(&failed).ok())
+            .unwrap_or_default(),
+        guix_output_hash: row.get(27)?,
+        pooled_runs: None,
+    })
+}
+
+impl StoredResult {
+    /// The result's `metric`, if it was recorded.
+    pub fn value(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::Mean => self.mean,
+            Metric::Median => self.median,
+            Metric::Min => self.min,
+        }
+    }
+}
+
+/// Resolve a full or abbreviated commit hash, or a commit alias, to the single
+/// stored hash it matches.
+pub fn resolve_commit(conn: &Connection, prefix: &str) -> Result<Option<String>> {
+    // Hashes are stored in lowercase. A GLOB bound to a pattern with a literal
+    // prefix can use the index, where LIKE, being case-insensitive, can't.
+    let mut pattern = String::new();
+    for c in prefix.to_lowercase().chars() {
+        match c {
+            '*' | '?' | '[' => pattern.extend(['[', c, ']']),
+            c => pattern.push(c),
+        }
+    }
+    pattern.push('*');
+    // Two SELECTs, as an OR of the two keeps SQLite from using either index
+    let mut stmt = conn.prepare(
+        "SELECT commit_hash FROM benchmarks WHERE commit_hash GLOB ?2
+         UNION
+         SELECT commit_hash FROM benchmarks WHERE commit_alias = ?1",
+    )?;
+    let matches: Vec<String> = stmt
+        .query_map([prefix, &pattern], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| format!("Failed to look up commit {}", prefix))?;
+
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.into_iter().next()),
+        _ => Err(anyhow::anyhow!(
+            "Commit prefix {} is ambiguous, matches: {}",
+            prefix,
+            matches.join(", ")
+        )),
+    }
+}
+
+/// The most recent timed result for each command and snapshot benchmarked at `commit_hash`.
+pub fn latest_results(conn: &Connection, commit_hash: &str) -> Result<Vec<StoredResult>> {
+    latest_results_with(conn, commit_hash, false)
+}
+
+/// [`latest_results`], also considering incomplete IBD runs with `include_incomplete`.
+pub fn latest_results_with(
+    conn: &Connection,
+    commit_hash: &str,
+    include_incomplete: bool,
+) -> Result<Vec<StoredResult>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM benchmarks
+         WHERE id IN (
+             SELECT MAX(id) FROM benchmarks
+             WHERE commit_hash = ?1 AND mean IS NOT NULL
+               AND (?2 OR status IS NOT ?3)
+             GROUP BY command, snapshot_height
+         )
+         ORDER BY command",
+        STORED_COLUMNS
+    ))?;
+    let results = stmt
+        .query_map(
+            params![commit_hash, include_incomplete, STATUS_INCOMPLETE],
+            stored_result,
+        )?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| format!("Failed to read results for commit {}", commit_hash))?;
+    Ok(results)
+}
+
+/// Every non-NULL column of the result with `id`, as `(name, value)` pairs formatted
+/// for display.
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub fn result_columns(conn: &Connection, id: i64) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT * FROM benchmarks WHERE id = ?1")?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let values = stmt
+        .query_row([id], |row| {
+            (0..names.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .with_context(|| format!("Failed to read result {}", id))?;
+    let columns = names
+        .into_iter()
+        .zip(values)
+        .filter_map(|(name, value)| {
+            let value = match value {
+                Value::Null => return None,
+                Value::Integer(i) => i.to_string(),
+                Value::Real(f) => f.to_string(),
+                Value::Text(text) => text,
+                Value::Blob(blob) => format!("<{} bytes>", blob.len()),
+            };
+            Some((name, value))
+        })
+        .collect();
+    Ok(columns)
+}
+
+/// Whether the notification for run `run_id` was already delivered to `sink`.
+pub fn is_delivered(conn: &Connection, run_id: &str, sink: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT 1 FROM notifications WHERE run_id = ?1 AND sink = ?2",
+        [run_id, sink],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|row| row.is_some())
+    .with_context(|| {
+        format!(
+            "Failed to check delivery of notification for run {}",
+            run_id
+        )
+    })
+}
+
+pub fn record_delivery(conn: &Connection, run_id: &str, sink: &str) -> Result<()> {
+    insert_row(
+        conn,
+        "notifications",
+        &[
+            ("run_id", &run_id),
+            ("sink", &sink),
+            ("delivered_at", &Utc::now().to_rfc3339()),
+        ],
+    )
+    .with_context(|| {
+        format!(
+            "Failed to record delivery of notification for run {}",
+            run_id
+        )
+    })?;
+    Ok(())
+}
+
+/// Every stored result with a timing, oldest first.
+pub fn timed_results(conn: &Connection) -> Result<Vec<StoredResult>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM benchmarks WHERE mean IS NOT NULL ORDER BY id",
+        STORED_COLUMNS
+    ))?;
+    let results = stmt
+        .query_map([], stored_result)?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read timed results")?;
+    Ok(results)
+}
+
+/// Every timed result, newest first, of `commit_hash` if given and only of runs
+/// whose label contains `label` if given, for pooling repeated runs. IBD runs that
+/// stopped short of `stopatheight` are left out unless `include_incomplete`.
+pub fn poolable_results(
+    conn: &Connection,
+    commit_hash: Option<&str>,
+    label: Option<&str>,
+    include_incomplete: bool,
+) -> Result<Vec<StoredResult>> {
+    // `?1 IS NULL OR commit_hash = ?1` would keep the commit index from being used
+    let commit_filter = match commit_hash {
+        Some(_) => "commit_hash = ?1",
+        None => "?1 IS NULL",
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM benchmarks
+         WHERE mean IS NOT NULL AND {}
+           AND (?2 IS NULL OR instr(run_label, ?2) > 0)
+           AND (?3 OR status IS NOT ?4)
+         ORDER BY id DESC",
+        STORED_COLUMNS, commit_filter
+    ))?;
+    let results = stmt
+        .query_map(
+            params![commit_hash, label, include_incomplete, STATUS_INCOMPLETE],
+            stored_result,
+        )?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read results to pool")?;
+    Ok(results)
+}
+
+/// The `limit` most recently stored results, newest first, only of runs whose
+/// label contains `label` if given.
+pub fn recent_results(
+    conn: &Connection,
+    limit: usize,
+    label: Option<&str>,
+) -> Result<Vec<StoredResult>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM benchmarks
+         WHERE ?2 IS NULL OR instr(run_label, ?2) > 0
+         ORDER BY id DESC LIMIT ?1",
+        STORED_COLUMNS
+    ))?;
+    let results = stmt
+        .query_map(params![limit, label], stored_result)?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read recent results")?;
+    Ok(results)
+}
+
+/// Attach a free-text note to a stored result, replacing any existing note.
+pub fn annotate(conn: &Connection, id: i64, note: &str) -> Result<()> {
+    let updated = conn
+        .execute(
+            "UPDATE benchmarks SET note = ?1 WHERE id = ?2",
+            params![note, id],
+        )
+        .with_context(|| format!("Failed to annotate result {}", id))?;
+    if updated == 0 {
+        return Err(anyhow::anyhow!("No result with id {}", id));
+    }
+    Ok(())
+}
+
+/// Store the assertions each of the results `ids` violated, given in the same order.
+fn record_failed_assertions(conn: &Connection, ids: &[i64], failed: &[Vec<String>]) -> Result<()> {
+    for (id, failed) in ids.iter().zip(failed) {
+        if failed.is_empty() {
+            continue;
+        }
+        conn.execute(
+            "UPDATE benchmarks SET failed_assertions = ?1 WHERE id = ?2",
+            params![serde_json::to_string(failed)?, id],
+        )
+        .with_context(|| format!("Failed to record the assertions result {} failed", id))?;
+    }
+    Ok(())
+}
+
+/// Store the height each of the results `ids` synced to, given in the same order,
+/// marking those that fell short of `stop_height` as incomplete. A run that never
+/// updated its tip is incomplete too.
+fn record_reached_heights(
+    conn: &Connection,
+    ids: &[i64],
+    heights: &[Option<u64>],
+    stop_height: u64,
+) -> Result<()> {
+    for (id, height) in ids.iter().zip(heights) {
+        conn.execute(
+            "UPDATE benchmarks SET reached_height = ?1 WHERE id = ?2",
+            params![height, id],
+        )
+        .with_context(|| format!("Failed to record the height result {} reached", id))?;
+        if height.is_none_or(|height| height < stop_height) {
+            conn.execute(
+                "UPDATE benchmarks SET status = ?1 WHERE id = ?2",
+                params![STATUS_INCOMPLETE, id],
+            )
+            .with_context(|| format!("Failed to mark result {} incomplete", id))?;
+        }
+    }
+    Ok(())
+}
+
+/// Store the checkpoints each of the results `ids` reached, given in the same order.
+fn record_checkpoints(
+    conn: &Connection,
+    ids: &[i64],
+    checkpoints: &[Vec<Checkpoint>],
+) -> Result<()> {
+    for (id, checkpoints) in ids.iter().zip(checkpoints) {
+        for checkpoint in checkpoints {
+            insert_row(
+                conn,
+                "checkpoints",
+                &[
+                    ("benchmark_id", id),
+                    ("height", &checkpoint.height),
+                    ("cumulative_secs", &checkpoint.cumulative_secs),
+                    ("segment_secs", &checkpoint.segment_secs),
+                ],
+            )
+            .with_context(|| "Failed to insert checkpoint into database")?;
+        }
+    }
+    Ok(())
+}
+
+/// Store the phases each of the results `ids` went through, given in the same
+/// order.
+fn record_phases(conn: &Connection, ids: &[i64], phases: &[Vec<SyncPhase>]) -> Result<()> {
+    for (id, phases) in ids.iter().zip(phases) {
+        for phase in phases {
+            insert_row(
+                conn,
+                "phases",
+                &[
+                    ("benchmark_id", id),
+                    ("phase", &phase.name),
+                    ("secs", &phase.secs),
+                ],
+            )
+            .with_context(|| "Failed to insert phase into database")?;
+        }
+    }
+    Ok(())
+}
+
+/// Point results whose kept log was at `old` to `new`, or to no log when the log
+/// was deleted.
+pub fn set_log_path(conn: &Connection, old: &Path, new: Option<&Path>) -> Result<()> {
+    set_kept_path(conn, "log_path", old, new)
+}
+
+/// Point results whose kept command output was at `old` to `new`, or to no output
+/// when it was deleted.
+pub fn set_output_path(conn: &Connection, old: &Path, new: Option<&Path>) -> Result<()> {
+    set_kept_path(conn, "output_path", old, new)
+}
+
+fn set_kept_path(conn: &Connection, column: &str, old: &Path, new: Option<&Path>) -> Result<()> {
+    let new = new.map(|path| path.display().to_string());
+    conn.execute(
+        &format!("UPDATE benchmarks SET {0} = ?1 WHERE {0} = ?2", column),
+        params![new, old.display().to_string()],
+    )
+    .with_context(|| format!("Failed to update {} {}", column, old.display()))?;
+    Ok(())
+}
+
+/// Whether any run of `commit_hash` has been stored, including failed builds.
+pub fn is_benchmarked(conn: &Connection, commit_hash: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (
+             SELECT 1 FROM benchmarks
+             WHERE commit_hash = ?1 AND status IS NOT ?2 AND status IS NOT ?3
+         )",
+        [commit_hash, STATUS_BUILT, STATUS_RECORDED],
+        |row| row.get(0),
+    )
+    .with_context(|| format!("Failed to look up commit {}", commit_hash))
+}
+
+/// Record that `commit_hash` was left unbenchmarked by a commit filter, and why.
+pub fn record_filtered(
+    conn: &Connection,
+    commit_hash: &str,
+    reason: &str,
+    batch_id: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO filtered_commits (commit_hash, reason, batch_id, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![commit_hash, reason, batch_id, Utc::now().to_rfc3339()],
+    )
+    .with_context(|| format!("Failed to record that {} was filtered out", commit_hash))?;
+    Ok(())
+}
+
+/// Make a result the baseline later runs of the same command and snapshot height
+/// are checked against, replacing any previous baseline.
+pub fn promote(conn: &Connection, id: i64) -> Result<()> {
+    let tx = conn
+        .unchecked_transaction()
+        .with_context(|| "Failed to start transaction")?;
+
+    let (command, snapshot_height, mean): (String, Option<u64>, Option<f64>) = tx
+        .query_row(
+            "SELECT command, snapshot_height, mean FROM benchmarks WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .with_context(|| format!("Failed to read result {}", id))?
+        .ok_or_else(|| anyhow::anyhow!("No result with id {}", id))?;
+    if mean.is_none() {
+        return Err(anyhow::anyhow!(
+            "Result {} has no timing and can't be a baseline",
+            id
+        ));
+    }
+
+    tx.execute(
+        "UPDATE benchmarks SET is_baseline = 0
+         WHERE command = ?1 AND snapshot_height IS ?2",
+        params![command, snapshot_height],
+    )
+    .with_context(|| "Failed to clear the previous baseline")?;
+    tx.execute("UPDATE benchmarks SET is_baseline = 1 WHERE id = ?1", [id])
+        .with_context(|| format!("Failed to promote result {}", id))?;
+    tx.commit().with_context(|| "Failed to commit baseline")?;
+    Ok(())
+}
+
+/// The `limit` commits with timed results that were most recently benchmarked,
+/// newest first.
+pub fn recent_commits(conn: &Connection, limit: usize) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT commit_hash FROM benchmarks WHERE mean IS NOT NULL
+         GROUP BY commit_hash ORDER BY MAX(id) DESC LIMIT ?1",
+    )?;
+    let commits = stmt
+        .query_map([limit], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read recently benchmarked commits")?;
+    Ok(commits)
+}
+
+/// Every commit with timed results.
+pub fn timed_commits(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT DISTINCT commit_hash FROM benchmarks WHERE mean IS NOT NULL")?;
+    let commits = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read benchmarked commits")?;
+    Ok(commits)
+}
+
+/// The earliest and latest time any of `results` was stored, where recorded.
+pub fn stored_between(
+    conn: &Connection,
+    results: &[&StoredResult],
+) -> Result<(Option<String>, Option<String>)> {
+    let mut times = Vec::new();
+    for result in results {
+        let created_at: Option<String> = conn
+            .query_row(
+                "SELECT created_at FROM benchmarks WHERE id = ?1",
+                [result.id],
+                |row| row.get(0),
+            )
+            .with_context(|| format!("Failed to read time of result {}", result.id))?;
+        times.extend(created_at);
+    }
+    // RFC 3339 timestamps in UTC sort chronologically
+    times.sort();
+    Ok((times.first().cloned(), times.last().cloned()))
+}
+
+/// The recorded parent of `commit_hash`, if any run captured it.
+pub fn parent_hash(conn: &Connection, commit_hash: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT parent_hash FROM benchmarks
+         WHERE commit_hash = ?1 AND parent_hash IS NOT NULL
+         ORDER BY id DESC LIMIT 1",
+        [commit_hash],
+        |row| row.get(0),
+    )
+    .optional()
+    .with_context(|| format!("Failed to read parent of commit {}", commit_hash))
+}
+
+/// The id and output hash of the newest `guix` build of `commit_hash` stored by
+/// `command`, which a new build of it should reproduce.
+pub fn previous_guix_output(
+    conn: &Connection,
+    commit_hash: &str,
+    command: &str,
+) -> Result<Option<(i64, String)>> {
+    conn.query_row(
+        "SELECT id, guix_output_hash FROM benchmarks
+         WHERE commit_hash = ?1 AND command = ?2 AND guix_output_hash IS NOT NULL
+         ORDER BY id DESC LIMIT 1",
+        [commit_hash, command],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .with_context(|| format!("Failed to read earlier guix builds of {}", commit_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn results(json: &str) -> HyperfineResults {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn row_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM benchmarks", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn failed_insert_leaves_no_rows() {
+        let conn = open_db(Path::new(":memory:")).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER fail_bad BEFORE INSERT ON benchmarks WHEN NEW.command = 'bad'
+             BEGIN SELECT RAISE(ABORT, 'forced failure'); END",
+        )
+        .unwrap();
+        let results = results(
+            r#"{"results": [
+                {"command": "good", "mean": 1.0, "times": [1.0]},
+                {"command": "bad", "mean": 2.0, "times": [2.0]}
+            ]}"#,
+        );
+
+        let inserted = insert_results(&conn, "abc", results, &RunMetadata::default());
+        assert!(inserted.is_err());
+        assert_eq!(row_count(&conn), 0);
+    }
+
+    #[test]
+    fn failed_detail_insert_leaves_no_rows() {
+        let conn = open_db(Path::new(":memory:")).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER fail_phase BEFORE INSERT ON phases
+             BEGIN SELECT RAISE(ABORT, 'forced failure'); END",
+        )
+        .unwrap();
+        let results = results(r#"{"results": [{"command": "a", "mean": 1.0, "times": [1.0]}]}"#);
+        let phases = [vec![SyncPhase {
+            name: "blocks",
+            secs: 1.0,
+        }]];
+        let details = ResultDetails {
+            phases: &phases,
+            ..Default::default()
+        };
+
+        let inserted =
+            insert_results_with(&conn, "abc", results, &RunMetadata::default(), &details);
+        assert!(inserted.is_err());
+        assert_eq!(row_count(&conn), 0);
+    }
+
+    #[test]
+    fn each_result_is_stored_under_its_own_commit() {
+        let conn = open_db(Path::new(":memory:")).unwrap();
+        let results = results(
+            r#"{"results": [
+                {"command": "a", "mean": 1.0, "times": [1.0], "parameters": {"commit": "aaa"}},
+                {"command": "b", "mean": 2.0, "times": [2.0], "parameters": {"commit": "bbb"}}
+            ]}"#,
+        );
+
+        let ids = insert_results(&conn, "requested", results, &RunMetadata::default()).unwrap();
+        let commits: Vec<String> = ids
+            .iter()
+            .map(|id| {
+                conn.query_row(
+                    "SELECT commit_hash FROM benchmarks WHERE id = ?1",
+                    [id],
+                    |row| row.get(0),
+                )
+                .unwrap()
+            })
+            .collect();
+        assert_eq!(commits, ["aaa", "bbb"]);
+    }
+
+    thread_local! {
+        /// Statements run on a traced connection on this thread, with their
+        /// parameters bound.
+        static TRACED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn trace_statement(sql: &str) {
+        TRACED.with(|traced| traced.borrow_mut().push(sql.to_string()));
+    }
+
+    /// The query plan of each statement `run` executes on `conn`, leaving out those
+    /// without one, e.g. `BEGIN`.
+    fn plans(conn: &mut Connection, run: impl FnOnce(&Connection)) -> Vec<(String, String)> {
+        TRACED.with(|traced| traced.borrow_mut().clear());
+        conn.trace(Some(trace_statement));
+        run(conn);
+        conn.trace(None);
+        TRACED
+            .with(|traced| traced.take())
+            .into_iter()
+            .filter_map(|sql| {
+                let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)).ok()?;
+                let details: Vec<String> = stmt
+                    .query_map([], |row| row.get(3))
+                    .unwrap()
+                    .collect::<rusqlite::Result<_>>()
+                    .unwrap();
+                (!details.is_empty()).then(|| (sql, details.join("\n")))
+            })
+            .collect()
+    }
+
+    /// Fail unless every statement in `plans` searches an index instead of scanning.
+    fn assert_indexed(plans: &[(String, String)]) {
+        assert!(!plans.is_empty());
+        for (sql, plan) in plans {
+            assert!(
+                !plan.contains("SCAN") && plan.contains("SEARCH") && plan.contains("INDEX"),
+                "{} is planned as\n{}",
+                sql,
+                plan
+            );
+        }
+    }
+
+    /// A database at `path` with many results, each with checkpoints and phases.
+    fn synthetic_db(path: &Path) -> Connection {
+        let conn = open_db(path).unwrap();
+        conn.execute_batch(
+            "WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20000)
+             INSERT INTO benchmarks (commit_hash, commit_alias, command, mean, status)
+             SELECT lower(hex(randomblob(20))), 'v' || i, 'command ' || (i % 10), i, 'ok'
+             FROM n;
+             INSERT INTO checkpoints SELECT id, 1000, 1.0, 1.0 FROM benchmarks;
+             INSERT INTO phases SELECT id, 'blocks', 1.0 FROM benchmarks;",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn list_walks_results_newest_first_without_sorting() {
+        let mut conn = synthetic_db(Path::new(":memory:"));
+        let plans = plans(&mut conn, |conn| {
+            recent_results(conn, 20, None).unwrap();
+        });
+        let plans: Vec<&str> = plans.iter().map(|(_, plan)| plan.as_str()).collect();
+        assert_eq!(plans, ["SCAN benchmarks"]);
+    }
+
+    #[test]
+    fn commit_lookups_search_indexes() {
+        let mut conn = synthetic_db(Path::new(":memory:"));
+        let commit: String = conn
+            .query_row(
+                "SELECT commit_hash FROM benchmarks WHERE id = 1234",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_indexed(&plans(&mut conn, |conn| {
+            assert_eq!(
+                resolve_commit(conn, &commit[..12]).unwrap(),
+                Some(commit.clone())
+            );
+            assert_eq!(resolve_commit(conn, "v1234").unwrap(), Some(commit.clone()));
+        }));
+        // compare and regression detection
+        assert_indexed(&plans(&mut conn, |conn| {
+            latest_results(conn, &commit).unwrap();
+        }));
+        // Statistics pooled over a commit's runs
+        assert_indexed(&plans(&mut conn, |conn| {
+            poolable_results(conn, Some(&commit), None, false).unwrap();
+        }));
+    }
+
+    #[test]
+    fn prune_searches_indexes() {
+        let path = std::env::temp_dir().join(format!("bench-prune-{}.db", std::process::id()));
+        let mut conn = synthetic_db(&path);
+        let size = fs::metadata(&path).unwrap().len();
+        let plans = plans(&mut conn, |conn| {
+            prune_to_size(conn, &path, size - 1).unwrap();
+        });
+        let _ = fs::remove_file(&path);
+        assert_indexed(&plans);
+    }
+}
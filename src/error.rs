@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+use crate::exit_code;
+
+/// How a step of a benchmark run failed, for callers that handle failures by kind,
+/// e.g. retrying a fetch but not a build. Converts to `anyhow::Error` like any
+/// other error, and can be recovered from it with `downcast_ref`.
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    /// Fetching from the remote failed, e.g. for lack of network
+    #[error("Failed to fetch {commit}")]
+    GitFetch {
+        commit: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The commit could not be resolved or checked out
+    #[error("Failed to check out {commit}")]
+    Checkout {
+        commit: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The build exited unsuccessfully, i.e. the commit doesn't compile
+    #[error("Build failed with status {status}:\n{output_tail}")]
+    Build {
+        command: String,
+        status: ExitStatus,
+        output_tail: String,
+    },
+    /// hyperfine exited unsuccessfully, i.e. the benchmarked command failed
+    #[error(
+        "Hyperfine command failed with status {status}{}:\n{output_tail}",
+        hyperfine_reason(*.oom, *.exit_code)
+    )]
+    Hyperfine {
+        command: String,
+        status: ExitStatus,
+        /// Exit code hyperfine reported for the command, if it could be read
+        exit_code: Option<i32>,
+        /// Whether bitcoind was killed for running out of memory
+        oom: bool,
+        output_tail: String,
+    },
+    /// The results hyperfine exported are missing or unreadable
+    #[error("Failed to read results from {}", .path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The results could not be stored
+    #[error("Failed to store results in {}", .path.display())]
+    Db {
+        path: PathBuf,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// Anything else, e.g. a misconfiguration found while setting up the step
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// Why bitcoind stopped, as shown after hyperfine's status.
+fn hyperfine_reason(oom: bool, exit_code: Option<i32>) -> String {
+    let reason = if oom {
+        Some("ran out of memory and was killed".to_string())
+    } else {
+        exit_code.and_then(|code| exit_code::describe(Some(code)))
+    };
+    reason.map_or(String::new(), |reason| format!(", bitcoind {}", reason))
+}
@@ -0,0 +1,42 @@
+/// A readable reason for a benchmarked command's exit code, or `None` for success.
+/// hyperfine records `null` when the process itself was killed by a signal, and
+/// shells report a child killed by signal N as 128+N.
+pub fn describe(code: Option<i32>) -> Option<String> {
+    let signal = match code {
+        Some(0) => return None,
+        None => return Some("killed by a signal".to_string()),
+        Some(code) if code < 0 => -code,
+        Some(code) if code > 128 && code < 128 + 65 => code - 128,
+        Some(code) => return Some(format!("exited with status {}", code)),
+    };
+
+    let reason = match signal {
+        9 => "killed by SIGKILL (likely OOM)".to_string(),
+        11 => "killed by SIGSEGV (segmentation fault)".to_string(),
+        6 => "killed by SIGABRT (assertion failure or abort)".to_string(),
+        15 => "killed by SIGTERM".to_string(),
+        2 => "killed by SIGINT".to_string(),
+        7 => "killed by SIGBUS (bad memory access)".to_string(),
+        4 => "killed by SIGILL (illegal instruction)".to_string(),
+        signal => format!("killed by signal {}", signal),
+    };
+    Some(reason)
+}
+
+/// The exit code hyperfine reports for a failed command in its error message,
+/// e.g. "Command terminated with non-zero exit code: 137".
+pub fn from_output(output: &str) -> Option<i32> {
+    let (_, rest) = output.rsplit_once("non-zero exit code")?;
+    let digits: String = rest
+        .trim_start_matches(|c: char| c == ':' || c.is_whitespace())
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '-')
+        .collect();
+    digits.parse().ok()
+}
+
+/// Whether `code` is how a command killed by SIGKILL exits, as it is when the
+/// kernel's OOM killer stops it.
+pub fn is_sigkill(code: i32) -> bool {
+    code == 128 + 9 || code == -9
+}
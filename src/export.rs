@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::path::Path;
+
+/// A stored result with its JSON columns decoded, as written by exporters.
+#[cfg_attr(not(feature = "parquet"), allow(dead_code))]
+pub struct ExportRow {
+    pub id: i64,
+    pub commit_hash: String,
+    pub parent_hash: Option<String>,
+    pub command: String,
+    pub mean: Option<f64>,
+    pub stddev: Option<f64>,
+    pub median: Option<f64>,
+    pub user: Option<f64>,
+    pub system: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub times: Vec<f64>,
+    pub exit_codes: Vec<Option<i32>>,
+    pub blocks_per_sec: Option<f64>,
+    pub build_secs: Option<f64>,
+    pub status: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Every stored result, oldest first.
+pub fn rows(conn: &Connection) -> Result<Vec<ExportRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, commit_hash, parent_hash, command, mean, stddev, median, user, system,
+                min, max, times, exit_codes, blocks_per_sec, build_secs, status, note
+         FROM benchmarks ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(ExportRow {
+                id: row.get(0)?,
+                commit_hash: row.get(1)?,
+                parent_hash: row.get(2)?,
+                command: row.get(3)?,
+                mean: row.get(4)?,
+                stddev: row.get(5)?,
+                median: row.get(6)?,
+                user: row.get(7)?,
+                system: row.get(8)?,
+                min: row.get(9)?,
+                max: row.get(10)?,
+                // Unparseable columns are reported by `verify`, not here
+                times: json_column(row.get(11)?),
+                exit_codes: json_column(row.get(12)?),
+                blocks_per_sec: row.get(13)?,
+                build_secs: row.get(14)?,
+                status: row.get(15)?,
+                note: row.get(16)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read results for export")?;
+    Ok(rows)
+}
+
+fn json_column<T: serde::de::DeserializeOwned + Default>(json: Option<String>) -> T {
+    json.and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Write `rows` to a Snappy-compressed Parquet file, with timings as float columns
+/// and `times`/`exit_codes` as list columns.
+#[cfg(feature = "parquet")]
+pub fn write_parquet(rows: &[ExportRow], path: &Path) -> Result<()> {
+    use arrow::array::{
+        ArrayRef, Float64Array, Float64Builder, Int32Builder, Int64Array, ListBuilder, StringArray,
+    };
+    use arrow::record_batch::RecordBatch;
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use std::sync::Arc;
+
+    let float = |value: fn(&ExportRow) -> Option<f64>| -> ArrayRef {
+        Arc::new(Float64Array::from_iter(rows.iter().map(value)))
+    };
+    let string = |value: fn(&ExportRow) -> Option<&str>| -> ArrayRef {
+        Arc::new(StringArray::from_iter(rows.iter().map(value)))
+    };
+
+    let mut times = ListBuilder::new(Float64Builder::new());
+    let mut exit_codes = ListBuilder::new(Int32Builder::new());
+    for row in rows {
+        times.values().append_slice(&row.times);
+        times.append(true);
+        for &code in &row.exit_codes {
+            exit_codes.values().append_option(code);
+        }
+        exit_codes.append(true);
+    }
+
+    let batch = RecordBatch::try_from_iter([
+        (
+            "id",
+            Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.id))) as ArrayRef,
+        ),
+        ("commit_hash", string(|r| Some(r.commit_hash.as_str()))),
+        ("parent_hash", string(|r| r.parent_hash.as_deref())),
+        ("command", string(|r| Some(r.command.as_str()))),
+        ("mean", float(|r| r.mean)),
+        ("stddev", float(|r| r.stddev)),
+        ("median", float(|r| r.median)),
+        ("user", float(|r| r.user)),
+        ("system", float(|r| r.system)),
+        ("min", float(|r| r.min)),
+        ("max", float(|r| r.max)),
+        ("times", Arc::new(times.finish()) as ArrayRef),
+        ("exit_codes", Arc::new(exit_codes.finish()) as ArrayRef),
+        ("blocks_per_sec", float(|r| r.blocks_per_sec)),
+        ("build_secs", float(|r| r.build_secs)),
+        ("status", string(|r| r.status.as_deref())),
+        ("note", string(|r| r.note.as_deref())),
+    ])
+    .with_context(|| "Failed to build Parquet record batch")?;
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(properties))
+        .with_context(|| "Failed to create Parquet writer")?;
+    writer
+        .write(&batch)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    writer
+        .close()
+        .with_context(|| format!("Failed to finish {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet(_rows: &[ExportRow], _path: &Path) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Parquet export requires building with `--features parquet`"
+    ))
+}
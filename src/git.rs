@@ -0,0 +1,432 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::error::BenchError;
+use crate::runner::{Local, Runner};
+
+/// The commit that was checked out for a run.
+pub struct Checkout {
+    /// Full SHA the requested commit resolved to
+    pub commit_hash: String,
+    /// SHA of the first parent, `None` for a root commit
+    pub parent_hash: Option<String>,
+    /// What the commit changed under `src/` relative to its parent
+    pub src_diff: Option<DiffStat>,
+    /// First line of the commit message
+    pub subject: Option<String>,
+    /// How local changes were put aside before checking out, if there were any
+    pub dirty_action: Option<DirtyAction>,
+}
+
+/// What to do when the checkout has local changes or untracked files, which could
+/// block the checkout or be built into the benchmarked binary.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DirtyPolicy {
+    /// Fail the run, leaving the changes alone
+    #[default]
+    Abort,
+    /// `git stash` them, untracked files included, to be recovered with `git stash pop`
+    Stash,
+    /// Discard them with `git reset --hard` and `git clean -fd`. Ignored files such
+    /// as the build directory are kept.
+    Clean,
+}
+
+/// How a dirty checkout was made clean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirtyAction {
+    Stashed,
+    Cleaned,
+}
+
+impl DirtyAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            DirtyAction::Stashed => "stashed",
+            DirtyAction::Cleaned => "cleaned",
+        }
+    }
+}
+
+/// How a commit is checked out.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CheckoutOptions {
+    /// Discard local changes to tracked files that would block the checkout
+    pub force: bool,
+    /// Update submodules to the commit's, if the repository has any
+    pub submodules: bool,
+    pub dirty: DirtyPolicy,
+}
+
+/// Totals from `git diff --shortstat`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub files_changed: u64,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+/// Fetch and check out `commit`. With `remote_url`, the commit is fetched from that
+/// repository, e.g. a contributor's fork, through a temporary remote that is removed
+/// again afterwards. Without `fetch`, the commit must already be in the checkout.
+pub fn update_repository(
+    commit: &str,
+    runner: &dyn Runner,
+    remote_url: Option<&str>,
+    fetch: bool,
+    options: CheckoutOptions,
+) -> Result<Checkout, BenchError> {
+    let fetch_error = |source| BenchError::GitFetch {
+        commit: commit.to_string(),
+        source,
+    };
+    let checkout_error = |source| BenchError::Checkout {
+        commit: commit.to_string(),
+        source,
+    };
+
+    if !fetch {
+        if rev_parse(runner, &format!("{}^{{commit}}", commit)).is_err() {
+            return Err(checkout_error(anyhow::anyhow!(
+                "Commit {} is not in the repository; run without --no-fetch to fetch it",
+                commit
+            )));
+        }
+        return checkout(runner, commit, options).map_err(checkout_error);
+    }
+
+    let Some(remote_url) = remote_url else {
+        runner
+            .command("git", &["fetch", "--all"])
+            .status()
+            .with_context(|| "Failed to fetch git repository")
+            .map_err(fetch_error)?;
+        return checkout(runner, commit, options).map_err(checkout_error);
+    };
+
+    let remote = format!("bench-{}", std::process::id());
+    git(runner, &["remote", "add", &remote, remote_url])
+        .with_context(|| format!("Failed to add remote for {}", remote_url))?;
+    let checkout = git(runner, &["fetch", &remote])
+        .with_context(|| format!("Failed to fetch {}", remote_url))
+        .map_err(fetch_error)
+        .and_then(|()| {
+            // Branch names only exist under the temporary remote
+            let remote_branch = format!("{}/{}", remote, commit);
+            if rev_parse(runner, &format!("{}^{{commit}}", commit)).is_err()
+                && rev_parse(runner, &format!("{}^{{commit}}", remote_branch)).is_ok()
+            {
+                checkout(runner, &remote_branch, options)
+            } else {
+                checkout(runner, commit, options)
+            }
+            .map_err(checkout_error)
+        });
+    if let Err(e) = git(runner, &["remote", "remove", &remote]) {
+        eprintln!("Warning: failed to remove remote {}: {:#}", remote, e);
+    }
+    checkout
+}
+
+fn checkout(runner: &dyn Runner, commit: &str, options: CheckoutOptions) -> Result<Checkout> {
+    // Resolved first, as revisions like `HEAD~1` move with the checkout
+    let commit_hash = rev_parse(runner, &format!("{}^{{commit}}", commit))?;
+    let dirty_action = clean_worktree(runner, &commit_hash, options)?;
+    // Checking out a commit detaches HEAD, which is expected here
+    let mut args = vec!["-c", "advice.detachedHead=false", "checkout"];
+    if options.force {
+        args.push("--force");
+    }
+    args.push(&commit_hash);
+    git(runner, &args)?;
+    if options.submodules && rev_parse(runner, "HEAD:.gitmodules").is_ok() {
+        git(runner, &["submodule", "update", "--init", "--recursive"])
+            .with_context(|| format!("Failed to update submodules for {}", commit))?;
+    }
+
+    let parent_hash = rev_parse(runner, &format!("{}^", commit_hash)).ok();
+    let src_diff = parent_hash.as_ref().and_then(|parent| {
+        git_output(
+            runner,
+            &["diff", "--shortstat", parent, &commit_hash, "--", "src/"],
+        )
+        .map(|shortstat| parse_shortstat(&shortstat))
+        .map_err(|e| eprintln!("Warning: failed to diff against parent: {:#}", e))
+        .ok()
+    });
+
+    let subject = subject(runner, &commit_hash)
+        .map_err(|e| eprintln!("Warning: failed to read commit subject: {:#}", e))
+        .ok();
+
+    Ok(Checkout {
+        commit_hash,
+        parent_hash,
+        src_diff,
+        subject,
+        dirty_action,
+    })
+}
+
+/// Apply the dirty policy to any local changes before checking out `commit_hash`.
+/// Changes to tracked files don't count with `force`, which discards them anyway.
+fn clean_worktree(
+    runner: &dyn Runner,
+    commit_hash: &str,
+    options: CheckoutOptions,
+) -> Result<Option<DirtyAction>> {
+    let status = git_output(runner, &["status", "--porcelain"])?;
+    let dirty: Vec<&str> = status
+        .lines()
+        .filter(|line| !options.force || line.starts_with("??"))
+        .collect();
+    if dirty.is_empty() {
+        return Ok(None);
+    }
+
+    match options.dirty {
+        DirtyPolicy::Abort => Err(anyhow::anyhow!(
+            "The checkout has local changes that could end up in the benchmark:\n{}\n\
+             Commit or remove them, or set dirty_policy to `stash` or `clean`",
+            dirty.join("\n")
+        )),
+        DirtyPolicy::Stash => {
+            let message = format!("bitcoin_benchmark: before checking out {}", commit_hash);
+            git(
+                runner,
+                &["stash", "push", "--include-untracked", "-m", &message],
+            )
+            .with_context(|| "Failed to stash local changes")?;
+            println!("Stashed local changes, recover them with `git stash pop`");
+            Ok(Some(DirtyAction::Stashed))
+        }
+        DirtyPolicy::Clean => {
+            git(runner, &["reset", "--hard"]).with_context(|| "Failed to discard local changes")?;
+            git(runner, &["clean", "-fd"]).with_context(|| "Failed to remove untracked files")?;
+            println!("Discarded local changes and untracked files");
+            Ok(Some(DirtyAction::Cleaned))
+        }
+    }
+}
+
+/// The pull request a merge commit merged, from subjects such as "Merge
+/// bitcoin/bitcoin#12345: ...", "Merge #12345: ..." or "Merge pull request #12345
+/// from ...". `None` for anything else, including non-merge commits.
+pub fn pr_number(subject: &str) -> Option<u64> {
+    let rest = subject.strip_prefix("Merge ")?;
+    let (before, after) = rest.split_once('#')?;
+    // The `#` must belong to the PR reference, not a later part of the title
+    if before.contains(':') {
+        return None;
+    }
+    let digits: String = after.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+/// Parse e.g. "3 files changed, 10 insertions(+), 2 deletions(-)". Git omits the
+/// parts that are zero, and prints nothing at all for an empty diff.
+fn parse_shortstat(shortstat: &str) -> DiffStat {
+    let mut stat = DiffStat::default();
+    for part in shortstat.split(',') {
+        let Some((count, what)) = part.trim().split_once(' ') else {
+            continue;
+        };
+        let Ok(count) = count.parse() else {
+            continue;
+        };
+        if what.starts_with("file") {
+            stat.files_changed = count;
+        } else if what.starts_with("insertion") {
+            stat.insertions = count;
+        } else if what.starts_with("deletion") {
+            stat.deletions = count;
+        }
+    }
+    stat
+}
+
+/// First line of `commit`'s message.
+pub fn subject(runner: &dyn Runner, commit: &str) -> Result<String> {
+    git_output(runner, &["log", "-1", "--format=%s", commit])
+}
+
+/// Paths `commit` changes, compared to its first parent for merges.
+pub fn changed_paths(runner: &dyn Runner, commit: &str) -> Result<Vec<String>> {
+    let output = git_output(
+        runner,
+        &[
+            "log",
+            "-1",
+            "-m",
+            "--first-parent",
+            "--name-only",
+            "--format=",
+            commit,
+        ],
+    )?;
+    Ok(output
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// How a commit tested during a bisection behaved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BisectVerdict {
+    Good,
+    Bad,
+    /// It could not be tested, e.g. it failed to build
+    Skip,
+}
+
+/// Start bisecting between `good` and `bad`, checking out the first commit to test.
+pub fn bisect_start(repo_path: &Path, good: &str, bad: &str) -> Result<()> {
+    let runner = &Local::new(repo_path);
+    git_output(runner, &["bisect", "start", bad, good])?;
+    Ok(())
+}
+
+/// Mark the checked out commit, returning the first bad commit once the bisection
+/// has converged on it.
+pub fn bisect_mark(repo_path: &Path, verdict: BisectVerdict) -> Result<Option<String>> {
+    let runner = &Local::new(repo_path);
+    let verdict = match verdict {
+        BisectVerdict::Good => "good",
+        BisectVerdict::Bad => "bad",
+        BisectVerdict::Skip => "skip",
+    };
+    let output = git_output(runner, &["bisect", verdict])?;
+    Ok(output
+        .lines()
+        .next()
+        .and_then(|line| line.strip_suffix(" is the first bad commit"))
+        .map(String::from))
+}
+
+/// End the bisection, returning to the commit checked out before it started.
+pub fn bisect_reset(repo_path: &Path) -> Result<()> {
+    let runner = &Local::new(repo_path);
+    git(runner, &["bisect", "reset"])
+}
+
+/// Run a git command and return its trimmed stdout, failing if it exits unsuccessfully.
+fn git_output(runner: &dyn Runner, args: &[&str]) -> Result<String> {
+    let output = runner
+        .command("git", args)
+        .output()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed with status {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run a git command, failing if it exits unsuccessfully.
+fn git(runner: &dyn Runner, args: &[&str]) -> Result<()> {
+    let status = runner
+        .command("git", args)
+        .status()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "git {} failed with status {}",
+            args.join(" "),
+            status
+        ));
+    }
+    Ok(())
+}
+
+/// The most recent tag reachable from `tip` and the commits after it, oldest first.
+pub fn commits_since_last_release(repo_path: &Path, tip: &str) -> Result<(String, Vec<String>)> {
+    let runner = &Local::new(repo_path);
+    git(runner, &["fetch", "--all", "--tags"])?;
+
+    let tag = git_output(runner, &["describe", "--tags", "--abbrev=0", tip])
+        .with_context(|| format!("Failed to find the latest release tag before {}", tip))?;
+    let range = format!("{}..{}", tag, tip);
+    let commits = git_output(runner, &["rev-list", "--reverse", &range])?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok((tag, commits))
+}
+
+/// `commits`, e.g. stored hashes, newest first in `git rev-list --topo-order`
+/// order, so a commit always comes before its ancestors whichever branch it is on.
+/// Commits `repo_path` doesn't have, e.g. from a deleted branch, follow in their
+/// given order.
+pub fn topo_order(repo_path: &Path, commits: &[&str]) -> Result<Vec<String>> {
+    let (mut ordered, missing) = topo_split(repo_path, commits)?;
+    ordered.extend(missing);
+    Ok(ordered)
+}
+
+/// [`topo_order`], keeping the commits `repo_path` doesn't have apart.
+pub fn topo_split(repo_path: &Path, commits: &[&str]) -> Result<(Vec<String>, Vec<String>)> {
+    if commits.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    let runner = &Local::new(repo_path);
+    let mut args = vec!["rev-list", "--topo-order", "--ignore-missing"];
+    args.extend(commits);
+    let listed = git_output(runner, &args)?;
+
+    let mut ordered = Vec::new();
+    let mut remaining: Vec<&str> = commits.to_vec();
+    for hash in listed.lines() {
+        if remaining.is_empty() {
+            break;
+        }
+        // Stored hashes may be abbreviated
+        remaining.retain(|commit| {
+            let matched = !commit.is_empty() && hash.starts_with(commit);
+            if matched {
+                ordered.push(commit.to_string());
+            }
+            !matched
+        });
+    }
+    let missing = remaining.into_iter().map(str::to_string).collect();
+    Ok((ordered, missing))
+}
+
+/// Stably sort `items` so their commits, given by `commit`, follow [`topo_order`].
+pub fn sort_topo<T>(repo_path: &Path, items: &mut [T], commit: impl Fn(&T) -> &str) -> Result<()> {
+    let mut commits: Vec<&str> = Vec::new();
+    for item in items.iter() {
+        if !commits.contains(&commit(item)) {
+            commits.push(commit(item));
+        }
+    }
+    let order = topo_order(repo_path, &commits)?;
+    items.sort_by_key(|item| order.iter().position(|c| c == commit(item)));
+    Ok(())
+}
+
+/// Resolve a revision to the full SHA of the commit it names in `repo_path`.
+pub fn resolve_commit(repo_path: &Path, rev: &str) -> Result<String> {
+    let runner = &Local::new(repo_path);
+    rev_parse(runner, &format!("{}^{{commit}}", rev))
+}
+
+/// Resolve a revision to a full SHA in the runner's repository.
+fn rev_parse(runner: &dyn Runner, rev: &str) -> Result<String> {
+    let output = runner
+        .command("git", &["rev-parse", "--verify", "--quiet", rev])
+        .output()
+        .with_context(|| format!("Failed to execute git rev-parse {}", rev))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("Unknown revision {}", rev));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+
+use crate::config::Config;
+use crate::runner::Runner;
+
+/// The shell running Bitcoin Core's reproducible release build in the checkout.
+/// Earlier outputs are removed first, as `guix-build` refuses to overwrite them.
+/// `JOBS` is only passed when `build_jobs` is set, so the command, which builds of
+/// a commit are compared by, doesn't change with the machine's core count.
+pub fn build_command(config: &Config) -> String {
+    let mut env = Vec::new();
+    if !config.guix_hosts.is_empty() {
+        env.push(format!("HOSTS='{}'", config.guix_hosts.join(" ")));
+    }
+    if let Some(jobs) = config.build_jobs {
+        env.push(format!("JOBS={}", jobs));
+    }
+    let env = if env.is_empty() {
+        String::new()
+    } else {
+        format!("env {} ", env.join(" "))
+    };
+    format!("rm -Rf guix-build-* && {}./contrib/guix/guix-build", env)
+}
+
+/// SHA-256 of the `SHA256SUMS.part` files the last build wrote for each host, which
+/// changes if any output it lists does.
+pub fn output_hash(runner: &dyn Runner, config: &Config) -> Result<String> {
+    let output = runner
+        .command(
+            &config.shell,
+            &[
+                "-c",
+                "cat guix-build-*/output/*/SHA256SUMS.part | sha256sum",
+            ],
+        )
+        .output()
+        .with_context(|| "Failed to hash the guix build output")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let hash = stdout.split_whitespace().next().unwrap_or_default();
+    // The shell reports the status of sha256sum, which succeeds even if cat found nothing
+    if !output.status.success() || !output.stderr.is_empty() || hash.len() != 64 {
+        anyhow::bail!(
+            "guix-build left no SHA256SUMS.part to hash: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(hash.to_string())
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::compare;
+use crate::config::{Config, Metric};
+use crate::db;
+use crate::git;
+use crate::output::{self, Render, Style};
+
+/// Whether a step made a command slower or faster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Regression,
+    Improvement,
+}
+
+/// A commit whose result for one command moved past the regression threshold
+/// relative to the stored commit before it.
+#[derive(Serialize)]
+pub struct Step {
+    pub change: Change,
+    pub commit: String,
+    pub commit_alias: Option<String>,
+    /// The stored commit before `commit`, which it was compared against
+    pub base: String,
+    pub command: String,
+    /// Change in `metric` relative to the base, in percent
+    pub delta_pct: f64,
+    /// The regression threshold, widened for low confidence
+    pub threshold_pct: f64,
+    pub confidence: f64,
+}
+
+/// The output of `history`.
+#[derive(Serialize)]
+pub struct History {
+    /// Statistic the results were compared by
+    pub metric: Metric,
+    pub threshold_pct: f64,
+    /// How many stored commits the repository has, and were walked
+    pub commits: usize,
+    /// Stored commits the repository doesn't have, which have no place in the order
+    pub unordered: Vec<String>,
+    /// Oldest first
+    pub steps: Vec<Step>,
+}
+
+/// Walk every stored commit oldest first in git topological order, comparing each
+/// against the stored commit before it as `compare` would, and keep the steps that
+/// moved a command's `comparison_metric` past the regression threshold either way.
+pub fn build(conn: &Connection, config: &Config) -> Result<History> {
+    let metric = config.comparison_metric;
+    let threshold_pct = config.regression_threshold_pct;
+    let stored = db::timed_commits(conn)?;
+    let stored: Vec<&str> = stored.iter().map(String::as_str).collect();
+    let (mut commits, unordered) = git::topo_split(&config.repo_path, &stored)?;
+    commits.reverse();
+
+    let mut steps = Vec::new();
+    let mut previous: Option<(String, Vec<db::StoredResult>)> = None;
+    for commit in &commits {
+        let results = db::latest_results(conn, commit)?;
+        if let Some((base, base_results)) = &previous {
+            let report =
+                compare::Report::new(commit, &results, base, base_results, metric, threshold_pct);
+            for comparison in report.comparisons {
+                let change = if comparison.regression {
+                    Change::Regression
+                } else if comparison.delta_pct < -comparison.threshold_pct {
+                    Change::Improvement
+                } else {
+                    continue;
+                };
+                steps.push(Step {
+                    change,
+                    commit: commit.clone(),
+                    commit_alias: report.commit_alias.map(String::from),
+                    base: base.clone(),
+                    command: comparison.command.to_string(),
+                    delta_pct: comparison.delta_pct,
+                    threshold_pct: comparison.threshold_pct,
+                    confidence: comparison.confidence,
+                });
+            }
+        }
+        // Commits whose every run was incomplete have nothing to compare against
+        if !results.is_empty() {
+            previous = Some((commit.clone(), results));
+        }
+    }
+
+    Ok(History {
+        metric,
+        threshold_pct,
+        commits: commits.len(),
+        unordered,
+        steps,
+    })
+}
+
+impl Render for History {
+    fn print_human(&self, style: &Style) {
+        let hash_len = output::hash_length(
+            self.steps
+                .iter()
+                .flat_map(|s| [s.commit.as_str(), s.base.as_str()]),
+            style.hash_len,
+        );
+        for step in &self.steps {
+            let commit = step
+                .commit_alias
+                .as_deref()
+                .unwrap_or_else(|| output::short_hash(&step.commit, hash_len));
+            let what = match step.change {
+                Change::Regression => "Regression introduced at",
+                Change::Improvement => "Improvement at",
+            };
+            println!(
+                "{} {} ({:+.1}%, against {}): {}",
+                what,
+                commit,
+                step.delta_pct,
+                output::short_hash(&step.base, hash_len),
+                step.command
+            );
+        }
+
+        let count = |change| self.steps.iter().filter(|s| s.change == change).count();
+        println!(
+            "{} regressions and {} improvements over {} commits{}",
+            count(Change::Regression),
+            count(Change::Improvement),
+            self.commits,
+            match self.metric {
+                Metric::Mean => String::new(),
+                metric => format!(", by {}", metric.column()),
+            }
+        );
+        if !self.unordered.is_empty() {
+            eprintln!(
+                "Warning: left out {} stored commits the repository doesn't have",
+                self.unordered.len()
+            );
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "change",
+            "commit",
+            "base",
+            "command",
+            "metric",
+            "delta_pct",
+            "threshold_pct",
+            "confidence",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for step in &self.steps {
+            rows.push(vec![
+                match step.change {
+                    Change::Regression => "regression",
+                    Change::Improvement => "improvement",
+                }
+                .to_string(),
+                step.commit.clone(),
+                step.base.clone(),
+                step.command.clone(),
+                self.metric.column().to_string(),
+                step.delta_pct.to_string(),
+                step.threshold_pct.to_string(),
+                step.confidence.to_string(),
+            ]);
+        }
+        rows
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::RunOptions;
+
+/// Run `pre_run_hook`, if configured, before the pipeline. A failing hook aborts
+/// the run.
+pub fn pre_run(config: &Config, commit: &str, options: &RunOptions) -> Result<()> {
+    let Some(hook) = &config.pre_run_hook else {
+        return Ok(());
+    };
+    run_hook(hook, &env(config, commit, options))
+}
+
+/// Run `post_run_hook`, if configured, however the run ended. Failures are only
+/// reported, so they never mask the outcome of the run itself.
+pub fn post_run(config: &Config, commit: &str, options: &RunOptions, outcome: &Result<Vec<i64>>) {
+    let Some(hook) = &config.post_run_hook else {
+        return;
+    };
+
+    let mut env = env(config, commit, options);
+    match outcome {
+        Ok(ids) => {
+            env.push(("BENCH_OUTCOME", "success".to_string()));
+            let ids: Vec<String> = ids.iter().map(i64::to_string).collect();
+            env.push(("BENCH_RESULT_IDS", ids.join(",")));
+        }
+        Err(e) => {
+            env.push(("BENCH_OUTCOME", "failure".to_string()));
+            env.push(("BENCH_ERROR", format!("{:#}", e)));
+        }
+    }
+    if let Err(e) = run_hook(hook, &env) {
+        eprintln!("Warning: {:#}", e);
+    }
+}
+
+/// Run metadata passed to both hooks.
+fn env(config: &Config, commit: &str, options: &RunOptions) -> Vec<(&'static str, String)> {
+    let mut env = vec![
+        ("BENCH_COMMIT", commit.to_string()),
+        ("BENCH_REPO_PATH", config.repo_path.display().to_string()),
+        ("BENCH_DATADIR", config.datadir.display().to_string()),
+        ("BENCH_DB_PATH", config.db_path.display().to_string()),
+        ("BENCH_STOPATHEIGHT", config.stopatheight.to_string()),
+    ];
+    if let Some(storage) = &options.storage {
+        env.push(("BENCH_STORAGE", storage.clone()));
+    }
+    env
+}
+
+fn run_hook(hook: &Path, env: &[(&str, String)]) -> Result<()> {
+    let status = Command::new(hook)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .status()
+        .with_context(|| format!("Failed to execute hook {}", hook.display()))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Hook {} failed with status {}",
+            hook.display(),
+            status
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,338 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::db::{self, RunMetadata};
+
+pub struct ImportSummary {
+    pub files: usize,
+    pub results: usize,
+    pub skipped: usize,
+}
+
+/// Import every hyperfine JSON export found under `dir`, skipping files that fail to
+/// parse or don't record which commit they benchmarked.
+pub fn import_dir(conn: &Connection, dir: &Path) -> Result<ImportSummary> {
+    let mut summary = ImportSummary {
+        files: 0,
+        results: 0,
+        skipped: 0,
+    };
+
+    for path in json_files(dir)? {
+        match import_file(conn, &path) {
+            Ok(count) => {
+                summary.files += 1;
+                summary.results += count;
+            }
+            Err(e) => {
+                eprintln!("Skipping {}: {:#}", path.display(), e);
+                summary.skipped += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn import_file(conn: &Connection, path: &Path) -> Result<usize> {
+    let results = db::read_results_file(path)?;
+
+    let Some(first) = results.results.first() else {
+        return Err(anyhow::anyhow!("File contains no results"));
+    };
+    if let Some(result) = results.results.iter().find(|r| r.parameters.is_none()) {
+        return Err(anyhow::anyhow!(
+            "Result for `{}` has no commit parameter",
+            result.command
+        ));
+    }
+    let commit = first
+        .parameters
+        .as_ref()
+        .expect("checked above")
+        .commit
+        .clone();
+
+    let ids = db::insert_results(conn, &commit, results, &RunMetadata::default())?;
+    Ok(ids.len())
+}
+
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("Failed to read directory {}", dir.display()))?
+                .path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// How a mapped column's text is stored.
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Real,
+    Integer,
+    /// An RFC 3339 timestamp
+    Time,
+}
+
+/// `benchmarks` columns another tool's results can be mapped to. Timings are in
+/// seconds.
+const MAPPABLE: &[(&str, Kind)] = &[
+    ("commit_hash", Kind::Text),
+    ("command", Kind::Text),
+    ("mean", Kind::Real),
+    ("stddev", Kind::Real),
+    ("median", Kind::Real),
+    ("min", Kind::Real),
+    ("max", Kind::Real),
+    ("user", Kind::Real),
+    ("system", Kind::Real),
+    ("created_at", Kind::Time),
+    ("machine", Kind::Text),
+    ("commit_alias", Kind::Text),
+    ("run_label", Kind::Text),
+    ("note", Kind::Text),
+    ("bitcoind_version", Kind::Text),
+    ("build_secs", Kind::Real),
+    ("dbcache", Kind::Integer),
+    ("par", Kind::Integer),
+    ("start_height", Kind::Integer),
+    ("stop_height", Kind::Integer),
+];
+
+/// Columns every imported result needs.
+const REQUIRED: &[&str] = &["commit_hash", "command", "mean"];
+
+pub struct TableSummary {
+    pub results: usize,
+    pub skipped: usize,
+}
+
+/// Parse a `--mapping` spec, `source=target` pairs separated by commas, e.g.
+/// `sha=commit_hash,name=command,avg_secs=mean`, into `(source, target)` pairs.
+pub fn parse_mapping(spec: &str) -> Result<Vec<(String, &'static str)>> {
+    let mut mapping = Vec::new();
+    for pair in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (source, target) = pair
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Mapping `{}` is not source=target", pair))?;
+        let target = MAPPABLE
+            .iter()
+            .map(|(name, _)| *name)
+            .find(|name| *name == target.trim())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Cannot map to `{}`, expected one of {}",
+                    target.trim(),
+                    MAPPABLE
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+        if mapping.iter().any(|(_, mapped)| *mapped == target) {
+            return Err(anyhow::anyhow!("`{}` is mapped more than once", target));
+        }
+        mapping.push((source.trim().to_string(), target));
+    }
+    let missing: Vec<&str> = REQUIRED
+        .iter()
+        .copied()
+        .filter(|required| !mapping.iter().any(|(_, target)| target == required))
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The mapping must map a column to each of {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(mapping)
+}
+
+/// Import the rows of `path`, a CSV file with a header row or, with a `.jsonl`
+/// extension, JSON Lines of objects, from another tool. Each row is read and
+/// stored as it comes, with its columns renamed by `mapping`. Rows that don't
+/// parse, lack a required value or have values of the wrong type are skipped.
+/// Either every other row is stored or, on error, none are.
+pub fn import_table(
+    conn: &Connection,
+    path: &Path,
+    mapping: &[(String, &'static str)],
+) -> Result<TableSummary> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let rows: Box<dyn Iterator<Item = Result<Row>>> =
+        if path.extension().is_some_and(|ext| ext == "jsonl") {
+            Box::new(json_lines(BufReader::new(file)))
+        } else {
+            Box::new(csv_rows(BufReader::new(file))?)
+        };
+    // Rolled back when dropped without committing
+    let tx = conn
+        .unchecked_transaction()
+        .with_context(|| "Failed to start transaction")?;
+    let mut summary = TableSummary {
+        results: 0,
+        skipped: 0,
+    };
+    for (i, row) in rows.enumerate() {
+        match row.and_then(|row| map_row(&row, mapping)) {
+            Ok(fields) => {
+                db::insert_mapped(&tx, &fields)?;
+                summary.results += 1;
+            }
+            Err(e) => {
+                eprintln!("Skipping row {}: {:#}", i + 1, e);
+                summary.skipped += 1;
+            }
+        }
+    }
+    tx.commit()
+        .with_context(|| "Failed to commit imported results")?;
+    Ok(summary)
+}
+
+/// A row of another tool's results, as source column names and their values.
+type Row = Vec<(String, String)>;
+
+/// `row` as `benchmarks` columns and the values to store.
+fn map_row(row: &Row, mapping: &[(String, &'static str)]) -> Result<Vec<(&'static str, Value)>> {
+    let mut fields = Vec::new();
+    for (source, target) in mapping {
+        let text = row
+            .iter()
+            .find(|(name, _)| name == source)
+            .map(|(_, value)| value.trim())
+            .filter(|value| !value.is_empty());
+        let Some(text) = text else {
+            if REQUIRED.contains(target) {
+                return Err(anyhow::anyhow!("No {} for {}", source, target));
+            }
+            continue;
+        };
+        let kind = MAPPABLE
+            .iter()
+            .find_map(|(name, kind)| (name == target).then_some(*kind))
+            .expect("targets are checked when parsing the mapping");
+        let value = match kind {
+            Kind::Text => Value::Text(text.to_string()),
+            Kind::Real => Value::Real(
+                text.parse()
+                    .with_context(|| format!("{} `{}` is not a number", source, text))?,
+            ),
+            Kind::Integer => Value::Integer(
+                text.parse()
+                    .with_context(|| format!("{} `{}` is not an integer", source, text))?,
+            ),
+            Kind::Time => Value::Text(
+                DateTime::parse_from_rfc3339(text)
+                    .with_context(|| format!("{} `{}` is not an RFC 3339 time", source, text))?
+                    .to_rfc3339(),
+            ),
+        };
+        fields.push((*target, value));
+    }
+    Ok(fields)
+}
+
+/// The rows of a CSV file, named by its header row. Quoted fields may contain
+/// commas, doubled quotes and line breaks.
+fn csv_rows(mut reader: impl BufRead) -> Result<impl Iterator<Item = Result<Row>>> {
+    let header = csv_record(&mut reader)?.ok_or_else(|| anyhow::anyhow!("File is empty"))?;
+    Ok(std::iter::from_fn(move || {
+        let record = csv_record(&mut reader).transpose()?;
+        Some(record.map(|fields| header.iter().cloned().zip(fields).collect()))
+    }))
+}
+
+/// The fields of the next record of a CSV file, `None` at its end. Blank lines are
+/// skipped.
+fn csv_record(reader: &mut impl BufRead) -> Result<Option<Vec<String>>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader
+            .read_line(&mut line)
+            .with_context(|| "Failed to read CSV")?
+            == 0
+        {
+            if quoted {
+                return Err(anyhow::anyhow!("CSV ends inside a quoted field"));
+            }
+            return Ok(None);
+        }
+        if !quoted && fields.is_empty() && line.trim_end_matches(['\r', '\n']).is_empty() {
+            continue;
+        }
+        // Line breaks are only kept inside quotes
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                ('"', true) => quoted = false,
+                ('"', false) if field.is_empty() => quoted = true,
+                (',', false) => fields.push(std::mem::take(&mut field)),
+                ('\r' | '\n', false) => {}
+                (c, _) => field.push(c),
+            }
+        }
+        if !quoted {
+            fields.push(field);
+            return Ok(Some(fields));
+        }
+    }
+}
+
+/// The rows of a JSON Lines file, one object per line. Strings are taken as they
+/// are and other values as their JSON, with `null` treated as missing.
+fn json_lines(reader: impl BufRead) -> impl Iterator<Item = Result<Row>> {
+    reader
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|line| {
+            let line = line.with_context(|| "Failed to read JSON Lines")?;
+            let serde_json::Value::Object(object) = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid JSON line `{}`", line))?
+            else {
+                return Err(anyhow::anyhow!("JSON line `{}` is not an object", line));
+            };
+            Ok(object
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(name, value)| match value {
+                    serde_json::Value::String(text) => (name, text),
+                    value => (name, value.to_string()),
+                })
+                .collect())
+        })
+}
@@ -0,0 +1,171 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::StoredResult;
+use crate::exit_code;
+use crate::output::{self, Format, Render, Style};
+
+/// The output of `list`.
+#[derive(Serialize)]
+struct Listing<'a> {
+    /// Newest first, in git topological order where the repository has the commits
+    results: &'a [StoredResult],
+    #[serde(skip)]
+    pr_url: &'a str,
+}
+
+/// Print `results` in `format`, with tables following `style` and pull requests
+/// linked under `pr_url`.
+pub fn print_list(
+    results: &[StoredResult],
+    format: Format,
+    style: &Style,
+    pr_url: &str,
+) -> Result<()> {
+    output::render(&Listing { results, pr_url }, format, style)
+}
+
+/// The stored status, tagged with whether the result is the baseline and whether
+/// it is suspect for the CPU having throttled.
+pub fn status_label(result: &StoredResult) -> String {
+    let mut label = result.status.clone().unwrap_or_else(|| "ok".to_string());
+    if result.is_baseline {
+        label.push_str(",baseline");
+    }
+    if result.throttled == Some(true) {
+        label.push_str(",throttled");
+    }
+    label
+}
+
+impl Render for Listing<'_> {
+    fn print_human(&self, style: &Style) {
+        let results = self.results;
+        if results.is_empty() {
+            println!("No results stored");
+            return;
+        }
+
+        let hash_len = output::hash_length(
+            results.iter().map(|r| r.commit_hash.as_str()),
+            style.hash_len,
+        );
+        let unit = style.unit_for(results.iter().filter_map(|r| r.mean));
+        let timing = |secs: Option<f64>| secs.map_or("-".to_string(), |s| style.number(s, unit));
+        let label = |result: &StoredResult| {
+            let hash = output::short_hash(&result.commit_hash, hash_len);
+            result
+                .commit_alias
+                .clone()
+                .unwrap_or_else(|| hash.to_string())
+        };
+        let width = results
+            .iter()
+            .map(|r| label(r).len())
+            .max()
+            .unwrap_or(0)
+            .max("commit".len());
+        println!(
+            "{:>6}  {:<width$}  {:>12}  {:>10}  {:>10}  {:>10}  {:>12}  {:<12}  command",
+            "id",
+            "commit",
+            format!("mean ({})", output::suffix(unit)),
+            "stddev",
+            "p90",
+            "p99",
+            "blocks/s",
+            "status"
+        );
+        for result in results {
+            println!(
+                "{:>6}  {:<width$}  {:>12}  {:>10}  {:>10}  {:>10}  {:>12}  {:<12}  {}",
+                result.id,
+                label(result),
+                timing(result.mean),
+                timing(result.stddev),
+                timing(result.p90),
+                timing(result.p99),
+                result
+                    .blocks_per_sec
+                    .map_or("-".to_string(), |b| format!("{:.1}", b)),
+                status_label(result),
+                result.command
+            );
+            if let Some(label) = &result.run_label {
+                println!("{:>6}  label: {}", "", label);
+            }
+            if let (Some(reached), Some(stop)) = (result.reached_height, result.stop_height) {
+                if reached < stop {
+                    println!("{:>6}  height: reached {} of {}", "", reached, stop);
+                }
+            }
+            if let Some(trace) = &result.rr_trace {
+                println!("{:>6}  rr trace: {}", "", trace);
+            }
+            if let Some(hash) = &result.guix_output_hash {
+                println!("{:>6}  guix output: {}", "", hash);
+            }
+            for assertion in &result.failed_assertions {
+                println!("{:>6}  failed assertion: {}", "", assertion);
+            }
+            if let Some(pr) = result.pr_number {
+                println!("{:>6}  pr: {}{}", "", self.pr_url, pr);
+            }
+            if let Some(note) = &result.note {
+                println!("{:>6}  note: {}", "", note);
+            }
+            let mut reasons: Vec<String> = result
+                .exit_codes
+                .iter()
+                .filter_map(|&code| exit_code::describe(code))
+                .collect();
+            reasons.dedup();
+            for reason in reasons {
+                println!("{:>6}  exit: {}", "", reason);
+            }
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "id",
+            "commit_hash",
+            "commit_alias",
+            "command",
+            "mean",
+            "stddev",
+            "p50",
+            "p90",
+            "p99",
+            "blocks_per_sec",
+            "status",
+            "is_baseline",
+            "throttled",
+            "pr_number",
+            "run_label",
+            "note",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for r in self.results {
+            rows.push(vec![
+                r.id.to_string(),
+                r.commit_hash.clone(),
+                output::csv_value(r.commit_alias.as_deref()),
+                r.command.clone(),
+                output::csv_value(r.mean),
+                output::csv_value(r.stddev),
+                output::csv_value(r.p50),
+                output::csv_value(r.p90),
+                output::csv_value(r.p99),
+                output::csv_value(r.blocks_per_sec),
+                output::csv_value(r.status.as_deref()),
+                r.is_baseline.to_string(),
+                output::csv_value(r.throttled),
+                output::csv_value(r.pr_number),
+                output::csv_value(r.run_label.as_deref()),
+                output::csv_value(r.note.as_deref()),
+            ]);
+        }
+        rows
+    }
+}
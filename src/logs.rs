@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::db;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// What a pass over `log_dir` did.
+#[derive(Debug, Default)]
+pub struct Rotation {
+    pub compressed: usize,
+    pub deleted: usize,
+}
+
+/// Gzip kept logs and command output older than `log_compress_after_days` and delete
+/// those older than `log_retention_days`, updating the results that point at them.
+pub fn rotate(config: &Config, conn: &Connection) -> Result<Rotation> {
+    let mut rotation = Rotation::default();
+    let Some(log_dir) = &config.log_dir else {
+        return Ok(rotation);
+    };
+    let entries = match fs::read_dir(log_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(rotation),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", log_dir.display()));
+        }
+    };
+
+    let now = SystemTime::now();
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("Failed to read {}", log_dir.display()))?
+            .path();
+        let name = path.to_string_lossy().into_owned();
+        let (kept, compressed) = match name.strip_suffix(".gz") {
+            Some(kept) => (kept, true),
+            None => (name.as_str(), false),
+        };
+        let set_path = if kept.ends_with(".log") {
+            db::set_log_path
+        } else if kept.ends_with(".output") {
+            db::set_output_path
+        } else {
+            continue;
+        };
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to read the age of {}", path.display()))?;
+        let age_days = now.duration_since(modified).unwrap_or_default().as_secs() / DAY.as_secs();
+
+        if age_days >= config.log_retention_days {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete {}", path.display()))?;
+            set_path(conn, &path, None)?;
+            rotation.deleted += 1;
+        } else if !compressed && age_days >= config.log_compress_after_days {
+            gzip(&path)?;
+            let gzipped = PathBuf::from(format!("{}.gz", name));
+            set_path(conn, &path, Some(&gzipped))?;
+            rotation.compressed += 1;
+        }
+    }
+    Ok(rotation)
+}
+
+/// Replace `path` with `path.gz`, keeping its modification time.
+fn gzip(path: &Path) -> Result<()> {
+    let status = Command::new("gzip")
+        .arg("-f")
+        .arg(path)
+        .status()
+        .with_context(|| "Failed to run gzip")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "gzip of {} failed with {}",
+            path.display(),
+            status
+        ));
+    }
+    Ok(())
+}
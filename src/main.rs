@@ -1,31 +1,508 @@
+mod ab;
+mod aggregate;
+mod aliases;
+mod assertions;
+mod bitcoind;
+mod checkpoints;
+mod commit_filter;
+mod compare;
+mod compiler_cache;
+mod config;
+mod db;
+mod error;
+mod exit_code;
+mod export;
+mod git;
+mod guix;
+mod history;
+mod hooks;
+mod import;
+mod list;
+mod logs;
+mod matrix;
+mod node;
+mod notify;
+mod output;
+mod paths;
+mod process;
+mod progress;
+mod queue;
+mod regression;
+mod report;
+mod runner;
+mod seed;
+mod show_config;
+mod site;
+mod stability;
+mod sweep;
+mod system;
+mod thermal;
+mod toolchain;
+mod tui;
+mod verify;
+
 use anyhow::{Context, Result};
 use chrono::Utc;
-use clap::{Parser, Subcommand};
-use cron::Schedule;
-use rusqlite::{params, Connection};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use compiler_cache::CompilerCache;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 use std::fs;
-use std::process::Command;
-use std::str::FromStr;
+use std::io::{self, IsTerminal, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
 use tokio::time::sleep;
+use uuid::Uuid;
+
+use aliases::Aliases;
+use commit_filter::CommitFilter;
+use compare::Base;
+use config::{Benchmark, Config, LoadPolicy, ScheduleEntry};
+use db::RunMetadata;
+use error::BenchError;
+use output::Format;
+use runner::Runner;
+use toolchain::Toolchain;
 
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Cli {
+    /// Path to a TOML configuration file
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Override a configuration value, e.g. `--set build_jobs=4`
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    overrides: Vec<String>,
+
+    /// How read commands print their output. JSON formats also report errors as JSON.
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    format: Format,
+
+    /// Shorthand for `--format json`
+    #[arg(long, global = true, conflicts_with = "format")]
+    json: bool,
+
+    /// Shorthand for `--format json-pretty`
+    #[arg(long, global = true, conflicts_with_all = ["format", "json"])]
+    json_pretty: bool,
+
+    /// Show full commit hashes instead of truncating them to `hash_length`
+    #[arg(long, global = true)]
+    full_hash: bool,
+
+    /// TOML file naming commits, e.g. `pre-optimization = "4f1e2d3"`, so they can be
+    /// benchmarked by name and are shown by it
+    #[arg(long, global = true)]
+    commit_file: Option<PathBuf>,
+
     #[command(subcommand)]
-    command: Option<Commands>,
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Configured(Commands),
+    #[command(flatten)]
+    Setup(SetupCommands),
 }
 
+// Commands that only read the configuration, if that, and need none of the
+// directories it names.
+#[derive(Subcommand)]
+enum SetupCommands {
+    /// Print the configuration in effect once the config file, `${VAR}` references
+    /// and `--set` overrides are applied, noting where each value came from. Reads
+    /// no database and runs nothing.
+    ShowConfig,
+    /// Print the absolute paths the configuration in effect resolves to, e.g. of
+    /// the database and datadir, for wrapper scripts with `--format json`. Reads no
+    /// database and runs nothing.
+    Paths,
+    /// Print a completion script for `shell`, e.g. `bitcoin_benchmark completions bash
+    /// > ~/.local/share/bash-completion/completions/bitcoin_benchmark`
+    Completions { shell: clap_complete::Shell },
+}
+
+// Commands that run with the configuration loaded and its directories created.
 #[derive(Subcommand)]
 enum Commands {
     /// Run the application as a daemon
-    Daemon,
+    Daemon {
+        /// Seconds to sleep after each run, overriding `cooldown_secs`
+        #[arg(long)]
+        cooldown: Option<u64>,
+        /// Stop scheduling runs and exit after this long, e.g. `8h` or `1h30m`.
+        /// A run already in progress is allowed to finish.
+        #[arg(long, value_parser = parse_duration)]
+        max_runtime: Option<Duration>,
+        /// Benchmark every commit since the latest release tag that has no stored
+        /// results yet, instead of only the tip
+        #[arg(long)]
+        since_last_release: bool,
+    },
+    /// Queue a commit for the daemon, which benchmarks queued commits, its scheduled
+    /// jobs' among them, one at a time in priority order
+    Enqueue {
+        /// The commit to benchmark, resolved now
+        commit: String,
+        /// What to benchmark, `benchmark` when unset
+        #[arg(long, value_enum)]
+        scenario: Option<Benchmark>,
+        /// Higher priorities are benchmarked first
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        priority: i64,
+    },
     /// Run benchmark for a specific commit
     Run {
         /// The commit hash to benchmark
         #[arg(short, long)]
         commit: String,
+        /// Only run the given pipeline phases, e.g. `--only build,bench`
+        #[arg(long, value_enum, value_delimiter = ',')]
+        only: Option<Vec<Phase>>,
+        /// Path of the hyperfine results file to export to and import from
+        #[arg(long)]
+        results_file: Option<PathBuf>,
+        /// Benchmark on the datadir of this configured storage backend
+        #[arg(long)]
+        storage: Option<String>,
+        /// Fetch the commit from this repository, e.g. a contributor's fork
+        #[arg(long)]
+        repo_url: Option<String>,
+        /// Skip `git fetch` and check out the commit from the local repository
+        #[arg(long, conflicts_with = "repo_url")]
+        no_fetch: bool,
+        /// Show the height bitcoind has synced to while the benchmark runs
+        #[arg(long)]
+        progress: bool,
+        /// Rerun flaky benchmarks up to this many times, overriding `flaky_retries`
+        #[arg(long)]
+        flaky_retries: Option<u32>,
+        /// Sweep a hyperfine parameter used as `{name}` in `extra_args`. Repeatable;
+        /// every combination is benchmarked. `par`, e.g. `--param par=1,2,4`, is
+        /// passed to bitcoind as `-par` without needing `extra_args`.
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, String)>,
+        /// Continue syncing the datadir the previous appending run left, by
+        /// `append_chunk_blocks` more blocks, instead of syncing from scratch
+        #[arg(long)]
+        append_to_existing: bool,
+        /// Leave the datadir in place after the run, overriding `keep_datadir`
+        #[arg(long)]
+        keep_datadir: bool,
+        /// Print the results in the chosen `--format` instead of storing them,
+        /// leaving the database untouched
+        #[arg(long)]
+        no_db: bool,
+        /// Describe what this run is for, e.g. "testing new allocator build". Stored
+        /// with its results and shown by `list`.
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Check out and build a commit without benchmarking it, storing whether it
+    /// built and how long that took
+    Build {
+        /// The commit hash to build
+        #[arg(short, long)]
+        commit: String,
+        /// Fetch the commit from this repository, e.g. a contributor's fork
+        #[arg(long)]
+        repo_url: Option<String>,
+        /// Skip `git fetch` and check out the commit from the local repository
+        #[arg(long, conflicts_with = "repo_url")]
+        no_fetch: bool,
+    },
+    /// Find the first commit between `--good` and `--bad` whose benchmark mean exceeds
+    /// `--threshold-secs`, with `git bisect`. Commits with a stored result for the
+    /// configured benchmark are not benchmarked again.
+    Bisect {
+        /// A commit known to be fast enough
+        #[arg(long)]
+        good: String,
+        /// A commit known to be too slow
+        #[arg(long)]
+        bad: String,
+        /// Means above this many seconds mark a commit as bad
+        #[arg(long)]
+        threshold_secs: f64,
+    },
+    /// Benchmark a commit at each of several `-dbcache` values, smallest first,
+    /// recording which runs bitcoind was killed in for running out of memory, and
+    /// show the smallest value that fits and how the mean changes above it
+    DbcacheSweep {
+        /// The commit to benchmark
+        #[arg(short, long)]
+        commit: String,
+        /// `-dbcache` values to benchmark, in megabytes, e.g. `300,450,1000`
+        #[arg(long, value_delimiter = ',', required = true)]
+        values: Vec<u64>,
+        /// Skip `git fetch` and check out the commit from the local repository
+        #[arg(long)]
+        no_fetch: bool,
+    },
+    /// Build two commits and time both in one hyperfine invocation, each syncing
+    /// into its own datadir, for results that compare directly. Both are stored with
+    /// a shared `ab_group` id.
+    Ab {
+        /// The commit compared against
+        a: String,
+        /// The commit compared
+        b: String,
+        /// Timed runs of each commit, alternating as hyperfine does
+        #[arg(long, default_value_t = 1)]
+        runs: usize,
+        /// Skip `git fetch` and check out the commits from the local repository
+        #[arg(long)]
+        no_fetch: bool,
+    },
+    /// Check out, build and sync a commit once under `rr record`, untimed as
+    /// recording slows bitcoind down, keeping the trace in `rr_trace_dir` for
+    /// `rr replay`, e.g. to debug a crash at a known height
+    Record {
+        /// The commit to record
+        #[arg(short, long)]
+        commit: String,
+        /// Skip `git fetch` and check out the commit from the local repository
+        #[arg(long)]
+        no_fetch: bool,
+    },
+    /// Check the whole pipeline works on this machine by fetching, building and
+    /// benchmarking `bitcoind --version` into a temporary database
+    Selftest {
+        /// The commit to build
+        #[arg(short, long, default_value = "HEAD")]
+        commit: String,
+    },
+    /// Benchmark each commit read from stdin, one per line, e.g. from `git rev-list`.
+    /// Blank lines and `#` comments are ignored.
+    RunBatch {
+        /// Stop at the first commit that fails instead of continuing with the rest
+        #[arg(long)]
+        fail_fast: bool,
+        /// Benchmark commits again even if they already have stored results
+        #[arg(long)]
+        rerun: bool,
+        /// Benchmark every commit this many times, alternating between them and
+        /// reversing the order every other round (A B, B A, A B, ...), so drift
+        /// over the batch biases none of them. Implies `--rerun`.
+        #[arg(long, value_name = "ROUNDS", value_parser = clap::value_parser!(u32).range(1..))]
+        interleave: Option<u32>,
+        /// Only benchmark commits whose subject matches this regex, overriding
+        /// `commit_subject_filter`. The rest are recorded as filtered out.
+        #[arg(long, value_name = "REGEX")]
+        commit_subject_filter: Option<String>,
+        /// Only benchmark commits changing a path that matches this regex,
+        /// overriding `commit_path_filter`
+        #[arg(long, value_name = "REGEX")]
+        commit_path_filter: Option<String>,
+    },
+    /// Compare the stored results of a commit against another commit
+    Compare {
+        /// The commit to compare
+        commit: String,
+        /// The commit to compare against, defaults to the commit's parent
+        #[arg(long, conflicts_with_all = ["baseline_file", "compare_commands"])]
+        base: Option<String>,
+        /// A hyperfine results.json export to compare against instead of stored results
+        #[arg(long, conflicts_with = "compare_commands")]
+        baseline_file: Option<PathBuf>,
+        /// Compare two commands benchmarked at the commit instead of two commits,
+        /// the second against the first, e.g. to tune bitcoind flags. Each is
+        /// matched exactly, or by being the only stored command containing it.
+        #[arg(long, num_args = 2, value_names = ["BASE", "COMMAND"])]
+        compare_commands: Option<Vec<String>>,
+        /// Also compare IBD runs that stopped short of `stopatheight`
+        #[arg(long)]
+        include_incomplete: bool,
+        /// Pool every stored run of each command into one mean and standard
+        /// deviation, instead of comparing the latest runs
+        #[arg(long)]
+        aggregate: bool,
+    },
+    /// Show the pairwise change in mean between several commits
+    Matrix {
+        /// The commits to compare with each other
+        #[arg(required = true, num_args = 2..)]
+        commits: Vec<String>,
+    },
+    /// List the most recently stored results
+    List {
+        /// Maximum number of results to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Only show results of runs whose `--label` contains this text
+        #[arg(long)]
+        label: Option<String>,
+        /// Pool the stored runs of each commit and command into one mean and
+        /// standard deviation, with how many runs they combine
+        #[arg(long)]
+        aggregate: bool,
+    },
+    /// Walk every stored commit oldest first in git order, comparing each against
+    /// the stored commit before it, and list where commands regressed or improved
+    /// past the regression threshold
+    History,
+    /// Summarize the most recently benchmarked commits as Markdown, for sharing in
+    /// issues and PRs
+    Report {
+        /// Number of commits to summarize
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Write the Markdown to this file instead of printing it
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Show how much each command's mean varies between runs, between repeated
+    /// runs of the same commit and across recent runs, to tell which are too noisy
+    /// to gate on
+    Stability {
+        /// Number of most recent runs of each command to measure recent variation over
+        #[arg(long, default_value_t = 20)]
+        recent: usize,
+    },
+    /// Attach a note to a stored result, e.g. to explain an anomaly
+    Annotate {
+        /// Id of the result, as shown by `list`
+        id: i64,
+        /// The note to store, replacing any existing one
+        note: String,
+    },
+    /// Make a result the baseline that regressions are detected against
+    Promote {
+        /// Id of the result, as shown by `list`
+        id: i64,
     },
+    /// Check that the stored JSON columns of every result still parse
+    Verify {
+        /// Set unparseable columns to NULL
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Browse stored results interactively, comparing runs and charting each
+    /// command's mean over time. Requires building with `--features tui`.
+    Tui {
+        /// Number of most recent results to load
+        #[arg(short = 'n', long, default_value_t = 1000)]
+        limit: usize,
+    },
+    /// Export every stored result to a Parquet file, for DuckDB, pandas and the like
+    Export {
+        /// Path of the Parquet file to write
+        path: PathBuf,
+    },
+    /// Copy the results database to a file, safely even while the daemon is running
+    Backup {
+        /// Where to write the copy
+        out: PathBuf,
+    },
+    /// Replace the results database with a backup
+    Restore {
+        /// Backup to restore from
+        path: PathBuf,
+        /// Don't ask for confirmation before replacing the database
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Import a directory of historical hyperfine results.json exports, or with
+    /// `--mapping` a file of results from another tool
+    Import {
+        /// Directory to search for JSON files, or with `--mapping` a CSV file with a
+        /// header row or a `.jsonl` file of JSON objects
+        path: PathBuf,
+        /// Columns of the file to store as which result fields, as `source=target`
+        /// pairs separated by commas, e.g. `sha=commit_hash,name=command,secs=mean`.
+        /// commit_hash, command and mean must be mapped.
+        #[arg(long)]
+        mapping: Option<String>,
+    },
+    /// Merge the results databases of other runners into this one
+    MergeDb {
+        /// Results databases to merge, e.g. copied from each runner. Results that
+        /// don't record their machine are attributed to the file's name.
+        #[arg(required = true)]
+        sources: Vec<PathBuf>,
+    },
+}
+
+/// A stage of the benchmark pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Phase {
+    /// Fetch the repository and check out the commit
+    Fetch,
+    /// Build bitcoind from the current checkout
+    Build,
+    /// Run hyperfine against the built binary
+    Bench,
+    /// Import the hyperfine results into the database
+    Save,
+}
+
+/// Options controlling a single benchmark run.
+#[derive(Clone)]
+struct RunOptions {
+    phases: Vec<Phase>,
+    results_file: Option<PathBuf>,
+    /// Name of the storage backend the datadir lives on, if one was selected
+    storage: Option<String>,
+    /// Repository to fetch the commit from instead of the checkout's own remotes
+    repo_url: Option<String>,
+    /// Fetch before checking out the commit
+    fetch: bool,
+    /// Friendly name the commit was given in the `--commit-file`
+    commit_alias: Option<String>,
+    /// What the run is for, as given with `--label`
+    label: Option<String>,
+    /// Report sync progress on stderr during the benchmark
+    progress: bool,
+    /// Extra hyperfine parameter lists, as `(name, comma-separated values)`
+    params: Vec<(String, String)>,
+    /// Sync the next chunk of blocks onto the previous appending run's datadir
+    append: bool,
+    /// Id shared by the runs of one `run-batch`, and this run's position in it
+    batch: Option<(String, usize)>,
+    /// Print the results like this in the save phase instead of storing them
+    print_results: Option<(Format, output::Style)>,
+    /// Log how each stored result compares against the previous one, as the daemon does
+    preview: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            phases: Phase::value_variants().to_vec(),
+            results_file: None,
+            storage: None,
+            repo_url: None,
+            fetch: true,
+            commit_alias: None,
+            label: None,
+            progress: false,
+            params: Vec::new(),
+            append: false,
+            batch: None,
+            print_results: None,
+            preview: false,
+        }
+    }
+}
+
+impl RunOptions {
+    fn runs(&self, phase: Phase) -> bool {
+        self.phases.contains(&phase)
+    }
+
+    /// Whether the run writes to the database.
+    fn stores(&self) -> bool {
+        self.runs(Phase::Save) && self.print_results.is_none()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,200 +511,3391 @@ struct HyperfineResults {
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(from = "RawBenchmarkResult")]
 struct BenchmarkResult {
     command: String,
     mean: f64,
-    #[serde(default)]
     stddev: Option<f64>,
     median: f64,
-    user: f64,
-    system: f64,
+    user: Option<f64>,
+    system: Option<f64>,
     min: f64,
     max: f64,
     times: Vec<f64>,
-    exit_codes: Vec<i32>,
+    /// `None` where the command was killed by a signal
+    exit_codes: Vec<Option<i32>>,
+    parameters: Option<Parameters>,
+    /// Fields the export lacked, which were filled in with fallbacks
+    #[serde(skip)]
+    missing_fields: Vec<&'static str>,
+}
+
+/// A result as exported by any hyperfine version. Older versions omit some fields.
+#[derive(Deserialize)]
+struct RawBenchmarkResult {
+    command: String,
+    mean: f64,
+    stddev: Option<f64>,
+    median: Option<f64>,
+    user: Option<f64>,
+    system: Option<f64>,
+    min: Option<f64>,
+    max: Option<f64>,
+    times: Option<Vec<f64>>,
+    exit_codes: Option<Vec<Option<i32>>>,
     parameters: Option<Parameters>,
 }
 
+impl From<RawBenchmarkResult> for BenchmarkResult {
+    fn from(raw: RawBenchmarkResult) -> Self {
+        let mut missing_fields = Vec::new();
+        let mut note_missing = |present: bool, field| {
+            if !present {
+                missing_fields.push(field);
+            }
+        };
+        note_missing(raw.median.is_some(), "median");
+        note_missing(raw.user.is_some(), "user");
+        note_missing(raw.system.is_some(), "system");
+        note_missing(raw.min.is_some(), "min");
+        note_missing(raw.max.is_some(), "max");
+        note_missing(raw.times.is_some(), "times");
+        note_missing(raw.exit_codes.is_some(), "exit_codes");
+
+        let times = raw.times.unwrap_or_default();
+        // Without individual times the mean is the best estimate of every statistic
+        let min = raw
+            .min
+            .or_else(|| times.iter().copied().reduce(f64::min))
+            .unwrap_or(raw.mean);
+        let max = raw
+            .max
+            .or_else(|| times.iter().copied().reduce(f64::max))
+            .unwrap_or(raw.mean);
+
+        BenchmarkResult {
+            command: raw.command,
+            mean: raw.mean,
+            stddev: raw.stddev,
+            median: raw.median.unwrap_or(raw.mean),
+            user: raw.user,
+            system: raw.system,
+            min,
+            max,
+            times,
+            exit_codes: raw.exit_codes.unwrap_or_default(),
+            parameters: raw.parameters,
+            missing_fields,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct Parameters {
     commit: String,
+    /// Values of any `--param` lists swept alongside the commit
+    #[serde(flatten)]
+    extra: BTreeMap<String, String>,
+}
+
+impl output::Render for HyperfineResults {
+    fn print_human(&self, style: &output::Style) {
+        let unit = style.unit_for(self.results.iter().map(|r| r.mean));
+        let duration = |secs: f64| style.duration_in(secs, unit);
+        for result in &self.results {
+            println!("{}", result.command);
+            let mean = match result.stddev {
+                Some(stddev) => format!("{} ± {}", duration(result.mean), duration(stddev)),
+                None => duration(result.mean),
+            };
+            println!(
+                "  mean {}  median {}  range {} … {}",
+                mean,
+                duration(result.median),
+                duration(result.min),
+                duration(result.max)
+            );
+            let mut reasons: Vec<String> = result
+                .exit_codes
+                .iter()
+                .filter_map(|&code| exit_code::describe(code))
+                .collect();
+            reasons.dedup();
+            for reason in reasons {
+                println!("  exit: {}", reason);
+            }
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "command", "mean", "stddev", "median", "user", "system", "min", "max",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for r in &self.results {
+            rows.push(vec![
+                r.command.clone(),
+                r.mean.to_string(),
+                output::csv_value(r.stddev),
+                r.median.to_string(),
+                output::csv_value(r.user),
+                output::csv_value(r.system),
+                r.min.to_string(),
+                r.max.to_string(),
+            ]);
+        }
+        rows
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    let format = if cli.json_pretty {
+        Format::JsonPretty
+    } else if cli.json {
+        Format::Json
+    } else {
+        cli.format
+    };
 
-    match &cli.command {
-        Some(Commands::Daemon) => {
-            start_daemon().await?;
-        }
-        Some(Commands::Run { commit }) => {
-            run_benchmark(commit.to_string()).await?;
+    match run_command(&cli, format).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            output::print_error(&e, format);
+            ExitCode::FAILURE
         }
+    }
+}
+
+async fn run_command(cli: &Cli, format: Format) -> Result<()> {
+    let command = match &cli.command {
+        Some(Command::Configured(command)) => command,
+        Some(Command::Setup(command)) => return run_setup_command(cli, command, format),
         None => {
             println!("Please specify a command. Use --help for more information.");
+            return Ok(());
+        }
+    };
+    load_dotenv()?;
+    let mut config = Config::load(cli.config.as_deref(), &cli.overrides)?;
+    config.create_dirs()?;
+    let style = output::Style::new(&config, cli.full_hash);
+
+    match command {
+        Commands::Daemon {
+            cooldown,
+            max_runtime,
+            since_last_release,
+        } => {
+            if let Some(cooldown) = cooldown {
+                config.cooldown_secs = *cooldown;
+            }
+            start_daemon(&config, *max_runtime, *since_last_release).await?;
+        }
+        Commands::Run {
+            commit,
+            only,
+            results_file,
+            storage,
+            repo_url,
+            no_fetch,
+            progress,
+            flaky_retries,
+            params,
+            append_to_existing,
+            keep_datadir,
+            no_db,
+            label,
+        } => {
+            if let Some(storage) = storage {
+                config.select_storage(storage)?;
+            }
+            if let Some(flaky_retries) = flaky_retries {
+                config.flaky_retries = *flaky_retries;
+            }
+            config.keep_datadir |= keep_datadir;
+            if let Some((_, values)) = params.iter().find(|(name, _)| name == "par") {
+                for value in values.split(',') {
+                    let par = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("par value `{}` is not a number", value))?;
+                    config::validate_par(par)?;
+                }
+                // The swept value replaces the configured one
+                config.par = None;
+                if !config.extra_args.iter().any(|arg| arg.contains("{par}")) {
+                    config.extra_args.push("-par={par}".to_string());
+                }
+            }
+            let (commit, commit_alias) = match &cli.commit_file {
+                Some(path) => Aliases::load(path)?.resolve(commit),
+                None => (commit.clone(), None),
+            };
+            let mut options = RunOptions {
+                commit_alias,
+                label: label.clone(),
+                results_file: results_file.clone(),
+                storage: storage.clone(),
+                repo_url: repo_url.clone(),
+                fetch: !no_fetch,
+                progress: *progress,
+                params: params.clone(),
+                append: *append_to_existing,
+                print_results: no_db.then_some((format, style)),
+                ..Default::default()
+            };
+            if let Some(only) = only {
+                options.phases = only.clone();
+            }
+            run_benchmark(commit, &config, &options).await?;
+        }
+        Commands::Build {
+            commit,
+            repo_url,
+            no_fetch,
+        } => {
+            let (commit, commit_alias) = match &cli.commit_file {
+                Some(path) => Aliases::load(path)?.resolve(commit),
+                None => (commit.clone(), None),
+            };
+            let options = RunOptions {
+                commit_alias,
+                repo_url: repo_url.clone(),
+                fetch: !no_fetch,
+                ..Default::default()
+            };
+            build_only(commit, &config, &options).await?;
+        }
+        Commands::Bisect {
+            good,
+            bad,
+            threshold_secs,
+        } => {
+            bisect(&config, good, bad, *threshold_secs).await?;
+        }
+        Commands::DbcacheSweep {
+            commit,
+            values,
+            no_fetch,
+        } => {
+            let (commit, commit_alias) = match &cli.commit_file {
+                Some(path) => Aliases::load(path)?.resolve(commit),
+                None => (commit.clone(), None),
+            };
+            let options = RunOptions {
+                commit_alias,
+                fetch: !no_fetch,
+                ..Default::default()
+            };
+            let summary = dbcache_sweep(commit, &config, &options, values).await?;
+            output::render(&summary, format, &style)?;
+        }
+        Commands::Ab {
+            a,
+            b,
+            runs,
+            no_fetch,
+        } => {
+            let options = RunOptions {
+                fetch: !no_fetch,
+                ..Default::default()
+            };
+            let summary = ab(a.clone(), b.clone(), &config, &options, *runs).await?;
+            output::render(&summary, format, &style)?;
+        }
+        Commands::Record { commit, no_fetch } => {
+            let options = RunOptions {
+                fetch: !no_fetch,
+                ..Default::default()
+            };
+            record(commit.clone(), &config, &options).await?;
+        }
+        Commands::Selftest { commit } => {
+            selftest(commit.clone(), &config).await?;
+        }
+        Commands::RunBatch {
+            fail_fast,
+            rerun,
+            interleave,
+            commit_subject_filter,
+            commit_path_filter,
+        } => {
+            let filter = CommitFilter::new(
+                &config,
+                commit_subject_filter.as_deref(),
+                commit_path_filter.as_deref(),
+            )?;
+            run_batch(&config, *fail_fast, *rerun, *interleave, &filter).await?;
+        }
+        Commands::Compare {
+            commit,
+            base,
+            baseline_file,
+            compare_commands,
+            include_incomplete,
+            aggregate,
+        } => {
+            let selection = Selection {
+                include_incomplete: *include_incomplete,
+                aggregate: *aggregate,
+            };
+            match compare_commands.as_deref() {
+                Some([base_command, command]) => compare_commands_at(
+                    &config,
+                    format,
+                    &style,
+                    commit,
+                    [base_command, command],
+                    selection,
+                )?,
+                _ => {
+                    compare_commits(
+                        &config,
+                        format,
+                        &style,
+                        commit,
+                        base.as_deref(),
+                        baseline_file.as_deref(),
+                        selection,
+                    )
+                    .await?
+                }
+            }
+        }
+        Commands::Matrix { commits } => {
+            let conn = db::open_db(&config.db_path)?;
+            let mut results = Vec::new();
+            for commit in commits {
+                let commit_hash = db::resolve_commit(&conn, commit)?
+                    .ok_or_else(|| anyhow::anyhow!("No results stored for commit {}", commit))?;
+                let latest = db::latest_results(&conn, &commit_hash)?;
+                results.push((commit_hash, latest));
+            }
+            matrix::print(&matrix::build(&results), format, &style)?;
+        }
+        Commands::List {
+            limit,
+            label,
+            aggregate,
+        } => {
+            let conn = db::open_db(&config.db_path)?;
+            let mut results = if *aggregate {
+                let mut pooled =
+                    aggregate::pool(db::poolable_results(&conn, None, label.as_deref(), false)?);
+                pooled.truncate(*limit);
+                pooled
+            } else {
+                db::recent_results(&conn, *limit, label.as_deref())?
+            };
+            if let Err(e) = git::sort_topo(&config.repo_path, &mut results, |r| &r.commit_hash) {
+                eprintln!("Warning: Listing in the order results were stored: {:#}", e);
+            }
+            if *aggregate {
+                output::render(&aggregate::Listing { results: &results }, format, &style)?;
+            } else {
+                list::print_list(&results, format, &style, &config.pr_url)?;
+            }
+        }
+        Commands::History => {
+            let conn = db::open_db(&config.db_path)?;
+            let history = history::build(&conn, &config)?;
+            output::render(&history, format, &style)?;
+        }
+        Commands::Report { limit, out } => {
+            let conn = db::open_db(&config.db_path)?;
+            let report = report::build(&conn, *limit, &config)?;
+            match out {
+                Some(out) => {
+                    fs::write(out, report.markdown(&style))
+                        .with_context(|| format!("Failed to write report to {}", out.display()))?;
+                    println!("Wrote report to {}", out.display());
+                }
+                None => output::render(&report, format, &style)?,
+            }
+        }
+        Commands::Stability { recent } => {
+            let conn = db::open_db(&config.db_path)?;
+            let results = db::timed_results(&conn)?;
+            let report = stability::build(&results, *recent, config.regression_threshold_pct);
+            output::render(&report, format, &style)?;
+        }
+        Commands::Annotate { id, note } => {
+            let conn = db::open_db(&config.db_path)?;
+            db::annotate(&conn, *id, note)?;
+            println!("Annotated result {}", id);
+        }
+        Commands::Promote { id } => {
+            let conn = db::open_db(&config.db_path)?;
+            db::promote(&conn, *id)?;
+            println!("Result {} is now the baseline for its command", id);
+        }
+        Commands::Verify { fix } => {
+            let conn = db::open_db(&config.db_path)?;
+            let invalid = verify::find_invalid(&conn)?;
+            let versions = verify::hyperfine_versions(&conn)?;
+            if versions.len() > 1 {
+                eprintln!(
+                    "Warning: results were timed by several hyperfine versions ({}), which may not be comparable",
+                    versions.join(", ")
+                );
+            }
+            for column in &invalid {
+                eprintln!(
+                    "Result {}: invalid {}: {}",
+                    column.id, column.column, column.error
+                );
+            }
+            if invalid.is_empty() {
+                println!("All stored results are valid");
+            } else if *fix {
+                verify::fix(&conn, &invalid)?;
+                println!("Cleared {} invalid columns", invalid.len());
+            } else {
+                anyhow::bail!(
+                    "{} invalid columns found, rerun with --fix to clear them",
+                    invalid.len()
+                );
+            }
+        }
+        Commands::Tui { limit } => {
+            let conn = db::open_db(&config.db_path)?;
+            tui::run(&conn, *limit, &style)?;
+        }
+        Commands::Export { path } => {
+            let conn = db::open_db(&config.db_path)?;
+            let rows = export::rows(&conn)?;
+            export::write_parquet(&rows, path)?;
+            println!("Exported {} results to {}", rows.len(), path.display());
+        }
+        Commands::Backup { out } => {
+            db::backup(&config.db_path, out)?;
+            println!(
+                "Backed up {} to {}",
+                config.db_path.display(),
+                out.display()
+            );
+        }
+        Commands::Restore { path, yes } => {
+            let prompt = format!(
+                "Replace {} with {}? [y/N] ",
+                config.db_path.display(),
+                path.display()
+            );
+            if !yes && !confirm(&prompt)? {
+                anyhow::bail!("Restore cancelled, pass --yes to restore non-interactively");
+            }
+            db::restore(&config.db_path, path)?;
+            println!(
+                "Restored {} from {}",
+                config.db_path.display(),
+                path.display()
+            );
+        }
+        Commands::Import {
+            path,
+            mapping: Some(mapping),
+        } => {
+            let mapping = import::parse_mapping(mapping)?;
+            let conn = db::open_db(&config.db_path)?;
+            let summary = import::import_table(&conn, path, &mapping)?;
+            println!(
+                "Imported {} results from {}, skipped {} rows",
+                summary.results,
+                path.display(),
+                summary.skipped
+            );
+        }
+        Commands::Import {
+            path,
+            mapping: None,
+        } => {
+            let conn = db::open_db(&config.db_path)?;
+            let summary = import::import_dir(&conn, path)?;
+            println!(
+                "Imported {} results from {} files, skipped {} files",
+                summary.results, summary.files, summary.skipped
+            );
+        }
+        Commands::Enqueue {
+            commit,
+            scenario,
+            priority,
+        } => {
+            let commit_hash = git::resolve_commit(&config.repo_path, commit)?;
+            config.with_overrides(*scenario, None)?;
+            let conn = db::open_db(&config.db_path)?;
+            let id = queue::enqueue(&conn, &commit_hash, *scenario, None, *priority, None)?;
+            println!(
+                "Queued {} as #{} with priority {}, {} commits waiting",
+                commit,
+                id,
+                priority,
+                queue::pending(&conn)?
+            );
+        }
+        Commands::MergeDb { sources } => {
+            let conn = db::open_db(&config.db_path)?;
+            for source in sources {
+                let machine = source.file_stem().map_or_else(
+                    || source.display().to_string(),
+                    |stem| stem.to_string_lossy().into_owned(),
+                );
+                let summary = db::merge(&conn, source, &machine)
+                    .with_context(|| format!("Failed to merge {}", source.display()))?;
+                println!(
+                    "Merged {} results from {}, skipped {} already present",
+                    summary.merged,
+                    source.display(),
+                    summary.skipped
+                );
+            }
         }
     }
 
     Ok(())
 }
 
-async fn start_daemon() -> Result<()> {
-    let cron_expression = "0 0 0 * * * *"; // Every day at midnight
-    let schedule = Schedule::from_str(cron_expression).unwrap();
-    let mut upcoming = schedule.upcoming(Utc);
+async fn start_daemon(
+    config: &Config,
+    max_runtime: Option<Duration>,
+    since_last_release: bool,
+) -> Result<()> {
+    let schedules = config
+        .schedule
+        .iter()
+        .map(ScheduleEntry::parse_cron)
+        .collect::<Result<Vec<_>>>()?;
+    let deadline = max_runtime.map(|max_runtime| Instant::now() + max_runtime);
+    match db::open_db(&config.db_path).and_then(|conn| queue::requeue_interrupted(&conn)) {
+        Ok(0) => {}
+        Ok(requeued) => println!("Requeued {} commits interrupted by a restart", requeued),
+        Err(e) => eprintln!("Warning: {:#}", e),
+    }
+
+    // Scheduled and queued runs share the checkout and the datadir, so jobs only
+    // queue their commits and the coordinator benchmarks them one at a time
+    let wake = Arc::new(Notify::new());
+    let stop = Arc::new(AtomicBool::new(false));
+    let coordinator = tokio::spawn(coordinate(config.clone(), wake.clone(), stop.clone()));
 
-    while let Some(datetime) = upcoming.next() {
-        let now = Utc::now();
-        let duration = datetime - now;
+    // Each job's next fire time, earliest first
+    let mut due = BinaryHeap::new();
+    for (job, schedule) in schedules.iter().enumerate() {
+        if let Some(datetime) = schedule.upcoming(config.timezone).next() {
+            due.push(Reverse((datetime, job)));
+        }
+    }
 
-        if duration.to_std().is_ok() {
-            let delay = duration.to_std().unwrap();
-            sleep(delay).await;
-        } else {
-            // If the duration is negative, skip to next
-            continue;
+    // Fire times that came while the job's previous commits were still queued
+    let mut skipped = 0;
+    while let Some(Reverse((datetime, job))) = due.pop() {
+        let now = Utc::now().with_timezone(&config.timezone);
+        if let Some(next) = schedules[job].after(&datetime.max(now)).next() {
+            due.push(Reverse((next, job)));
+        }
+        let delay = (datetime - now).to_std().unwrap_or_default();
+        if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+            break;
         }
+        let entry = &config.schedule[job];
+        println!(
+            "Next run of scheduled job `{}` at {}",
+            entry.cron,
+            datetime.format("%Y-%m-%d %H:%M %Z")
+        );
+        sleep(delay).await;
 
-        if let Err(e) = run_benchmark("master".to_string()).await {
-            eprintln!("Error running benchmark: {:?}", e);
+        match db::open_db(&config.db_path)
+            .and_then(|conn| queue::active_for_job(&conn, &entry.cron))
+        {
+            Ok(0) => {}
+            Ok(_) => {
+                println!(
+                    "Skipped a run of scheduled job `{}`, previous run still active",
+                    entry.cron
+                );
+                skipped += 1;
+                continue;
+            }
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+        let commits = if since_last_release {
+            match entry
+                .apply(config)
+                .and_then(|config| unbenchmarked_since_last_release(&config))
+            {
+                Ok(commits) => commits,
+                Err(e) => {
+                    eprintln!("Error listing commits since the last release: {:?}", e);
+                    continue;
+                }
+            }
+        } else {
+            vec!["master".to_string()]
+        };
+        let queued = db::open_db(&config.db_path).and_then(|conn| {
+            for commit in &commits {
+                queue::enqueue(
+                    &conn,
+                    commit,
+                    entry.scenario,
+                    entry.stopatheight,
+                    0,
+                    Some(&entry.cron),
+                )?;
+            }
+            Ok(())
+        });
+        match queued {
+            Ok(()) => println!(
+                "Running scheduled job `{}`, queued {} commits",
+                entry.cron,
+                commits.len()
+            ),
+            Err(e) => eprintln!("Error queueing scheduled job `{}`: {:?}", entry.cron, e),
         }
+        wake.notify_one();
     }
 
+    if let Some(deadline) = deadline {
+        sleep(deadline.saturating_duration_since(Instant::now())).await;
+        println!("Maximum runtime reached, exiting");
+    }
+    stop.store(true, Ordering::SeqCst);
+    wake.notify_one();
+    if let Err(e) = coordinator.await {
+        eprintln!("Warning: the run coordinator failed: {}", e);
+    }
+    report_skipped(skipped);
     Ok(())
 }
 
-async fn run_benchmark(commit: String) -> Result<()> {
-    let repo_path = "/home/will/src/bitcoin";
-    let db_path = "/home/will/src/bitcoin_benchmark/results.db";
+/// How often the coordinator checks the queue while it has nothing to do, for
+/// commits queued with `enqueue` by another process.
+const QUEUE_POLL: Duration = Duration::from_secs(30);
 
-    tokio::task::spawn_blocking(move || -> Result<()> {
-        git_update_repository(&commit, repo_path)?;
-        run_hyperfine(&commit, repo_path)?;
-        save_results_to_db(&commit, repo_path, db_path)?;
-        Ok(())
-    })
-    .await
-    .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
+/// Benchmark queued commits one at a time, highest priority first, until `stop` is
+/// set, checking the queue again when woken. A benchmark still running then is
+/// allowed to finish. Housekeeping is done whenever the queue runs dry.
+async fn coordinate(config: Config, wake: Arc<Notify>, stop: Arc<AtomicBool>) {
+    let mut ran = false;
+    while !stop.load(Ordering::SeqCst) {
+        let entry = match db::open_db(&config.db_path).and_then(|conn| queue::claim_next(&conn)) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => {
+                if ran {
+                    maintenance(&config);
+                    ran = false;
+                }
+                let _ = tokio::time::timeout(QUEUE_POLL, wake.notified()).await;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Warning: {:#}", e);
+                let _ = tokio::time::timeout(QUEUE_POLL, wake.notified()).await;
+                continue;
+            }
+        };
+
+        match &entry.job {
+            Some(job) => println!("Running {} for scheduled job `{}`", entry.commit, job),
+            None => println!(
+                "Running queued {} with priority {}",
+                entry.commit, entry.priority
+            ),
+        }
+        let options = RunOptions {
+            preview: true,
+            ..Default::default()
+        };
+        // Failed instead of run as something other than what was queued
+        let queued_config = entry
+            .scenario()
+            .and_then(|scenario| config.with_overrides(scenario, entry.stopatheight));
+        let outcome = match queued_config {
+            Ok(queued_config) => {
+                run_benchmark(entry.commit.clone(), &queued_config, &options).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &outcome {
+            eprintln!("Error benchmarking queued {}: {:?}", entry.commit, e);
+        }
+        let finished = db::open_db(&config.db_path)
+            .and_then(|conn| queue::finish(&conn, entry.id, outcome.is_ok()));
+        if let Err(e) = finished {
+            eprintln!("Warning: {:#}", e);
+        }
+        enforce_max_db_size(&config);
+        ran = true;
+        if !stop.load(Ordering::SeqCst) {
+            cooldown(&config).await;
+        }
+    }
+}
+
+/// Keep the database within `max_db_size_mb`, if set, saying what was pruned.
+fn enforce_max_db_size(config: &Config) {
+    let Some(max_mb) = config.max_db_size_mb else {
+        return;
+    };
+    let pruned = db::open_db(&config.db_path)
+        .and_then(|conn| db::prune_to_size(&conn, &config.db_path, max_mb * 1024 * 1024));
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    match pruned {
+        Ok(Some(pruned)) => {
+            println!(
+                "Database exceeded max_db_size_mb of {}: deleted {} oldest results (ids {} to {}), \
+                 shrinking it from {:.1} to {:.1} MB",
+                max_mb,
+                pruned.deleted,
+                pruned.first_id,
+                pruned.last_id,
+                mb(pruned.bytes_before),
+                mb(pruned.bytes_after)
+            );
+            if pruned.bytes_after > max_mb * 1024 * 1024 {
+                eprintln!(
+                    "Warning: the database is still over max_db_size_mb with only baselines left"
+                );
+            }
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!(
+            "Warning: keeping the database under max_db_size_mb failed: {:#}",
+            e
+        ),
+    }
+}
+
+/// Run a command that needs no directories of the configuration created.
+fn run_setup_command(cli: &Cli, command: &SetupCommands, format: Format) -> Result<()> {
+    match command {
+        SetupCommands::Completions { shell } => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(*shell, &mut command, name, &mut io::stdout());
+        }
+        SetupCommands::ShowConfig => {
+            load_dotenv()?;
+            let (config, sources) =
+                Config::load_with_sources(cli.config.as_deref(), &cli.overrides)?;
+            let style = output::Style::new(&config, cli.full_hash);
+            output::render(&show_config::build(&config, sources)?, format, &style)?;
+        }
+        SetupCommands::Paths => {
+            load_dotenv()?;
+            let config = Config::load(cli.config.as_deref(), &cli.overrides)?;
+            let style = output::Style::new(&config, cli.full_hash);
+            output::render(&paths::resolve(&config)?, format, &style)?;
+        }
+    }
     Ok(())
 }
 
-fn git_update_repository(commit: &str, repo_path: &str) -> Result<()> {
-    std::env::set_current_dir(repo_path)
-        .with_context(|| format!("Failed to change directory to {}", repo_path))?;
+/// Load `.env`, where secrets such as webhook URLs can live to be referenced as
+/// `${VAR}`, if there is one.
+fn load_dotenv() -> Result<()> {
+    match dotenvy::dotenv() {
+        Ok(_) => Ok(()),
+        Err(e) if e.not_found() => Ok(()),
+        Err(e) => Err(e).with_context(|| "Failed to load .env"),
+    }
+}
 
-    Command::new("git")
-        .args(["fetch", "--all"])
-        .status()
-        .with_context(|| "Failed to fetch git repository")?;
+/// Housekeeping the daemon does whenever its queue runs dry: rotate kept logs and
+/// publish the results site. Failures only warn, as it is done again next time.
+fn maintenance(config: &Config) {
+    let rotated = db::open_db(&config.db_path).and_then(|conn| logs::rotate(config, &conn));
+    match rotated {
+        Ok(rotation) if rotation.compressed + rotation.deleted > 0 => println!(
+            "Compressed {} and deleted {} kept logs",
+            rotation.compressed, rotation.deleted
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("Warning: log rotation failed: {:#}", e),
+    }
 
-    Command::new("git")
-        .args(["checkout", commit])
-        .status()
-        .with_context(|| format!("Failed to checkout commit {}", commit))?;
+    if let Some(site_dir) = &config.site_dir {
+        let style = output::Style::new(config, false);
+        match db::open_db(&config.db_path).and_then(|conn| site::publish(config, &conn, &style)) {
+            Ok(charts) => println!("Published {} charts to {}", charts, site_dir.display()),
+            Err(e) => eprintln!("Warning: publishing the results site failed: {:#}", e),
+        }
+    }
+}
+
+fn report_skipped(skipped: usize) {
+    if skipped > 0 {
+        println!(
+            "Skipped {} scheduled runs in total while a previous run was still active",
+            skipped
+        );
+    }
+}
+
+/// Benchmark every commit listed on stdin in order. Commits with stored results are
+/// skipped unless `rerun` is set, so an interrupted batch can simply be restarted.
+async fn run_batch(
+    config: &Config,
+    fail_fast: bool,
+    rerun: bool,
+    interleave: Option<u32>,
+    filter: &CommitFilter,
+) -> Result<()> {
+    let batch_id = Uuid::new_v4().to_string();
+    let mut commits = Vec::new();
+    let mut filtered = 0;
+    for line in io::stdin().lines() {
+        let line = line.with_context(|| "Failed to read commits from stdin")?;
+        let commit = line.split('#').next().unwrap_or_default().trim();
+        if !commit.is_empty() {
+            // Resolve up front, as each run moves HEAD
+            let commit_hash = git::resolve_commit(&config.repo_path, commit)?;
+            if let Some(reason) = filter.skip_reason(&config.repo_path, &commit_hash)? {
+                println!("Skipping {}, {}", commit, reason);
+                db::record_filtered(
+                    &db::open_db(&config.db_path)?,
+                    &commit_hash,
+                    &reason,
+                    Some(&batch_id),
+                )?;
+                filtered += 1;
+                continue;
+            }
+            commits.push((commit.to_string(), commit_hash));
+        }
+    }
+
+    let (mut benchmarked, mut skipped, mut failed) = (0, 0, 0);
+    for (position, (commit, commit_hash)) in batch_order(&commits, interleave).enumerate() {
+        if !rerun
+            && interleave.is_none()
+            && db::is_benchmarked(&db::open_db(&config.db_path)?, commit_hash)?
+        {
+            println!("Skipping {}, already benchmarked", commit);
+            skipped += 1;
+            continue;
+        }
+
+        if benchmarked + failed > 0 {
+            cooldown(config).await;
+        }
+        let options = RunOptions {
+            batch: Some((batch_id.clone(), position)),
+            ..Default::default()
+        };
+        match run_benchmark(commit_hash.clone(), config, &options).await {
+            Ok(()) => benchmarked += 1,
+            Err(e) if !fail_fast => {
+                eprintln!("Error benchmarking {}: {:?}", commit, e);
+                failed += 1;
+            }
+            Err(e) => return Err(e.context(format!("Failed to benchmark {}", commit))),
+        }
+    }
 
+    if let Some(rounds) = interleave {
+        println!(
+            "Completed {} runs of {} commits over {} interleaved rounds, {} failed",
+            benchmarked,
+            commits.len(),
+            rounds,
+            failed
+        );
+    } else {
+        println!(
+            "Benchmarked {} commits, skipped {} already benchmarked, {} failed",
+            benchmarked, skipped, failed
+        );
+    }
+    if filtered > 0 {
+        println!("Filtered out {} commits", filtered);
+    }
+    if failed > 0 {
+        anyhow::bail!("{} commits failed to benchmark", failed);
+    }
     Ok(())
 }
 
-fn run_hyperfine(commit: &str, repo_path: &str) -> Result<()> {
-    std::env::set_current_dir(repo_path)
-        .with_context(|| format!("Failed to change directory to {}", &repo_path))?;
+/// The order a batch benchmarks `commits` in: once each, or `rounds` times each
+/// with every other round reversed.
+fn batch_order<T>(commits: &[T], rounds: Option<u32>) -> impl Iterator<Item = &T> {
+    let rounds = rounds.unwrap_or(1) as usize;
+    (0..rounds).flat_map(move |round| {
+        let forward = round % 2 == 0;
+        (0..commits.len()).map(move |i| {
+            let i = if forward { i } else { commits.len() - 1 - i };
+            &commits[i]
+        })
+    })
+}
 
-    let hyperfine_command = format!(
-        "hyperfine \
-        --parameter-list commit {commit} \
-        --setup 'rm -Rf build && git checkout {{commit}} && cmake -B build && cmake --build build -j$(nproc)' \
-        --prepare 'sync && rm -Rf /mnt/bench/.bitcoin/*' \
-        --cleanup '' \
-        --runs 1 \
-        --show-output \
-        --export-json results.json \
-        './build/src/bitcoind -datadir=/mnt/bench/.bitcoin -connect=127.0.0.1:8333 -port=8444 -rpcport=8445 -dbcache=16385 -printtoconsole=0 -stopatheight=100000'"
-    );
+/// Commits between the latest release tag and the tip that have not been stored and
+/// the commit filter lets through, oldest first and at most `max_backfill` of them.
+/// The filter is applied afresh each time, so a commit rejected under an earlier
+/// filter is picked up once the filter changes. Rejections are recorded in
+/// `filtered_commits` as a log only.
+fn unbenchmarked_since_last_release(config: &Config) -> Result<Vec<String>> {
+    let (tag, commits) = git::commits_since_last_release(&config.repo_path, "master")?;
+    let conn = db::open_db(&config.db_path)?;
+    let filter = CommitFilter::new(config, None, None)?;
+    let mut pending = Vec::new();
+    for commit in commits {
+        if db::is_benchmarked(&conn, &commit)? {
+            continue;
+        }
+        match filter.skip_reason(&config.repo_path, &commit)? {
+            Some(reason) => {
+                println!("Skipping {}, {}", commit, reason);
+                db::record_filtered(&conn, &commit, &reason, None)?;
+            }
+            None => pending.push(commit),
+        }
+    }
+    if pending.is_empty() {
+        println!("Every commit since {} has been benchmarked", tag);
+        return Ok(pending);
+    }
+    // The rest stay unbenchmarked, so a later job picks them up
+    let deferred = config
+        .max_backfill
+        .map_or(0, |max| pending.len().saturating_sub(max));
+    pending.truncate(pending.len() - deferred);
+    println!("Benchmarking {} new commits since {}", pending.len(), tag);
+    if deferred > 0 {
+        println!("Deferred {} more to later jobs (max_backfill)", deferred);
+    }
+    Ok(pending)
+}
 
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(&hyperfine_command)
-        .output()
-        .with_context(|| "Failed to execute hyperfine command")?;
+/// Which stored results `compare` reads of each commit.
+#[derive(Clone, Copy)]
+struct Selection {
+    /// Also read IBD runs that stopped short of `stopatheight`
+    include_incomplete: bool,
+    /// Pool every stored run of each command instead of reading only the latest
+    aggregate: bool,
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(anyhow::anyhow!(
-            "Hyperfine command failed with status {}: \nStdout: {}\nStderr: {}",
-            output.status,
-            stdout,
-            stderr
-        ));
+async fn compare_commits(
+    config: &Config,
+    format: Format,
+    style: &output::Style,
+    commit: &str,
+    base: Option<&str>,
+    baseline_file: Option<&Path>,
+    selection: Selection,
+) -> Result<()> {
+    let conn = db::open_db(&config.db_path)?;
+    let (commit_hash, results) = stored_for_compare(config, &conn, commit, selection)?;
+
+    let (base_label, base_results) = if let Some(baseline_file) = baseline_file {
+        (
+            baseline_file.display().to_string(),
+            compare::baseline_from_file(baseline_file)?,
+        )
+    } else {
+        let base_hash = resolve_compare_base(config, &conn, &commit_hash, base).await?;
+        let (_, base_results) = stored_for_compare(config, &conn, &base_hash, selection)?;
+        (base_hash, base_results)
+    };
+
+    if let Some(versions) = compare::mixed_hyperfine_versions(results.iter().chain(&base_results)) {
+        eprintln!(
+            "Warning: these results were timed by different hyperfine versions ({})",
+            versions.join(", ")
+        );
+    }
+    let report = compare::Report::new(
+        &commit_hash,
+        &results,
+        &base_label,
+        &base_results,
+        config.comparison_metric,
+        config.regression_threshold_pct,
+    );
+    output::render(&report, format, style)?;
+    let regressions = report.regressions;
+
+    if regressions > 0 {
+        anyhow::bail!(
+            "{} command(s) regressed by more than {}%, or more for low-confidence results",
+            regressions,
+            config.regression_threshold_pct
+        );
     }
+    Ok(())
+}
 
+/// Compare the results of two commands stored for `commit`, the second against the
+/// first, failing if it regressed.
+fn compare_commands_at(
+    config: &Config,
+    format: Format,
+    style: &output::Style,
+    commit: &str,
+    [base_command, command]: [&str; 2],
+    selection: Selection,
+) -> Result<()> {
+    let conn = db::open_db(&config.db_path)?;
+    let (commit_hash, results) = stored_for_compare(config, &conn, commit, selection)?;
+    let base = compare::find_command(&results, base_command)?;
+    let result = compare::find_command(&results, command)?;
+    if base.command == result.command {
+        anyhow::bail!("`{}` and `{}` are the same command", base_command, command);
+    }
+    let report = compare::CommandReport::new(
+        &commit_hash,
+        base,
+        result,
+        config.comparison_metric,
+        config.regression_threshold_pct,
+    )?;
+    output::render(&report, format, style)?;
+    if report.comparison.regression {
+        anyhow::bail!(
+            "The command regressed by more than {}% against the base command, or more for \
+             low-confidence results",
+            config.regression_threshold_pct
+        );
+    }
     Ok(())
 }
 
-fn save_results_to_db(commit: &str, repo_path: &str, db_path: &str) -> Result<()> {
-    let results_json_path = format!("{}/results.json", repo_path);
-    let data = fs::read_to_string(&results_json_path)
-        .with_context(|| format!("Failed to read results.json file at {}", results_json_path))?;
-    let results: HyperfineResults =
-        serde_json::from_str(&data).with_context(|| "Failed to parse JSON from results.json")?;
+/// The stored results of `commit` that `compare` compares, and its full hash.
+fn stored_for_compare(
+    config: &Config,
+    conn: &rusqlite::Connection,
+    commit: &str,
+    selection: Selection,
+) -> Result<(String, Vec<db::StoredResult>)> {
+    let Selection {
+        include_incomplete,
+        aggregate,
+    } = selection;
+    if aggregate && config.comparison_metric != config::Metric::Mean {
+        anyhow::bail!("--aggregate pools means, so needs the mean comparison_metric");
+    }
+    let commit_hash = db::resolve_commit(conn, commit)?
+        .ok_or_else(|| anyhow::anyhow!("No results stored for commit {}", commit))?;
+    let results = if aggregate {
+        aggregate::pool(db::poolable_results(
+            conn,
+            Some(&commit_hash),
+            None,
+            include_incomplete,
+        )?)
+    } else {
+        db::latest_results_with(conn, &commit_hash, include_incomplete)?
+    };
+    Ok((commit_hash, results))
+}
+
+/// Resolve the base commit to compare against, offering to benchmark an
+/// unbenchmarked parent.
+async fn resolve_compare_base(
+    config: &Config,
+    conn: &rusqlite::Connection,
+    commit_hash: &str,
+    base: Option<&str>,
+) -> Result<String> {
+    let base_hash = match compare::resolve_base(conn, commit_hash, base)? {
+        Base::Stored(base_hash) => base_hash,
+        Base::Unbenchmarked(parent) => {
+            let prompt = format!(
+                "Parent {} has not been benchmarked. Benchmark it now? [y/N] ",
+                parent
+            );
+            if !confirm(&prompt)? {
+                anyhow::bail!(
+                    "Parent {} has not been benchmarked, run `run --commit {}` first",
+                    parent,
+                    parent
+                );
+            }
+            run_benchmark(parent.clone(), config, &RunOptions::default()).await?;
+            parent
+        }
+    };
+    Ok(base_hash)
+}
+
+/// Ask a yes/no question on the terminal, answering no when not interactive.
+fn confirm(prompt: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
-    let conn = Connection::open(db_path).with_context(|| "Failed to connect to SQLite database")?;
+/// Parse a `--param` given as `name=v1,v2,v3`.
+fn parse_param(input: &str) -> Result<(String, String), String> {
+    let (name, values) = input
+        .split_once('=')
+        .ok_or_else(|| format!("`{}` is not of the form name=v1,v2,...", input))?;
+    let name = name.trim();
+    if name.is_empty() || values.is_empty() {
+        return Err(format!("`{}` is not of the form name=v1,v2,...", input));
+    }
+    if name == "commit" {
+        return Err("`commit` is set by the tool and cannot be swept".to_string());
+    }
+    Ok((name.to_string(), values.to_string()))
+}
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS benchmarks (
-            id INTEGER PRIMARY KEY,
-            commit_hash TEXT NOT NULL,
-            command TEXT NOT NULL,
-            mean REAL,
-            stddev REAL,
-            median REAL,
-            user REAL,
-            system REAL,
-            min REAL,
-            max REAL,
-            times TEXT,
-            exit_codes TEXT,
-            parameters TEXT
-        )",
-        [],
-    )
-    .with_context(|| "Failed to create benchmarks table")?;
+/// Parse a duration such as `90s`, `30m`, `8h`, `1d` or `1h30m`. A bare number is
+/// taken as seconds.
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    if let Ok(secs) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("invalid duration unit `{}` in `{}`", c, input)),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("missing number before `{}` in `{}`", c, input))?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("duration `{}` is too long", input))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(format!("missing unit after `{}` in `{}`", number, input));
+    }
+    Ok(Duration::from_secs(total))
+}
 
-    // Insert the benchmark results
-    for result in results.results {
-        // Extract commit from parameters if available
-        let commit_value = if let Some(ref params) = result.parameters {
-            &params.commit
+/// Let the machine settle between consecutive runs so the next one starts from a
+/// similar thermal and page cache state.
+async fn cooldown(config: &Config) {
+    if config.cooldown_secs == 0 {
+        return;
+    }
+    if config.drop_caches {
+        // On the machine that benchmarks, whose caches would warm the next run
+        let runner = runner::for_config(config);
+        let dropped = if runner.is_local() {
+            system::drop_caches()
         } else {
-            commit
+            run_checked(
+                runner.as_ref(),
+                &config.shell,
+                &["-c", "sync && echo 3 > /proc/sys/vm/drop_caches"],
+            )
         };
+        if let Err(e) = dropped {
+            eprintln!("Warning: failed to drop page caches: {:#}", e);
+        }
+    }
+    println!("Cooling down for {} seconds", config.cooldown_secs);
+    sleep(Duration::from_secs(config.cooldown_secs)).await;
+}
 
-        conn.execute(
-            "INSERT INTO benchmarks (
-                commit_hash, command, mean, stddev, median, user, system, min, max, times, exit_codes, parameters
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
-            params![
-                commit_value,
-                result.command,
-                result.mean,
-                result.stddev,
-                result.median,
-                result.user,
-                result.system,
-                result.min,
-                result.max,
-                serde_json::to_string(&result.times)
-                    .with_context(|| "Failed to serialize times")?,
-                serde_json::to_string(&result.exit_codes)
-                    .with_context(|| "Failed to serialize exit_codes")?,
-                serde_json::to_string(&result.parameters)
-                    .with_context(|| "Failed to serialize parameters")?,
-            ],
-        )
-        .with_context(|| "Failed to insert benchmark result into database")?;
+async fn run_benchmark(commit: String, config: &Config, options: &RunOptions) -> Result<()> {
+    let config = config.clone();
+    let options = options.clone();
+
+    if options.runs(Phase::Save) && !options.runs(Phase::Bench) && options.results_file.is_none() {
+        anyhow::bail!("--results-file is required to save results without running the benchmark");
     }
 
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let run_id = Uuid::new_v4().to_string();
+        let outcome = hooks::pre_run(&config, &commit, &options)
+            .and_then(|()| run_pipeline(&commit, &run_id, &config, &options));
+        let failed_assertions = outcome
+            .as_ref()
+            .map_or(0, |outcome| outcome.failed_assertions);
+        let outcome = outcome.map(|outcome| outcome.ids);
+        hooks::post_run(&config, &commit, &options, &outcome);
+        // Unstored runs have no history to find regressions against
+        if options.print_results.is_none() {
+            if let (true, Ok(ids)) = (options.preview, &outcome) {
+                log_preview(&config, ids);
+            }
+            notify::after_run(&config, &commit, &run_id, &outcome);
+        }
+        outcome?;
+        // Failed only now, so the violating results are stored, compared and notified
+        if config.fail_on_assertion && failed_assertions > 0 {
+            anyhow::bail!("{} results failed assertions", failed_assertions);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
+    Ok(())
+}
+
+/// Log a logfmt line per stored result comparing it against the previous result, as
+/// regression notifications do, so the daemon's log can be followed as a feed.
+fn log_preview(config: &Config, ids: &[i64]) {
+    let metric = config.comparison_metric;
+    let comparisons = db::open_db(&config.db_path).and_then(|conn| {
+        regression::compare_previous(&conn, ids, metric, config.regression_threshold_pct)
+    });
+    let comparisons = match comparisons {
+        Ok(comparisons) => comparisons,
+        Err(e) => {
+            eprintln!("Warning: failed to compare the new results: {:#}", e);
+            return;
+        }
+    };
+    for comparison in comparisons {
+        let mut fields = vec![
+            ("event", "preview".to_string()),
+            ("commit", comparison.commit),
+            ("command", comparison.command),
+            ("metric", metric.column().to_string()),
+            ("value", format!("{:.3}", comparison.value)),
+        ];
+        match comparison.against {
+            Some(against) => fields.extend([
+                ("previous_commit", against.commit),
+                ("previous_value", format!("{:.3}", against.value)),
+                ("delta_pct", format!("{:+.2}", against.delta_pct)),
+                ("threshold_pct", format!("{:.2}", against.threshold_pct)),
+                ("confidence", format!("{:.2}", against.confidence)),
+                ("regression", against.regression.to_string()),
+            ]),
+            None => fields.push(("regression", "false".to_string())),
+        }
+        println!("{}", output::logfmt(&fields));
+    }
+}
+
+/// Check out and build `commit` as a run would, without benchmarking it, and store
+/// a row recording the build. Failed builds are recorded as they are for runs.
+async fn build_only(commit: String, config: &Config, options: &RunOptions) -> Result<()> {
+    let config = config.clone();
+    let options = options.clone();
+
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let run_id = Uuid::new_v4().to_string();
+        let mut run = PipelineRun::new(&commit, &run_id, &config, &options)?;
+        if config.pipeline.steps().contains(&Phase::Fetch) {
+            fetch_step(&mut run, &config, &options)?;
+        }
+        build_step(&mut run, &config, &options)?;
+        if config.benchmark == Benchmark::Guix {
+            return Ok(save_guix_step(&mut run, &config, &options)?);
+        }
+        let build_command = run.build_command.as_deref().unwrap_or_default();
+        db::record_build(&config.db_path, &run.commit, build_command, &run.metadata)?;
+        println!(
+            "{} built in {:.1}s",
+            run.commit,
+            run.metadata.build_secs.unwrap_or_default()
+        );
+        Ok(())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
     Ok(())
 }
+
+/// Check out and build `commit` as a run would, then record it syncing under `rr`.
+async fn record(commit: String, config: &Config, options: &RunOptions) -> Result<()> {
+    if config.benchmark != Benchmark::Ibd {
+        anyhow::bail!("record needs the ibd benchmark");
+    }
+    if config.rr_trace_dir.is_none() {
+        anyhow::bail!("record needs an rr_trace_dir to keep the trace in");
+    }
+    let config = config.clone();
+    let options = options.clone();
+
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let run_id = Uuid::new_v4().to_string();
+        let mut run = PipelineRun::new(&commit, &run_id, &config, &options)?;
+        if config.pipeline.steps().contains(&Phase::Fetch) {
+            fetch_step(&mut run, &config, &options)?;
+        }
+        build_step(&mut run, &config, &options)?;
+        record_step(&run, &config)?;
+        Ok(())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??;
+    Ok(())
+}
+
+/// Sync `run`'s build once under `rr record` into a new trace in `rr_trace_dir`,
+/// untimed, and store a row pointing at the trace. bitcoind failing is what a
+/// recording is usually for, so it is stored as the row's error rather than
+/// returned.
+fn record_step(run: &PipelineRun, config: &Config) -> Result<PathBuf> {
+    let trace_root = config
+        .rr_trace_dir
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Recording under rr needs an rr_trace_dir"))?;
+    let runner = runner::for_config(config);
+    let run_id = run.metadata.run_id.as_deref().unwrap_or("recording");
+    let trace_dir = trace_root.join(format!(
+        "{}-{}",
+        output::short_hash(&run.commit, 12),
+        run_id
+    ));
+    run_checked(
+        runner.as_ref(),
+        "mkdir",
+        &["-p", &trace_root.display().to_string()],
+    )?;
+
+    let snapshot_result = results_json_path(config, &run.commit).with_extension("rr.snapshot.json");
+    let prepare = bitcoind::prepare_command(config, &snapshot_result)?;
+    run_checked(runner.as_ref(), &config.shell, &["-c", &prepare])?;
+    let _ = fs::remove_file(&snapshot_result);
+    let command = with_extra_args(config, bitcoind::record_command(config, &trace_dir));
+    println!(
+        "Recording {} under rr into {}",
+        run.commit,
+        trace_dir.display()
+    );
+    let status = runner
+        .command(&config.shell, &["-c", &command])
+        .status()
+        .with_context(|| "Failed to execute rr")?;
+    let error = (!status.success()).then(|| format!("bitcoind exited with {}", status));
+    if !config.keep_datadir {
+        let wipe = format!("rm -Rf {}/*", config.datadir.display());
+        run_checked(runner.as_ref(), &config.shell, &["-c", &wipe])?;
+    }
+
+    let metadata = RunMetadata {
+        stop_height: Some(config.stopatheight),
+        ..run.metadata.clone()
+    };
+    db::record_rr_trace(
+        &config.db_path,
+        &run.commit,
+        &command,
+        &trace_dir,
+        error.as_deref(),
+        &metadata,
+    )?;
+    if let Some(error) = &error {
+        println!("Recorded a run where {}", error);
+    }
+    println!("Replay it with `rr replay {}`", trace_dir.display());
+    Ok(trace_dir)
+}
+
+/// Bisect between `good` and `bad` for the first commit whose mean exceeds
+/// `threshold_secs`, then print it with its subject.
+async fn bisect(config: &Config, good: &str, bad: &str, threshold_secs: f64) -> Result<()> {
+    if config.benchmark == Benchmark::Guix {
+        anyhow::bail!("bisect compares hyperfine timings, which the guix benchmark has none of");
+    }
+    if config.ssh_host.is_some() {
+        anyhow::bail!("bisect steps through the local checkout and cannot run over ssh_host");
+    }
+    git::bisect_start(&config.repo_path, good, bad)?;
+    let outcome = bisect_steps(config, threshold_secs).await;
+    if let Err(e) = git::bisect_reset(&config.repo_path) {
+        eprintln!("Warning: failed to reset the bisection: {:#}", e);
+    }
+
+    let culprit = outcome?;
+    let subject =
+        git::subject(&runner::Local::new(&config.repo_path), &culprit).unwrap_or_default();
+    println!(
+        "First commit slower than {}s: {} {}",
+        threshold_secs, culprit, subject
+    );
+    Ok(())
+}
+
+/// Test the commits `git bisect` checks out until it names the first bad one.
+async fn bisect_steps(config: &Config, threshold_secs: f64) -> Result<String> {
+    let command = timed_command(config);
+    let style = output::Style::new(config, true);
+    loop {
+        let commit = git::resolve_commit(&config.repo_path, "HEAD")?;
+        let verdict = match bisect_mean(config, &commit, &command).await {
+            Ok(mean) if mean > threshold_secs => {
+                println!("{} took {}, marking it bad", commit, style.duration(mean));
+                git::BisectVerdict::Bad
+            }
+            Ok(mean) => {
+                println!("{} took {}, marking it good", commit, style.duration(mean));
+                git::BisectVerdict::Good
+            }
+            Err(e) => {
+                eprintln!("Error benchmarking {}, skipping it: {:?}", commit, e);
+                git::BisectVerdict::Skip
+            }
+        };
+        if let Some(culprit) = git::bisect_mark(&config.repo_path, verdict)? {
+            return Ok(culprit);
+        }
+    }
+}
+
+/// The mean of `commit`'s latest result for `command`, benchmarking it first if
+/// there is none stored.
+async fn bisect_mean(config: &Config, commit: &str, command: &str) -> Result<f64> {
+    let stored = || -> Result<Option<f64>> {
+        let conn = db::open_db(&config.db_path)?;
+        let results = db::latest_results(&conn, commit)?;
+        Ok(results
+            .iter()
+            .find(|result| result.command == command)
+            .and_then(|result| result.mean))
+    };
+    if let Some(mean) = stored()? {
+        println!("Using the stored result for {}", commit);
+        return Ok(mean);
+    }
+
+    // The bisection has the commit checked out already
+    let options = RunOptions {
+        fetch: false,
+        ..Default::default()
+    };
+    run_benchmark(commit.to_string(), config, &options).await?;
+    cooldown(config).await;
+    stored()?.ok_or_else(|| anyhow::anyhow!("No result for `{}` was stored", command))
+}
+
+/// Build `commit` once, then benchmark it at each of `values` of `-dbcache`,
+/// smallest first. Runs that fail, including by running out of memory, are part of
+/// the summary rather than errors.
+async fn dbcache_sweep(
+    commit: String,
+    config: &Config,
+    options: &RunOptions,
+    values: &[u64],
+) -> Result<sweep::Summary> {
+    if config.benchmark != Benchmark::Ibd {
+        anyhow::bail!("dbcache-sweep needs the ibd benchmark");
+    }
+    if let Some(&value) = values.iter().find(|&&value| value < bitcoind::MIN_DBCACHE) {
+        anyhow::bail!(
+            "dbcache value {} is below bitcoind's minimum of {} MB",
+            value,
+            bitcoind::MIN_DBCACHE
+        );
+    }
+    let mut values = values.to_vec();
+    values.sort_unstable();
+    values.dedup();
+    let build = {
+        let (config, options) = (config.clone(), options.clone());
+        tokio::task::spawn_blocking(move || -> Result<PipelineRun> {
+            let mut build =
+                PipelineRun::new(&commit, &Uuid::new_v4().to_string(), &config, &options)?;
+            if config.pipeline.steps().contains(&Phase::Fetch) {
+                fetch_step(&mut build, &config, &options)?;
+            }
+            build_step(&mut build, &config, &options)?;
+            Ok(build)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Task failed: {}", e))??
+    };
+
+    let mut points = Vec::new();
+    for dbcache in values {
+        let run_config = Config {
+            dbcache,
+            ..config.clone()
+        };
+        println!("Benchmarking {} with -dbcache={}", build.commit, dbcache);
+        let mut run = PipelineRun {
+            commit: build.commit.clone(),
+            results_path: results_json_path(config, &build.commit),
+            metadata: RunMetadata {
+                run_id: Some(Uuid::new_v4().to_string()),
+                dbcache: Some(dbcache),
+                ..build.metadata.clone()
+            },
+            build_command: build.build_command.clone(),
+            ids: Vec::new(),
+            checkpoints: Vec::new(),
+            reached_heights: Vec::new(),
+            phases: Vec::new(),
+            failed_assertions: 0,
+        };
+        let options = options.clone();
+        let outcome = tokio::task::spawn_blocking(move || -> Result<Option<f64>> {
+            if bench_step(&mut run, &run_config, &options)?.is_break() {
+                anyhow::bail!("skipped for load");
+            }
+            save_step(&mut run, &run_config, &options)?;
+            let conn = db::open_db(&run_config.db_path)?;
+            Ok(db::latest_results(&conn, &run.commit)?
+                .iter()
+                .find(|result| run.ids.contains(&result.id))
+                .and_then(|result| result.mean))
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Task failed: {}", e))?;
+
+        points.push(match outcome {
+            Ok(mean) => sweep::Point {
+                dbcache,
+                mean,
+                oom: false,
+                error: None,
+            },
+            Err(e) => {
+                let oom = matches!(
+                    e.downcast_ref::<BenchError>(),
+                    Some(BenchError::Hyperfine { oom: true, .. })
+                );
+                if oom {
+                    eprintln!("bitcoind ran out of memory with -dbcache={}", dbcache);
+                } else {
+                    eprintln!("Error benchmarking -dbcache={}: {:?}", dbcache, e);
+                }
+                // The first line, as the output tail was printed above already
+                let error =
+                    (!oom).then(|| e.to_string().lines().next().unwrap_or_default().to_string());
+                sweep::Point {
+                    dbcache,
+                    mean: None,
+                    oom,
+                    error,
+                }
+            }
+        });
+        cooldown(config).await;
+    }
+    Ok(sweep::summarize(build.commit, points))
+}
+
+/// Build `a` and `b`, then time them in one hyperfine invocation, so both run
+/// under the same conditions and hyperfine compares them itself.
+async fn ab(
+    a: String,
+    b: String,
+    config: &Config,
+    options: &RunOptions,
+    runs: usize,
+) -> Result<ab::Summary> {
+    if config.benchmark != Benchmark::Ibd {
+        anyhow::bail!("ab needs the ibd benchmark");
+    }
+    if !config.pipeline.steps().contains(&Phase::Fetch) {
+        anyhow::bail!("ab checks out both commits, which the build_from_checkout pipeline can't");
+    }
+    if runs == 0 {
+        anyhow::bail!("--runs must be at least 1");
+    }
+    let (config, options) = (config.clone(), options.clone());
+    tokio::task::spawn_blocking(move || ab_pipeline(&a, &b, &config, &options, runs))
+        .await
+        .map_err(|e| anyhow::anyhow!("Task failed: {}", e))?
+}
+
+fn ab_pipeline(
+    a: &str,
+    b: &str,
+    config: &Config,
+    options: &RunOptions,
+    runs: usize,
+) -> Result<ab::Summary> {
+    let runner = runner::for_config(config);
+    let group = Uuid::new_v4().to_string();
+    // Each side's build is copied aside with its own datadir, so neither run
+    // starts from the other's chain
+    let ab_dir = config.datadir.join("ab");
+    let builds_dir = config.builds_dir();
+    let mut sides = Vec::new();
+    for (name, commit) in [("a", a), ("b", b)] {
+        let mut run = PipelineRun::new(commit, &Uuid::new_v4().to_string(), config, options)?;
+        fetch_step(&mut run, config, options)?;
+        build_step(&mut run, config, options)?;
+        let build_dir = builds_dir.join(name);
+        let binary = build_dir.join("bitcoind").display().to_string();
+        let side_config = Config {
+            datadir: ab_dir.join(name).join("datadir"),
+            ..config.clone()
+        };
+        let datadir = side_config.datadir.display().to_string();
+        run_checked(runner.as_ref(), "mkdir", &["-p", &datadir])?;
+        run_checked(
+            runner.as_ref(),
+            "mkdir",
+            &["-p", &build_dir.display().to_string()],
+        )?;
+        run_checked(runner.as_ref(), "cp", &[bitcoind::BITCOIND, &binary])?;
+        run.metadata.ab_group = Some(group.clone());
+        sides.push((run, side_config, binary));
+    }
+    // Both sides start from the same seed, synced by the last build
+    let seed_height = seed::ensure(config, runner.as_ref(), bitcoind::BITCOIND)?;
+
+    let results_path = results_json_path(config, &format!("ab-{}", group));
+    let mut args: Vec<String> = ["--shell", &config.shell, "--runs", &runs.to_string()]
+        .map(String::from)
+        .to_vec();
+    // One prepare per command, each wiping only its own datadir
+    for (run, side_config, binary) in &sides {
+        let snapshot_result = results_path.with_extension(format!("{}.snapshot.json", run.commit));
+        args.push("--prepare".to_string());
+        args.push(bitcoind::prepare_command_using(
+            side_config,
+            &snapshot_result,
+            binary,
+        )?);
+    }
+    if !config.keep_datadir {
+        let wipe: Vec<String> = sides
+            .iter()
+            .map(|(_, side_config, _)| format!("rm -Rf {}/*", side_config.datadir.display()))
+            .collect();
+        args.extend(["--cleanup".to_string(), wipe.join(" && ")]);
+    }
+    args.extend(
+        [
+            "--show-output",
+            "--export-json",
+            &results_path.to_string_lossy(),
+        ]
+        .map(String::from),
+    );
+    let commands: Vec<String> = sides
+        .iter()
+        .map(|(_, side_config, binary)| {
+            with_extra_args(config, bitcoind::command_using(side_config, binary))
+        })
+        .collect();
+    args.extend(commands.iter().cloned());
+
+    enter_checkout(runner.as_ref(), config)?;
+    println!(
+        "Timing {} against {} in one hyperfine run",
+        sides[1].0.commit, sides[0].0.commit
+    );
+    let hyperfine_version = hyperfine_version(runner.as_ref());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (status, output_tail) = process::run_with_output_tail(
+        &mut runner.command("hyperfine", &args),
+        BENCH_ERROR_TAIL_LINES,
+    )
+    .with_context(|| "Failed to execute hyperfine command")?;
+    if !status.success() {
+        let exit_code = exit_code::from_output(&output_tail);
+        return Err(BenchError::Hyperfine {
+            oom: exit_code.is_some_and(exit_code::is_sigkill),
+            command: commands.join("; "),
+            status,
+            exit_code,
+            output_tail,
+        }
+        .into());
+    }
+    runner.fetch_file(&results_path, &results_path)?;
+    let results = db::read_results_file(&results_path)?;
+    let _ = fs::remove_file(&results_path);
+    if results.results.len() != sides.len() {
+        anyhow::bail!(
+            "hyperfine exported {} results for the {} commands",
+            results.results.len(),
+            sides.len()
+        );
+    }
+    if !config.keep_datadir {
+        run_checked(
+            runner.as_ref(),
+            "rm",
+            &["-Rf", &ab_dir.display().to_string()],
+        )?;
+    }
+
+    let conn = db::open_db(&config.db_path)?;
+    let mut stored = Vec::new();
+    for ((run, side_config, _), result) in sides.iter_mut().zip(results.results) {
+        let snapshot_height = match &config.assumeutxo_snapshot {
+            Some(_) => {
+                let snapshot_result =
+                    results_path.with_extension(format!("{}.snapshot.json", run.commit));
+                runner.fetch_file(&snapshot_result, &snapshot_result)?;
+                let height = bitcoind::snapshot_height(&snapshot_result)?;
+                let _ = fs::remove_file(&snapshot_result);
+                Some(height)
+            }
+            None => None,
+        };
+        run.metadata.snapshot_height = snapshot_height;
+        run.metadata.start_height = match config.scenario() {
+            Some(_) => seed_height,
+            None => Some(snapshot_height.unwrap_or(0)),
+        };
+        run.metadata.stop_height = Some(side_config.stopatheight);
+        run.metadata.hyperfine_version = hyperfine_version.clone();
+        let (mean, stddev) = (result.mean, result.stddev);
+        let ids = db::insert_results(
+            &conn,
+            &run.commit,
+            HyperfineResults {
+                results: vec![result],
+            },
+            &run.metadata,
+        )?;
+        stored.push(ab::Side {
+            commit: run.commit.clone(),
+            mean,
+            stddev,
+            id: ids[0],
+        });
+    }
+    let b = stored.pop().expect("two sides");
+    let a = stored.pop().expect("two sides");
+    Ok(ab::Summary { group, a, b })
+}
+
+/// Run `program` through `runner`, failing if it does.
+fn run_checked(runner: &dyn Runner, program: &str, args: &[&str]) -> Result<()> {
+    let status = runner
+        .command(program, args)
+        .status()
+        .with_context(|| format!("Failed to execute {}", program))?;
+    if !status.success() {
+        anyhow::bail!("`{} {}` failed with {}", program, args.join(" "), status);
+    }
+    Ok(())
+}
+
+/// Command timed by `selftest`, which only needs the build to have worked.
+const SELFTEST_COMMAND: &str = "./build/src/bitcoind --version";
+
+/// Run every pipeline step for `commit` as a run would, but time a near-instant
+/// command and store the results in a temporary database that is removed again.
+async fn selftest(commit: String, config: &Config) -> Result<()> {
+    let dir = config
+        .temp_dir()
+        .join(format!("bench-selftest-{}", std::process::id()));
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let runner = runner::for_config(config);
+    let remote_dir = dir.to_string_lossy().to_string();
+    if !runner.is_local() {
+        run_checked(runner.as_ref(), "mkdir", &["-p", &remote_dir])?;
+    }
+    let mut config = config.clone();
+    config.db_path = dir.join("results.db");
+    let options = RunOptions {
+        results_file: Some(dir.join("results.json")),
+        ..Default::default()
+    };
+
+    let outcome = tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut run = PipelineRun::new(&commit, &Uuid::new_v4().to_string(), &config, &options)?;
+        if config.pipeline.steps().contains(&Phase::Fetch) {
+            println!("selftest: fetching {}", commit);
+            fetch_step(&mut run, &config, &options)?;
+        }
+        println!("selftest: building {}", run.commit);
+        build_step(&mut run, &config, &options)?;
+
+        println!("selftest: benchmarking `{}`", SELFTEST_COMMAND);
+        // Timed where it was built, which writes the results there
+        let runner = runner::for_config(&config);
+        let results_path = run.results_path.to_string_lossy().to_string();
+        let mut hyperfine = runner.command(
+            "hyperfine",
+            &[
+                "--shell",
+                &config.shell,
+                "--parameter-list",
+                "commit",
+                &run.commit,
+                "--runs",
+                "2",
+                "--export-json",
+                &results_path,
+                SELFTEST_COMMAND,
+            ],
+        );
+        let (status, output_tail) =
+            process::run_with_output_tail(&mut hyperfine, BENCH_ERROR_TAIL_LINES)
+                .with_context(|| "Failed to execute hyperfine command")?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "Hyperfine command failed with status {}:\n{}",
+                status,
+                output_tail
+            ));
+        }
+        runner.fetch_file(&run.results_path, &run.results_path)?;
+        check_results_exported(&run.results_path)?;
+
+        println!("selftest: saving to {}", config.db_path.display());
+        save_step(&mut run, &config, &options)?;
+        let conn = db::open_db(&config.db_path)?;
+        if db::latest_results(&conn, &run.commit)?.is_empty() {
+            anyhow::bail!("No result was stored for {}", run.commit);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task failed: {}", e))?;
+
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        eprintln!("Warning: failed to remove {}: {}", dir.display(), e);
+    }
+    if !runner.is_local() {
+        if let Err(e) = run_checked(runner.as_ref(), "rm", &["-Rf", &remote_dir]) {
+            eprintln!(
+                "Warning: failed to remove {} on ssh_host: {:#}",
+                remote_dir, e
+            );
+        }
+    }
+    outcome.with_context(|| "Selftest failed")?;
+    println!("selftest: ok");
+    Ok(())
+}
+
+/// State carried from one pipeline step to the next.
+struct PipelineRun {
+    /// The commit as given, replaced by its full hash once fetched
+    commit: String,
+    results_path: PathBuf,
+    metadata: RunMetadata,
+    /// Command the build step ran
+    build_command: Option<String>,
+    /// Ids of the stored rows
+    ids: Vec<i64>,
+    /// Checkpoints each result reached, in the order of the results
+    checkpoints: Vec<Vec<checkpoints::Checkpoint>>,
+    /// Height each IBD result synced to, in the order of the results
+    reached_heights: Vec<Option<u64>>,
+    /// Phases each IBD result went through, in the order of the results
+    phases: Vec<Vec<checkpoints::SyncPhase>>,
+    /// How many of the stored rows violated an assertion
+    failed_assertions: usize,
+}
+
+/// What [`run_pipeline`] stored.
+struct PipelineOutcome {
+    /// Ids of the stored rows
+    ids: Vec<i64>,
+    /// How many of `ids` violated an assertion
+    failed_assertions: usize,
+}
+
+impl PipelineRun {
+    /// Start a run of `commit`. Without a fetch step, `commit` must be what the
+    /// repository has checked out.
+    fn new(commit: &str, run_id: &str, config: &Config, options: &RunOptions) -> Result<Self> {
+        let commit = if config.pipeline.steps().contains(&Phase::Fetch) {
+            commit.to_string()
+        } else {
+            checked_out_commit(commit, config)?
+        };
+
+        Ok(PipelineRun {
+            commit: commit.clone(),
+            results_path: options
+                .results_file
+                .clone()
+                .unwrap_or_else(|| results_json_path(config, &commit)),
+            metadata: RunMetadata {
+                run_id: Some(run_id.to_string()),
+                // The benchmark runs on the SSH host, not where it was started
+                machine: config.ssh_host.clone().or_else(system::hostname),
+                par: config.par,
+                dbcache: (config.benchmark == Benchmark::Ibd).then_some(config.dbcache),
+                batch_id: options.batch.as_ref().map(|(id, _)| id.clone()),
+                batch_position: options.batch.as_ref().map(|&(_, position)| position),
+                storage: options.storage.clone(),
+                repo_url: options.repo_url.clone(),
+                commit_alias: options.commit_alias.clone(),
+                run_label: options.label.clone(),
+                assumevalid: config.assumevalid.clone(),
+                assumeutxo_snapshot: config
+                    .assumeutxo_snapshot
+                    .as_ref()
+                    .map(|path| path.display().to_string()),
+                cgroup_memory: config.cgroup_memory.clone(),
+                cgroup_cpu: config.cgroup_cpu.clone(),
+                isolate_cpus: config.isolate_cpus.clone(),
+                rpc_command: config
+                    .rpc_command
+                    .clone()
+                    .filter(|_| config.benchmark == Benchmark::Rpc),
+                ..Default::default()
+            },
+            build_command: None,
+            ids: Vec::new(),
+            checkpoints: Vec::new(),
+            reached_heights: Vec::new(),
+            phases: Vec::new(),
+            failed_assertions: 0,
+        })
+    }
+}
+
+/// Run the steps of the configured pipeline that `options` selects for `commit`,
+/// returning the ids of any stored rows and how many violated an assertion.
+fn run_pipeline(
+    commit: &str,
+    run_id: &str,
+    config: &Config,
+    options: &RunOptions,
+) -> Result<PipelineOutcome> {
+    let mut run = PipelineRun::new(commit, run_id, config, options)?;
+    for &step in config.pipeline.steps() {
+        if !options.runs(step) {
+            continue;
+        }
+        let flow = match step {
+            Phase::Fetch => fetch_step(&mut run, config, options).map(ControlFlow::Continue),
+            Phase::Build => build_step(&mut run, config, options).map(ControlFlow::Continue),
+            // A guix run's build is what it benchmarks
+            Phase::Bench if config.benchmark == Benchmark::Guix => Ok(ControlFlow::Continue(())),
+            Phase::Bench => bench_step(&mut run, config, options),
+            Phase::Save if config.benchmark == Benchmark::Guix => {
+                save_guix_step(&mut run, config, options).map(ControlFlow::Continue)
+            }
+            Phase::Save => match options.print_results {
+                Some((format, style)) => print_step(&run, format, &style, options),
+                None => save_step(&mut run, config, options),
+            }
+            .map(ControlFlow::Continue),
+        }?;
+        if flow.is_break() {
+            break;
+        }
+    }
+    let stop_height = run.metadata.stop_height.unwrap_or(config.stopatheight);
+    let incomplete = run
+        .reached_heights
+        .iter()
+        .any(|height| height.is_none_or(|height| height < stop_height));
+    if config.rr_on_incomplete && incomplete && options.stores() {
+        println!(
+            "Syncing {} again under rr, as a run stopped short of stopatheight",
+            run.commit
+        );
+        if let Err(e) = record_step(&run, config) {
+            eprintln!("Warning: failed to record the incomplete run: {:#}", e);
+        }
+    }
+    Ok(PipelineOutcome {
+        ids: run.ids,
+        failed_assertions: run.failed_assertions,
+    })
+}
+
+/// The full hash of `commit`, failing unless it is what the repository has checked
+/// out, for pipelines that benchmark the checkout as it is instead of fetching it.
+fn checked_out_commit(commit: &str, config: &Config) -> Result<String> {
+    let head = git::resolve_commit(&config.repo_path, "HEAD")?;
+    let commit_hash = git::resolve_commit(&config.repo_path, commit)?;
+    if head != commit_hash {
+        return Err(anyhow::anyhow!(
+            "The build_from_checkout pipeline benchmarks the current checkout, which is {}, not {}",
+            head,
+            commit
+        ));
+    }
+    Ok(head)
+}
+
+/// Fetch and check out the commit.
+fn fetch_step(
+    run: &mut PipelineRun,
+    config: &Config,
+    options: &RunOptions,
+) -> Result<(), BenchError> {
+    let runner = runner::for_config(config);
+    let checkout = git::update_repository(
+        &run.commit,
+        runner.as_ref(),
+        options.repo_url.as_deref(),
+        options.fetch,
+        git::CheckoutOptions {
+            force: config.force_checkout,
+            submodules: config.update_submodules,
+            dirty: config.dirty_policy,
+        },
+    )?;
+    run.commit = checkout.commit_hash;
+    run.metadata.parent_hash = checkout.parent_hash;
+    run.metadata.pr_number = checkout.subject.as_deref().and_then(git::pr_number);
+    run.metadata.commit_subject = checkout.subject;
+    run.metadata.dirty_action = checkout
+        .dirty_action
+        .map(|action| action.name().to_string());
+    if let Some(diff) = checkout.src_diff {
+        run.metadata.src_files_changed = Some(diff.files_changed);
+        run.metadata.src_insertions = Some(diff.insertions);
+        run.metadata.src_deletions = Some(diff.deletions);
+    }
+    Ok(())
+}
+
+/// Build bitcoind from the checkout, recording a failed build when saving.
+fn build_step(
+    run: &mut PipelineRun,
+    config: &Config,
+    options: &RunOptions,
+) -> Result<(), BenchError> {
+    let runner = runner::for_config(config);
+    // Recorded before building, as a toolchain upgrade can also break the build
+    run.metadata.toolchain = Toolchain::detect(runner.as_ref(), &config.shell).to_json();
+    let build = match build_bitcoin(runner.as_ref(), config) {
+        Ok(build) => build,
+        Err(e) => {
+            if let (
+                BenchError::Build {
+                    command,
+                    output_tail,
+                    ..
+                },
+                true,
+            ) = (&e, options.stores())
+            {
+                if let Err(db_err) = db::record_build_failure(
+                    &config.db_path,
+                    &run.commit,
+                    command,
+                    output_tail,
+                    &run.metadata,
+                ) {
+                    eprintln!("Failed to record build failure: {:?}", db_err);
+                }
+            }
+            return Err(e);
+        }
+    };
+    run.build_command = Some(build.command);
+    run.metadata.build_secs = Some(build.duration_secs);
+    run.metadata.build_jobs = Some(build.jobs);
+    run.metadata.compiler_cache = build.compiler_cache.map(|cache| cache.name().to_string());
+    run.metadata.cmake_generator = config
+        .cmake_generator
+        .filter(|_| config.benchmark != Benchmark::Guix)
+        .map(|generator| generator.cmake_name().to_string());
+    run.metadata.cache_hit_rate = build.cache_hit_rate;
+    run.metadata.guix_output_hash = build.output_hash;
+    run.metadata.bitcoind_version = bitcoind::version(runner.as_ref());
+    Ok(())
+}
+
+/// Run the benchmark. Breaks out of the pipeline when the run is skipped for load or
+/// swap use.
+fn bench_step(
+    run: &mut PipelineRun,
+    config: &Config,
+    options: &RunOptions,
+) -> Result<ControlFlow<()>, BenchError> {
+    let runner = runner::for_config(config);
+    // This machine's load, CPU and kernel log say nothing about a remote run
+    let local = runner.is_local();
+    let load_start = local.then(system::sample_load);
+    if let (Some(max_load), Some(load_start)) = (config.max_load, &load_start) {
+        if load_start.load_avg > max_load {
+            match config.load_policy {
+                LoadPolicy::Warn => eprintln!(
+                    "Warning: load average {:.2} exceeds max_load {:.2}, results may be noisy",
+                    load_start.load_avg, max_load
+                ),
+                LoadPolicy::Skip => {
+                    eprintln!(
+                        "Skipping benchmark of {}: load average {:.2} exceeds max_load {:.2}",
+                        run.commit, load_start.load_avg, max_load
+                    );
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+        }
+    }
+    if let (Some(max_swap_mb), Some(load_start)) = (config.max_swap_used_mb, &load_start) {
+        let swap_mb = load_start.swap_used / 1_000_000;
+        if swap_mb > max_swap_mb {
+            match config.swap_policy {
+                LoadPolicy::Warn => eprintln!(
+                    "Warning: {} MB of swap in use exceeds max_swap_used_mb {}, results may be noisy",
+                    swap_mb, max_swap_mb
+                ),
+                LoadPolicy::Skip => {
+                    eprintln!(
+                        "Skipping benchmark of {}: {} MB of swap in use exceeds max_swap_used_mb {}",
+                        run.commit, swap_mb, max_swap_mb
+                    );
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+        }
+    }
+
+    // Appending runs continue from where the previous one left the datadir. They
+    // can't be retried, as the chain has moved on by the time they finish.
+    let append_config;
+    let (config, append_start) = if options.append {
+        if !local {
+            return Err(anyhow::anyhow!(
+                "--append-to-existing tracks the datadir locally and cannot run over ssh_host"
+            )
+            .into());
+        }
+        if config.benchmark != Benchmark::Ibd || config.assumeutxo_snapshot.is_some() {
+            return Err(anyhow::anyhow!(
+                "--append-to-existing needs the ibd benchmark without an assumeutxo_snapshot"
+            )
+            .into());
+        }
+        let start = bitcoind::appended_height(config)?;
+        append_config = Config {
+            stopatheight: start + config.append_chunk_blocks,
+            flaky_retries: 0,
+            ..config.clone()
+        };
+        (&append_config, Some(start))
+    } else {
+        (config, None)
+    };
+    let seed_height = if options.append {
+        None
+    } else {
+        seed::ensure(config, runner.as_ref(), bitcoind::BITCOIND)?
+    };
+
+    let previous_governors = if local {
+        system::cpu_governors()
+    } else {
+        Vec::new()
+    };
+    if let Some(governor) = &config.cpu_governor {
+        if !local {
+            eprintln!("Warning: cpu_governor is not applied over ssh_host");
+        } else if previous_governors.is_empty() {
+            eprintln!("Warning: CPU frequency scaling is not available, cannot set governor");
+        } else if let Err(e) = system::set_cpu_governors(&previous_governors, governor) {
+            eprintln!(
+                "Warning: failed to set CPU governor to {}: {:#}",
+                governor, e
+            );
+        }
+    }
+    // Record what is actually in effect, which differs from the request on failure
+    if local {
+        run.metadata.cpu_governor = system::governor_summary(&system::cpu_governors());
+    }
+    if let (Some(cpus), true) = (&config.isolate_cpus, local) {
+        let pinned = system::parse_cpu_list(cpus)?;
+        run.metadata.kernel_isolated =
+            system::isolated_cpus().map(|isolated| pinned.iter().all(|cpu| isolated.contains(cpu)));
+        if run.metadata.kernel_isolated == Some(false) {
+            eprintln!(
+                "Warning: CPUs {} are not all in the kernel's isolcpus, other processes \
+                 can still be scheduled on them",
+                cpus
+            );
+        }
+    }
+
+    // Only the CPUs bitcoind is pinned to say whether it ran throttled
+    let pinned = match &config.isolate_cpus {
+        Some(cpus) => Some(system::parse_cpu_list(cpus)?),
+        None => None,
+    };
+    let sampler = (local && config.thermal_sample_secs > 0)
+        .then(|| thermal::Sampler::start(Duration::from_secs(config.thermal_sample_secs), pinned));
+    run.metadata.hyperfine_version = hyperfine_version(runner.as_ref());
+    let bench = runner
+        .command("mkdir", &["-p", &config.datadir.to_string_lossy()])
+        .status()
+        .with_context(|| "Failed to execute mkdir")
+        .and_then(|status| {
+            status.success().then_some(()).ok_or_else(|| {
+                anyhow::anyhow!("Failed to create datadir at {}", config.datadir.display())
+            })
+        })
+        .map_err(BenchError::from)
+        .and_then(|()| {
+            smoke_run(
+                &run.commit,
+                config,
+                runner.as_ref(),
+                &run.results_path,
+                options,
+            )
+        })
+        .and_then(|()| {
+            run_hyperfine_with_retries(
+                &run.commit,
+                config,
+                runner.as_ref(),
+                &run.results_path,
+                options,
+            )
+        });
+    if local && config.cpu_governor.is_some() {
+        if let Err(e) = system::restore_cpu_governors(&previous_governors) {
+            eprintln!("Warning: failed to restore CPU governors: {:#}", e);
+        }
+    }
+    if let Some(sampler) = sampler {
+        let thermal = sampler.finish();
+        if thermal.throttled == Some(true) {
+            eprintln!("Warning: the CPU throttled during the benchmark, results are suspect");
+        }
+        run.metadata.throttled = thermal.throttled;
+        run.metadata.max_temp_c = thermal.max_temp_c;
+        run.metadata.min_cpu_mhz = thermal.min_freq_mhz;
+    }
+    if let Some(log_dir) = &config.log_dir {
+        match keep_debug_log(run, log_dir) {
+            Ok(kept) => run.metadata.log_path = kept.map(|path| path.display().to_string()),
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+        if config.keep_command_output {
+            match keep_command_output(run, log_dir) {
+                Ok(kept) => run.metadata.output_path = kept.map(|path| path.display().to_string()),
+                Err(e) => eprintln!("Warning: {:#}", e),
+            }
+        }
+    } else {
+        // Only collected to time the checkpoints
+        let _ = fs::remove_file(debug_log_file(&run.results_path));
+    }
+    if let (
+        Err(BenchError::Hyperfine {
+            command,
+            oom: true,
+            output_tail,
+            ..
+        }),
+        true,
+    ) = (&bench, options.stores())
+    {
+        if let Err(db_err) = db::record_oom(
+            &config.db_path,
+            &run.commit,
+            command,
+            output_tail,
+            &run.metadata,
+        ) {
+            eprintln!("Failed to record out of memory run: {:?}", db_err);
+        }
+    }
+    let BenchOutcome {
+        snapshot_height,
+        retries,
+        checkpoints,
+        reached_heights,
+        phases,
+    } = bench?;
+    for height in &reached_heights {
+        match height {
+            Some(height) if *height >= config.stopatheight => {
+                println!("Reached height {} of {}", height, config.stopatheight)
+            }
+            _ => eprintln!(
+                "Warning: a run only reached height {} of {}, storing it as incomplete",
+                height.map_or("none".to_string(), |height| height.to_string()),
+                config.stopatheight
+            ),
+        }
+    }
+    run.reached_heights = reached_heights;
+    for phases in &phases {
+        let timings: Vec<String> = phases
+            .iter()
+            .map(|phase| format!("{} {:.1}s", phase.name, phase.secs))
+            .collect();
+        match checkpoints::time_to_first_block(phases) {
+            Some(secs) => println!(
+                "Phases: {}, first block after {:.1}s",
+                timings.join(", "),
+                secs
+            ),
+            None if !timings.is_empty() => println!("Phases: {}", timings.join(", ")),
+            None => {}
+        }
+    }
+    run.phases = phases;
+    run.metadata.retries = Some(retries);
+    for checkpoint in checkpoints.iter().flatten() {
+        println!(
+            "Reached height {} after {:.1}s, {:.1}s since the previous checkpoint",
+            checkpoint.height, checkpoint.cumulative_secs, checkpoint.segment_secs
+        );
+    }
+    run.checkpoints = checkpoints;
+    if config.benchmark == Benchmark::Ibd {
+        match bitcoind::datadir_size(&datadir_size_file(&run.results_path)) {
+            Ok(size) => run.metadata.datadir_size_bytes = Some(size),
+            Err(e) => eprintln!("Warning: failed to measure the datadir: {:#}", e),
+        }
+    }
+    if let Some(start) = append_start {
+        run.metadata.start_height = Some(start);
+        run.metadata.stop_height = Some(config.stopatheight);
+        run.metadata.height_delta = Some(config.stopatheight - start);
+        // Continue the next chunk from where this one actually got to
+        let synced = run
+            .reached_heights
+            .iter()
+            .map(|height| height.map_or(start, |height| height.clamp(start, config.stopatheight)))
+            .min()
+            .unwrap_or(config.stopatheight);
+        bitcoind::record_appended_height(config, synced)?;
+        println!(
+            "Datadir at {} is now synced to height {}",
+            config.datadir.display(),
+            synced
+        );
+    } else if config.benchmark == Benchmark::Ibd {
+        // The prepare step wipes the datadir, so every run syncs from genesis
+        // unless it restored a seed or loaded a snapshot
+        run.metadata.start_height = match config.scenario() {
+            Some(_) => seed_height,
+            None => Some(snapshot_height.unwrap_or(0)),
+        };
+        run.metadata.snapshot_height = snapshot_height;
+        run.metadata.stop_height = Some(config.stopatheight);
+        if config.keep_datadir {
+            println!("Kept datadir at {}", config.datadir.display());
+            eprintln!("Warning: the next run's prepare step will wipe this datadir");
+        }
+    }
+
+    if let Some(load_start) = load_start {
+        let load_end = system::sample_load();
+        run.metadata.load_start = Some(load_start.load_avg);
+        run.metadata.load_end = Some(load_end.load_avg);
+        run.metadata.steal_pct = system::steal_pct(&load_start, &load_end);
+        if let Some((pages_in, pages_out)) = system::swapped_pages(&load_start, &load_end) {
+            if pages_in + pages_out > 0 {
+                eprintln!(
+                    "Warning: {} pages were swapped in and {} out during the run, results may be noisy",
+                    pages_in, pages_out
+                );
+            }
+            run.metadata.swap_in_pages = Some(pages_in);
+            run.metadata.swap_out_pages = Some(pages_out);
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+/// Import the results into the database.
+fn save_step(
+    run: &mut PipelineRun,
+    config: &Config,
+    options: &RunOptions,
+) -> Result<(), BenchError> {
+    let results = db::read_results_file(&run.results_path).map_err(|source| BenchError::Parse {
+        path: run.results_path.clone(),
+        source,
+    })?;
+    check_results_match(&results, &run.commit, options);
+    let failed_assertions = check_assertions(run, config, &results)?;
+    let details = db::ResultDetails {
+        checkpoints: &run.checkpoints,
+        reached_heights: &run.reached_heights,
+        stop_height: run.metadata.stop_height.unwrap_or(config.stopatheight),
+        phases: &run.phases,
+        failed_assertions: &failed_assertions,
+    };
+    run.ids = db::open_db(&config.db_path)
+        .and_then(|conn| {
+            db::insert_results_with(&conn, &run.commit, results, &run.metadata, &details)
+        })
+        .map_err(|source| BenchError::Db {
+            path: config.db_path.clone(),
+            source,
+        })?;
+    // Only clean up files we generated, never one the user pointed us at
+    if options.results_file.is_none() {
+        fs::remove_file(&run.results_path).with_context(|| {
+            format!(
+                "Failed to remove results file at {}",
+                run.results_path.display()
+            )
+        })?;
+    }
+    run.failed_assertions = failed_assertions.iter().filter(|f| !f.is_empty()).count();
+    Ok(())
+}
+
+/// Store a `guix` build as a built row, failing if its output differs from that of
+/// the newest earlier build of the commit by the same command, which it should
+/// reproduce. Printing results instead only checks it.
+fn save_guix_step(
+    run: &mut PipelineRun,
+    config: &Config,
+    options: &RunOptions,
+) -> Result<(), BenchError> {
+    let (Some(command), Some(hash)) = (&run.build_command, &run.metadata.guix_output_hash) else {
+        return Err(anyhow::anyhow!(
+            "The guix benchmark stores its build, so it needs the build phase"
+        )
+        .into());
+    };
+    println!(
+        "{} built by guix in {:.1}s, output hash {}",
+        run.commit,
+        run.metadata.build_secs.unwrap_or_default(),
+        hash
+    );
+    let db_error = |source| BenchError::Db {
+        path: config.db_path.clone(),
+        source,
+    };
+    let previous = db::open_db(&config.db_path)
+        .and_then(|conn| db::previous_guix_output(&conn, &run.commit, command))
+        .map_err(db_error)?;
+    if options.print_results.is_none() {
+        let id = db::record_build(&config.db_path, &run.commit, command, &run.metadata)
+            .map_err(db_error)?;
+        run.ids.push(id);
+    }
+    match previous {
+        Some((id, previous)) if previous != *hash => Err(anyhow::anyhow!(
+            "The guix build of {} is not reproducible: its output hash {} differs from {} of result {}",
+            run.commit,
+            hash,
+            previous,
+            id
+        )
+        .into()),
+        Some((id, _)) => {
+            println!("Reproduced the output of result {}", id);
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// The configured assertions each of `results` violates, warning about each.
+fn check_assertions(
+    run: &PipelineRun,
+    config: &Config,
+    results: &HyperfineResults,
+) -> Result<Vec<Vec<String>>> {
+    let assertions = assertions::parse_all(&config.assertions)?;
+    let stop_height = run.metadata.stop_height.unwrap_or(config.stopatheight);
+    let mut failed = Vec::new();
+    for (i, result) in results.results.iter().enumerate() {
+        let subject = assertions::Subject {
+            mean: result.mean,
+            stddev: result.stddev,
+            median: result.median,
+            min: result.min,
+            max: result.max,
+            exit_codes: &result.exit_codes,
+            reached_height: run.reached_heights.get(i).copied().flatten(),
+            stop_height,
+        };
+        let mut violated = Vec::new();
+        for assertion in &assertions {
+            if let Some(why) = assertion.violation(&subject) {
+                eprintln!(
+                    "Warning: `{}` failed assertion `{}`: {}",
+                    result.command, assertion, why
+                );
+                violated.push(assertion.to_string());
+            }
+        }
+        failed.push(violated);
+    }
+    Ok(failed)
+}
+
+/// Warn when hyperfine reported other results than the run's parameter lists
+/// expand to, e.g. for a `--param` value containing an unintended comma. Each
+/// result is stored under the commit of its own parameters regardless.
+fn check_results_match(results: &HyperfineResults, commit: &str, options: &RunOptions) {
+    let expected: usize = options
+        .params
+        .iter()
+        .map(|(_, values)| values.split(',').count())
+        .product();
+    if results.results.len() != expected {
+        eprintln!(
+            "Warning: hyperfine reported {} results where the parameter lists expand to {}",
+            results.results.len(),
+            expected
+        );
+    }
+    for result in &results.results {
+        if let Some(parameters) = result
+            .parameters
+            .as_ref()
+            .filter(|parameters| parameters.commit != commit)
+        {
+            eprintln!(
+                "Warning: `{}` was timed for commit {} rather than {}, storing it under {}",
+                result.command, parameters.commit, commit, parameters.commit
+            );
+        }
+    }
+}
+
+/// Print the results instead of importing them, for runs that aren't stored.
+fn print_step(
+    run: &PipelineRun,
+    format: Format,
+    style: &output::Style,
+    options: &RunOptions,
+) -> Result<(), BenchError> {
+    let results = db::read_results_file(&run.results_path).map_err(|source| BenchError::Parse {
+        path: run.results_path.clone(),
+        source,
+    })?;
+    output::render(&results, format, style)?;
+    if options.results_file.is_none() {
+        fs::remove_file(&run.results_path).with_context(|| {
+            format!(
+                "Failed to remove results file at {}",
+                run.results_path.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Where the cleanup step records the size of the datadir the run synced.
+fn datadir_size_file(results_path: &Path) -> PathBuf {
+    results_path.with_extension("datadir_size")
+}
+
+/// Where the cleanup step collects the `debug.log` of each timed run, before it is
+/// moved into `log_dir`.
+fn debug_log_file(results_path: &Path) -> PathBuf {
+    results_path.with_extension("debug.log")
+}
+
+/// Whether the cleanup step collects each run's `debug.log`, to keep it in `log_dir`,
+/// time the checkpoints or check the sync reached `stopatheight`.
+fn collects_debug_log(config: &Config) -> bool {
+    config.log_dir.is_some() || config.benchmark == Benchmark::Ibd
+}
+
+/// Whether the run is timed at the configured checkpoints, which needs a sync from
+/// genesis.
+fn times_checkpoints(config: &Config, options: &RunOptions) -> bool {
+    !config.checkpoints.is_empty()
+        && config.benchmark == Benchmark::Ibd
+        && config.assumeutxo_snapshot.is_none()
+        && !options.append
+}
+
+/// Where hyperfine's `--output` writes the stdout of the latest timed command,
+/// which it truncates for every command. hyperfine only takes paths with a
+/// directory as files.
+fn command_output_scratch(results_path: &Path) -> PathBuf {
+    let scratch = results_path.with_extension("last_output");
+    if scratch.components().count() > 1 {
+        scratch
+    } else {
+        Path::new(".").join(scratch)
+    }
+}
+
+/// Where the cleanup step collects the stdout of every timed command, before it
+/// is moved into `log_dir`.
+fn command_output_file(results_path: &Path) -> PathBuf {
+    results_path.with_extension("output")
+}
+
+/// Whether hyperfine `version` can write command output to a file, which came with
+/// `--output` in 1.14.
+fn supports_output_file(version: Option<&str>) -> bool {
+    let Some(version) = version else {
+        return false;
+    };
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(major), Some(minor)) => (major, minor) >= (1, 14),
+        _ => false,
+    }
+}
+
+/// The cleanup step appending the latest command's output, headed by the
+/// parameters it ran with, to the collected output.
+fn collect_output_command(results_path: &Path, options: &RunOptions) -> String {
+    let mut header = "== commit={commit}".to_string();
+    for (name, _) in &options.params {
+        header.push_str(&format!(" {}={{{}}}", name, name));
+    }
+    // A command that printed nothing leaves no file behind
+    format!(
+        "{{ echo '{}' && cat {} || true; }} >> {}",
+        header,
+        command_output_scratch(results_path).display(),
+        command_output_file(results_path).display()
+    )
+}
+
+/// Move the `debug.log` the cleanup step collected into `log_dir`, named after the
+/// run, returning where it was kept.
+fn keep_debug_log(run: &PipelineRun, log_dir: &Path) -> Result<Option<PathBuf>> {
+    keep_collected(run, &debug_log_file(&run.results_path), log_dir, "log")
+}
+
+/// Move the command output the cleanup step collected into `log_dir`, named after
+/// the run, returning where it was kept.
+fn keep_command_output(run: &PipelineRun, log_dir: &Path) -> Result<Option<PathBuf>> {
+    let _ = fs::remove_file(command_output_scratch(&run.results_path));
+    keep_collected(
+        run,
+        &command_output_file(&run.results_path),
+        log_dir,
+        "output",
+    )
+}
+
+/// Move `collected` into `log_dir` as `<run_id>.<extension>`, if it was written.
+fn keep_collected(
+    run: &PipelineRun,
+    collected: &Path,
+    log_dir: &Path,
+    extension: &str,
+) -> Result<Option<PathBuf>> {
+    if !collected.exists() {
+        return Ok(None);
+    }
+    let run_id = run.metadata.run_id.as_deref().unwrap_or("unknown");
+    let kept = log_dir.join(format!("{}.{}", run_id, extension));
+    // Copied, as the log directory is often on another filesystem than the temp dir
+    fs::copy(collected, &kept).with_context(|| format!("Failed to keep {}", kept.display()))?;
+    fs::remove_file(collected)
+        .with_context(|| format!("Failed to remove {}", collected.display()))?;
+    Ok(Some(kept))
+}
+
+/// A results file path unique to this run, so concurrent or sequential runs never
+/// read each other's hyperfine output.
+fn results_json_path(config: &Config, commit: &str) -> PathBuf {
+    let commit: String = commit
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let timestamp = Utc::now().format("%Y%m%dT%H%M%S%.f");
+    config
+        .temp_dir()
+        .join(format!("results-{}-{}.json", commit, timestamp))
+}
+
+/// Number of trailing build output lines kept when a build fails.
+const BUILD_ERROR_TAIL_LINES: usize = 50;
+
+/// Lines of hyperfine output included in the error when the benchmark fails.
+const BENCH_ERROR_TAIL_LINES: usize = 50;
+
+struct BuildInfo {
+    command: String,
+    duration_secs: f64,
+    jobs: usize,
+    compiler_cache: Option<CompilerCache>,
+    cache_hit_rate: Option<f64>,
+    /// Hash of a `guix` build's outputs
+    output_hash: Option<String>,
+}
+
+fn build_bitcoin(runner: &dyn Runner, config: &Config) -> Result<BuildInfo, BenchError> {
+    enter_checkout(runner, config)?;
+    let guix = config.benchmark == Benchmark::Guix;
+
+    // Only a compiler cache on this machine can be cleared and read statistics from.
+    // Guix builds in its own containers, which don't use it.
+    let compiler_cache = (runner.is_local() && !guix)
+        .then(CompilerCache::detect)
+        .flatten();
+    if let Some(cache) = compiler_cache {
+        if config.clear_compiler_cache {
+            cache.clear()?;
+        }
+    }
+    let stats_before = compiler_cache.and_then(|cache| cache.stats());
+
+    let generator = match config.cmake_generator.filter(|_| !guix) {
+        Some(generator) => {
+            let installed = runner
+                .command(generator.tool(), &["--version"])
+                .output()
+                .is_ok_and(|output| output.status.success());
+            if !installed {
+                return Err(anyhow::anyhow!(
+                    "cmake_generator is {} but {} is not installed",
+                    generator.cmake_name(),
+                    generator.tool()
+                )
+                .into());
+            }
+            format!(" -G '{}'", generator.cmake_name())
+        }
+        None => String::new(),
+    };
+
+    let jobs = config.build_jobs.unwrap_or_else(system::cpu_count);
+    let build_command = if guix {
+        guix::build_command(config)
+    } else {
+        format!(
+            "rm -Rf build && cmake -B build{} && cmake --build build -j{}",
+            generator, jobs
+        )
+    };
+
+    let start = Instant::now();
+    let (status, output_tail) = process::run_with_output_tail(
+        &mut runner.command(&config.shell, &["-c", &build_command]),
+        BUILD_ERROR_TAIL_LINES,
+    )
+    .with_context(|| "Failed to execute build command")?;
+
+    if !status.success() {
+        return Err(BenchError::Build {
+            command: build_command,
+            status,
+            output_tail,
+        });
+    }
+
+    let duration_secs = start.elapsed().as_secs_f64();
+    let stats_after = compiler_cache.and_then(|cache| cache.stats());
+    let cache_hit_rate = match (stats_before, stats_after) {
+        (Some(before), Some(after)) => compiler_cache::hit_rate(before, after),
+        _ => None,
+    };
+    let output_hash = guix
+        .then(|| guix::output_hash(runner, config))
+        .transpose()?;
+
+    Ok(BuildInfo {
+        command: build_command,
+        duration_secs,
+        jobs,
+        compiler_cache,
+        cache_hit_rate,
+        output_hash,
+    })
+}
+
+/// Fail when a run ignored by hyperfine's `--ignore-failure` ended with an exit
+/// code that isn't in `expected_exit_codes`, as a hyperfine failure like any other,
+/// so that one killed for running out of memory is recorded as such.
+fn check_exit_codes(
+    results_path: &Path,
+    config: &Config,
+    status: ExitStatus,
+    output_tail: String,
+    killed: bool,
+) -> Result<(), BenchError> {
+    let results = db::read_results_file(results_path).map_err(|source| BenchError::Parse {
+        path: results_path.to_path_buf(),
+        source,
+    })?;
+    for result in &results.results {
+        if let Some(&code) = result
+            .exit_codes
+            .iter()
+            .find(|&&code| !config.exit_expected(code))
+        {
+            return Err(BenchError::Hyperfine {
+                oom: killed || code.is_some_and(exit_code::is_sigkill),
+                command: result.command.clone(),
+                status,
+                exit_code: code,
+                output_tail: format!(
+                    "{}\n`{}` {}, which is not in expected_exit_codes",
+                    output_tail,
+                    result.command,
+                    exit_code::describe(code).unwrap_or_default()
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Fail with a clear error when hyperfine exited successfully without exporting
+/// any results to `results_path`, which would otherwise surface as a confusing
+/// error once the results are saved.
+fn check_results_exported(results_path: &Path) -> Result<()> {
+    let data = fs::read_to_string(results_path).with_context(|| {
+        format!(
+            "hyperfine succeeded but did not write results to {}",
+            results_path.display()
+        )
+    })?;
+    let results: serde_json::Value = serde_json::from_str(&data).with_context(|| {
+        format!(
+            "hyperfine succeeded but wrote invalid JSON to {}",
+            results_path.display()
+        )
+    })?;
+    if results["results"].as_array().is_none_or(Vec::is_empty) {
+        return Err(anyhow::anyhow!(
+            "hyperfine succeeded but {} contains no results",
+            results_path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// The command hyperfine times for the configured benchmark.
+fn timed_command(config: &Config) -> String {
+    let command = match config.benchmark {
+        Benchmark::Ibd => bitcoind::command(config),
+        Benchmark::FunctionalTests => functional_tests_command(config),
+        Benchmark::Rpc => {
+            bitcoind::rpc_command(config, config.rpc_command.as_deref().unwrap_or_default())
+        }
+        Benchmark::Guix => unreachable!("guix builds are timed by the build step"),
+    };
+    with_extra_args(config, command)
+}
+
+/// `command` with the configured `extra_args` appended.
+fn with_extra_args(config: &Config, mut command: String) -> String {
+    for arg in &config.extra_args {
+        command.push(' ');
+        command.push_str(arg);
+    }
+    command
+}
+
+/// The functional test suite, run with as many parallel jobs as the build.
+fn functional_tests_command(config: &Config) -> String {
+    let jobs = config.build_jobs.unwrap_or_else(system::cpu_count);
+    format!("build/test/functional/test_runner.py -j{}", jobs)
+}
+
+/// What a benchmark that ran to completion found besides its timings.
+struct BenchOutcome {
+    /// Height of the assumeutxo snapshot the runs started from, if one is configured
+    snapshot_height: Option<u64>,
+    /// How many times the benchmark was rerun for looking flaky
+    retries: u32,
+    /// Checkpoints each result reached, in the order of the results
+    checkpoints: Vec<Vec<checkpoints::Checkpoint>>,
+    /// Height each IBD result synced to, in the order of the results, empty if
+    /// unknown
+    reached_heights: Vec<Option<u64>>,
+    /// Phases each IBD result went through, in the order of the results
+    phases: Vec<Vec<checkpoints::SyncPhase>>,
+}
+
+/// Benchmark up to `flaky_retries` extra times while the results look flaky, leaving
+/// the attempt with the lowest mean at `results_path`.
+fn run_hyperfine_with_retries(
+    commit: &str,
+    config: &Config,
+    runner: &dyn Runner,
+    results_path: &Path,
+    options: &RunOptions,
+) -> Result<BenchOutcome, BenchError> {
+    let mut best: Option<(f64, String, BenchOutcome)> = None;
+    let mut retries = 0;
+    let debug_log = debug_log_file(results_path);
+
+    loop {
+        // Every attempt appends to the collected log
+        let log_offset = fs::metadata(&debug_log).map_or(0, |metadata| metadata.len());
+        let reason = match run_hyperfine(commit, config, runner, results_path, options) {
+            Ok(snapshot_height) => {
+                let parse_error = |source| BenchError::Parse {
+                    path: results_path.to_path_buf(),
+                    source,
+                };
+                let data = fs::read_to_string(results_path)
+                    .with_context(|| "Failed to read results file")
+                    .map_err(parse_error)?;
+                let results: HyperfineResults = serde_json::from_str(&data)
+                    .with_context(|| "Failed to parse results JSON")
+                    .map_err(parse_error)?;
+                let mean: f64 = results.results.iter().map(|r| r.mean).sum();
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_mean, _, _)| mean < *best_mean)
+                {
+                    let checkpoints = if times_checkpoints(config, options) {
+                        attempt_checkpoints(&debug_log, log_offset, config, results.results.len())
+                    } else {
+                        Vec::new()
+                    };
+                    let (reached_heights, phases) = if config.benchmark == Benchmark::Ibd {
+                        attempt_sync(&debug_log, log_offset, results.results.len(), options)
+                    } else {
+                        (Vec::new(), Vec::new())
+                    };
+                    let outcome = BenchOutcome {
+                        snapshot_height,
+                        retries: 0,
+                        checkpoints,
+                        reached_heights,
+                        phases,
+                    };
+                    best = Some((mean, data, outcome));
+                }
+                match flaky_reason(&results, config) {
+                    Some(reason) => reason,
+                    None => break,
+                }
+            }
+            // Only a failed benchmark may pass on a retry. A run that ran out of
+            // memory would only do so again, and other errors aren't the benchmark's.
+            Err(e @ BenchError::Hyperfine { oom: false, .. }) if retries < config.flaky_retries => {
+                // The error carries the output tail, which is printed above anyway
+                let message = e.to_string();
+                message
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_end_matches(':')
+                    .to_string()
+            }
+            Err(e) if best.is_none() => return Err(e),
+            Err(e) => {
+                eprintln!("Warning: retry failed, keeping an earlier attempt: {:#}", e);
+                break;
+            }
+        };
+        if retries == config.flaky_retries {
+            break;
+        }
+        retries += 1;
+        eprintln!(
+            "Benchmark looks flaky ({}), retrying ({}/{})",
+            reason, retries, config.flaky_retries
+        );
+    }
+
+    let (_, data, outcome) = best.expect("a successful attempt is kept");
+    fs::write(results_path, data)
+        .with_context(|| format!("Failed to write results file at {}", results_path.display()))?;
+    Ok(BenchOutcome { retries, ..outcome })
+}
+
+/// The checkpoints of the attempt that appended to `debug_log` from `offset` on, one
+/// list per each of its `results`. Failing to time them only warrants a warning.
+fn attempt_checkpoints(
+    debug_log: &Path,
+    offset: u64,
+    config: &Config,
+    results: usize,
+) -> Vec<Vec<checkpoints::Checkpoint>> {
+    match checkpoints::from_log(debug_log, offset, &config.checkpoints) {
+        Ok(runs) if runs.len() == results => runs,
+        Ok(runs) => {
+            eprintln!(
+                "Warning: found {} bitcoind starts in debug.log for {} results, not timing checkpoints",
+                runs.len(),
+                results
+            );
+            Vec::new()
+        }
+        Err(e) => {
+            eprintln!("Warning: failed to time checkpoints: {:#}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// The height each of the `results` of the attempt that appended to `debug_log` from
+/// `offset` on synced to, and the IBD phases it went through. Failing to read them
+/// only warrants a warning.
+fn attempt_sync(
+    debug_log: &Path,
+    offset: u64,
+    results: usize,
+    options: &RunOptions,
+) -> (Vec<Option<u64>>, Vec<Vec<checkpoints::SyncPhase>>) {
+    let parsed = checkpoints::final_heights(debug_log, offset)
+        .and_then(|heights| Ok((heights, checkpoints::phases_from_log(debug_log, offset)?)));
+    match parsed {
+        // Both find the same starts unless some lack a timestamp
+        Ok((heights, phases)) if heights.len() == phases.len() => {
+            per_result(heights.into_iter().zip(phases).collect(), results, options)
+                .unwrap_or_default()
+                .into_iter()
+                .unzip()
+        }
+        Ok((heights, _)) => {
+            eprintln!("Warning: debug.log has no timestamps, not timing the sync phases");
+            let heights = per_result(heights, results, options).unwrap_or_default();
+            (heights, Vec::new())
+        }
+        Err(e) => {
+            eprintln!("Warning: failed to read how the runs synced: {:#}", e);
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+/// What was found for each bitcoind start in an attempt's `debug.log`, narrowed to
+/// one per each of its `results`. A snapshot run also starts bitcoind to load the
+/// snapshot, so the last start of each result's share counts. Appending runs
+/// collect the whole `debug.log` of the kept datadir, so only the last starts
+/// count. Warns and returns `None` if the starts don't add up.
+fn per_result<T: Clone>(starts: Vec<T>, results: usize, options: &RunOptions) -> Option<Vec<T>> {
+    if options.append && starts.len() >= results {
+        return Some(starts[starts.len() - results..].to_vec());
+    }
+    if results > 0 && !starts.is_empty() && starts.len().is_multiple_of(results) {
+        return Some(
+            starts
+                .chunks(starts.len() / results)
+                .map(|share| share[share.len() - 1].clone())
+                .collect(),
+        );
+    }
+    eprintln!(
+        "Warning: found {} bitcoind starts in debug.log for {} results, not reading how they synced",
+        starts.len(),
+        results
+    );
+    None
+}
+
+/// Why a set of results should be rerun, if it should.
+fn flaky_reason(results: &HyperfineResults, config: &Config) -> Option<String> {
+    for result in &results.results {
+        if let Some(code) = result
+            .exit_codes
+            .iter()
+            .find(|&&code| !config.exit_expected(code))
+        {
+            return Some(match exit_code::describe(*code) {
+                Some(reason) => format!("a run {}", reason),
+                None => "a run failed".to_string(),
+            });
+        }
+        if let (Some(max_pct), Some(stddev)) = (config.flaky_stddev_pct, result.stddev) {
+            let stddev_pct = stddev / result.mean * 100.0;
+            if stddev_pct > max_pct {
+                return Some(format!(
+                    "stddev is {:.1}% of the mean, above {:.1}%",
+                    stddev_pct, max_pct
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// Change to the checkout for local runs, from where the rpc benchmark's node and
+/// commands relative to it, like `./build/src/bitcoind`, are started.
+fn enter_checkout(runner: &dyn Runner, config: &Config) -> Result<()> {
+    if runner.is_local() {
+        let repo_path = &config.repo_path;
+        std::env::set_current_dir(repo_path)
+            .with_context(|| format!("Failed to change directory to {}", repo_path.display()))?;
+    }
+    Ok(())
+}
+
+/// The installed hyperfine's version, e.g. `1.18.0` from "hyperfine 1.18.0".
+fn hyperfine_version(runner: &dyn Runner) -> Option<String> {
+    let output = runner.command("hyperfine", &["--version"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.trim().strip_prefix("hyperfine")?.trim();
+    Some(version.to_string())
+}
+
+/// Sync once to `smoke_height`, if set, to catch a commit that can't sync at all
+/// before timing the full run. Appending runs skip it, as it would wipe their
+/// datadir.
+fn smoke_run(
+    commit: &str,
+    config: &Config,
+    runner: &dyn Runner,
+    results_path: &Path,
+    options: &RunOptions,
+) -> Result<(), BenchError> {
+    let Some(smoke_height) = config.smoke_height else {
+        return Ok(());
+    };
+    if config.benchmark != Benchmark::Ibd || options.append {
+        return Ok(());
+    }
+    let smoke_config = Config {
+        stopatheight: smoke_height,
+        checkpoints: Vec::new(),
+        keep_datadir: false,
+        keep_command_output: false,
+        log_dir: None,
+        ..config.clone()
+    };
+    let smoke_options = RunOptions {
+        progress: false,
+        ..options.clone()
+    };
+    let smoke_path = results_path.with_extension("smoke.json");
+    println!(
+        "Smoke testing {} with a sync to height {}",
+        commit, smoke_height
+    );
+    let smoked = run_hyperfine(commit, &smoke_config, runner, &smoke_path, &smoke_options);
+    for path in [
+        &smoke_path,
+        &datadir_size_file(&smoke_path),
+        &debug_log_file(&smoke_path),
+    ] {
+        let _ = fs::remove_file(path);
+    }
+    if let Err(e) = smoked {
+        eprintln!(
+            "Smoke run to height {} failed, not starting the full benchmark",
+            smoke_height
+        );
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Benchmark the built bitcoind, returning the height of the assumeutxo snapshot
+/// the runs started from, if one is configured.
+fn run_hyperfine(
+    commit: &str,
+    config: &Config,
+    runner: &dyn Runner,
+    results_path: &Path,
+    options: &RunOptions,
+) -> Result<Option<u64>, BenchError> {
+    enter_checkout(runner, config)?;
+
+    // A stale export from an earlier run must not pass for this one's
+    if results_path.exists() {
+        fs::remove_file(results_path).with_context(|| {
+            format!(
+                "Failed to remove old results file at {}",
+                results_path.display()
+            )
+        })?;
+    }
+
+    let ibd = config.benchmark == Benchmark::Ibd;
+    let snapshot_result = results_path.with_extension("snapshot.json");
+    let mut args: Vec<String> = [
+        "--shell",
+        &config.shell,
+        "--parameter-list",
+        "commit",
+        commit,
+    ]
+    .map(String::from)
+    .to_vec();
+    for (name, values) in &options.params {
+        args.extend(["--parameter-list".to_string(), name.clone(), values.clone()]);
+    }
+    // The functional tests create and clean up their own datadirs
+    if ibd {
+        let prepare = if options.append {
+            // Keep the chain the previous appending run synced
+            "sync".to_string()
+        } else {
+            bitcoind::prepare_command(config, &snapshot_result)?
+        };
+        args.extend(["--prepare".to_string(), prepare]);
+    }
+    // Measured in the cleanup step, as the wipe happens before hyperfine returns
+    let mut cleanup = if ibd {
+        let wipe = !config.keep_datadir && !options.append;
+        let log_file = collects_debug_log(config).then(|| debug_log_file(results_path));
+        bitcoind::cleanup_command(
+            config,
+            &datadir_size_file(results_path),
+            log_file.as_deref(),
+            wipe,
+        )
+    } else {
+        String::new()
+    };
+    let keep_output = config.keep_command_output && {
+        let version = hyperfine_version(runner);
+        let supported = supports_output_file(version.as_deref());
+        if !supported {
+            eprintln!(
+                "Warning: hyperfine {} cannot write command output to a file, not keeping it",
+                version.as_deref().unwrap_or("of unknown version")
+            );
+        }
+        supported
+    };
+    if keep_output {
+        // First, so the output is kept even if the rest of the cleanup fails
+        let collect = collect_output_command(results_path, options);
+        cleanup = if cleanup.is_empty() {
+            collect
+        } else {
+            format!("{} && {}", collect, cleanup)
+        };
+        args.extend([
+            "--output".to_string(),
+            command_output_scratch(results_path).display().to_string(),
+        ]);
+    } else {
+        args.push("--show-output".to_string());
+    }
+    // Checked against expected_exit_codes once hyperfine is done
+    if !config.expected_exit_codes.is_empty() {
+        args.push("--ignore-failure".to_string());
+    }
+    args.extend(
+        [
+            "--cleanup",
+            &cleanup,
+            "--runs",
+            "1",
+            "--export-json",
+            &results_path.to_string_lossy(),
+            &timed_command(config),
+        ]
+        .map(String::from),
+    );
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut hyperfine = runner.command("hyperfine", &args);
+
+    let node = if config.benchmark == Benchmark::Rpc {
+        if let Some(restore) = seed::restore_command(config) {
+            let restore = format!("rm -Rf {}/* && {}", config.datadir.display(), restore);
+            run_checked(runner, &config.shell, &["-c", &restore])?;
+        }
+        let mut node = node::start_node(&config.datadir, bitcoind::RPC_NODE_FLAGS)?;
+        node.wait_until_ready(Duration::from_secs(config.node_ready_timeout_secs))
+            .with_context(|| "bitcoind started for the rpc benchmark is not responding")?;
+        Some(node)
+    } else {
+        None
+    };
+    let watcher = (options.progress && ibd)
+        .then(|| progress::Watcher::start(config.datadir.join("debug.log"), config.stopatheight));
+    let oom_kills = runner.is_local().then(system::oom_kills).flatten();
+    let outcome = process::run_with_output_tail(&mut hyperfine, BENCH_ERROR_TAIL_LINES);
+    if let Some(watcher) = watcher {
+        watcher.finish();
+    }
+    // Stop the node however the benchmark went, so the next run can start its own
+    if let Some(node) = node {
+        match (node.stop_node(), &outcome) {
+            (Err(e), Ok(_)) => return Err(e.into()),
+            (Err(e), Err(_)) => eprintln!("Warning: {:#}", e),
+            (Ok(()), _) => {}
+        }
+    }
+
+    let (status, output_tail) = outcome.with_context(|| "Failed to execute hyperfine command")?;
+    let killed = oom_kills
+        .zip(system::oom_kills())
+        .is_some_and(|(before, after)| after > before);
+    if !status.success() {
+        let exit_code = exit_code::from_output(&output_tail);
+        return Err(BenchError::Hyperfine {
+            oom: killed || exit_code.is_some_and(exit_code::is_sigkill),
+            command: timed_command(config),
+            status,
+            exit_code,
+            output_tail,
+        });
+    }
+
+    runner
+        .fetch_file(results_path, results_path)
+        .and_then(|()| check_results_exported(results_path))
+        .map_err(|source| BenchError::Parse {
+            path: results_path.to_path_buf(),
+            source,
+        })?;
+    let mut outputs = Vec::new();
+    if ibd {
+        outputs.push(datadir_size_file(results_path));
+        if collects_debug_log(config) {
+            outputs.push(debug_log_file(results_path));
+        }
+    }
+    if keep_output {
+        outputs.push(command_output_file(results_path));
+    }
+    for output in outputs {
+        // Their absence is reported where they are read
+        let _ = runner.fetch_file(&output, &output);
+    }
+    if !config.expected_exit_codes.is_empty() {
+        check_exit_codes(results_path, config, status, output_tail, killed)?;
+    }
+
+    if !ibd || config.assumeutxo_snapshot.is_none() {
+        return Ok(None);
+    }
+    let height = bitcoind::snapshot_height(&snapshot_result)?;
+    fs::remove_file(&snapshot_result).with_context(|| {
+        format!(
+            "Failed to remove loadtxoutset result at {}",
+            snapshot_result.display()
+        )
+    })?;
+    Ok(Some(height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An export by hyperfine 1.18, which has every field.
+    const HYPERFINE_1_18: &str = r#"{"results": [{
+        "command": "bitcoind -stopatheight=1000",
+        "mean": 10.5, "stddev": 0.5, "median": 10.4,
+        "user": 8.0, "system": 1.0, "min": 10.0, "max": 11.1,
+        "times": [10.0, 10.4, 11.1],
+        "exit_codes": [0, 0, null],
+        "parameters": {"commit": "abc", "par": "4"}
+    }]}"#;
+
+    /// An export by a hyperfine old enough to lack median, exit_codes and parameters.
+    const OLD_HYPERFINE: &str = r#"{"results": [{
+        "command": "bitcoind -stopatheight=1000",
+        "mean": 10.5, "stddev": 0.5,
+        "user": 8.0, "system": 1.0, "min": 10.0, "max": 11.1,
+        "times": [10.0, 10.4, 11.1]
+    }]}"#;
+
+    #[test]
+    fn parses_current_hyperfine_export() {
+        let results: HyperfineResults = serde_json::from_str(HYPERFINE_1_18).unwrap();
+        let result = &results.results[0];
+        assert_eq!(result.median, 10.4);
+        assert_eq!(result.exit_codes, [Some(0), Some(0), None]);
+        let parameters = result.parameters.as_ref().unwrap();
+        assert_eq!(parameters.commit, "abc");
+        assert_eq!(parameters.extra["par"], "4");
+        assert!(result.missing_fields.is_empty());
+    }
+
+    #[test]
+    fn parses_old_hyperfine_export_with_fallbacks() {
+        let results: HyperfineResults = serde_json::from_str(OLD_HYPERFINE).unwrap();
+        let result = &results.results[0];
+        assert_eq!(result.median, result.mean);
+        assert_eq!(result.min, 10.0);
+        assert_eq!(result.max, 11.1);
+        assert!(result.exit_codes.is_empty());
+        assert!(result.parameters.is_none());
+        assert_eq!(result.missing_fields, ["median", "exit_codes"]);
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::StoredResult;
+use crate::output::{self, Format, Render, Style};
+
+/// Pairwise deltas between several commits' latest results for one command.
+#[derive(Serialize)]
+pub struct Matrix<'a> {
+    pub command: &'a str,
+    pub commits: Vec<&'a str>,
+    /// Each commit's alias, if it was given one
+    pub aliases: Vec<Option<&'a str>>,
+    pub means: Vec<f64>,
+    /// `deltas_pct[i][j]` is the change in mean of `commits[i]` relative to `commits[j]`
+    pub deltas_pct: Vec<Vec<f64>>,
+}
+
+/// The output of `matrix`.
+#[derive(Serialize)]
+struct Report<'a> {
+    /// One matrix per command benchmarked at every commit
+    matrices: &'a [Matrix<'a>],
+}
+
+/// Build a matrix for every command that has a timed result at each of `commits`,
+/// given as `(commit hash, latest results)`.
+pub fn build(commits: &[(String, Vec<StoredResult>)]) -> Vec<Matrix<'_>> {
+    let Some((_, first)) = commits.first() else {
+        return Vec::new();
+    };
+
+    let mut matrices = Vec::new();
+    for candidate in first {
+        let matches: Option<Vec<&StoredResult>> = commits
+            .iter()
+            .map(|(_, results)| {
+                results.iter().find(|r| {
+                    r.command == candidate.command
+                        && r.snapshot_height == candidate.snapshot_height
+                        && r.mean.is_some()
+                })
+            })
+            .collect();
+        let Some(matches) = matches else {
+            continue;
+        };
+        let means: Vec<f64> = matches.iter().filter_map(|r| r.mean).collect();
+
+        let deltas_pct = means
+            .iter()
+            .map(|mean| {
+                means
+                    .iter()
+                    .map(|other| (mean - other) / other * 100.0)
+                    .collect()
+            })
+            .collect();
+        matrices.push(Matrix {
+            command: &candidate.command,
+            commits: commits.iter().map(|(hash, _)| hash.as_str()).collect(),
+            aliases: matches.iter().map(|r| r.commit_alias.as_deref()).collect(),
+            means,
+            deltas_pct,
+        });
+    }
+    matrices
+}
+
+/// Print `matrices` in `format`. Tables show each matrix as a grid of row-versus-column
+/// deltas, with commit hashes and timings shown as `style` says.
+pub fn print(matrices: &[Matrix], format: Format, style: &Style) -> Result<()> {
+    output::render(&Report { matrices }, format, style)
+}
+
+impl Render for Report<'_> {
+    fn print_human(&self, style: &Style) {
+        if self.matrices.is_empty() {
+            println!("No command was benchmarked at every commit");
+            return;
+        }
+
+        for matrix in self.matrices {
+            let hash_len = output::hash_length(matrix.commits.iter().copied(), style.hash_len);
+            let unit = style.unit_for(matrix.means.iter().copied());
+            let labels: Vec<&str> = matrix
+                .commits
+                .iter()
+                .zip(&matrix.aliases)
+                .map(|(commit, alias)| {
+                    alias.unwrap_or_else(|| output::short_hash(commit, hash_len))
+                })
+                .collect();
+            let width = labels
+                .iter()
+                .map(|label| label.len())
+                .max()
+                .unwrap_or(0)
+                .max(8);
+            println!("{}", matrix.command);
+
+            let mean = format!("mean ({})", output::suffix(unit));
+            print!("  {:<width$}  {:>10}", "", mean);
+            for label in &labels {
+                print!("  {:>width$}", label);
+            }
+            println!();
+
+            for (i, label) in labels.iter().enumerate() {
+                let mean = style.number(matrix.means[i], unit);
+                print!("  {:<width$}  {:>10}", label, mean);
+                for (j, delta_pct) in matrix.deltas_pct[i].iter().enumerate() {
+                    let cell = if i == j {
+                        "-".to_string()
+                    } else {
+                        format!("{:+.1}%", delta_pct)
+                    };
+                    print!("  {:>width$}", cell);
+                }
+                println!();
+            }
+        }
+    }
+
+    /// One row per ordered pair of commits
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = ["command", "commit", "mean", "base", "delta_pct"];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for matrix in self.matrices {
+            for (i, commit) in matrix.commits.iter().enumerate() {
+                for (j, base) in matrix.commits.iter().enumerate() {
+                    if i != j {
+                        rows.push(vec![
+                            matrix.command.to_string(),
+                            commit.to_string(),
+                            matrix.means[i].to_string(),
+                            base.to_string(),
+                            matrix.deltas_pct[i][j].to_string(),
+                        ]);
+                    }
+                }
+            }
+        }
+        rows
+    }
+}
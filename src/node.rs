@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bitcoind::{BITCOIND, BITCOIN_CLI, RPC_PORT};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a node may take to shut down before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A bitcoind running in the background. It is stopped when dropped, so an error
+/// between starting and stopping it never leaves it running.
+pub struct NodeHandle {
+    child: Option<Child>,
+    datadir: PathBuf,
+}
+
+/// Start bitcoind on `datadir` with `flags`, in addition to the datadir and RPC port.
+pub fn start_node(datadir: &Path, flags: &[&str]) -> Result<NodeHandle> {
+    let child = Command::new(BITCOIND)
+        .arg(format!("-datadir={}", datadir.display()))
+        .arg(format!("-rpcport={}", RPC_PORT))
+        .args(flags)
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to execute {}", BITCOIND))?;
+    Ok(NodeHandle {
+        child: Some(child),
+        datadir: datadir.to_path_buf(),
+    })
+}
+
+impl NodeHandle {
+    /// Wait until the node answers RPCs, failing if it exits first. A node still not
+    /// answering after `timeout` is killed.
+    pub fn wait_until_ready(&mut self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.rpc("getblockchaininfo").is_ok() {
+                return Ok(());
+            }
+            if let Some(status) = self.child_mut().try_wait()? {
+                return Err(anyhow::anyhow!(
+                    "bitcoind exited with status {} before it was ready",
+                    status
+                ));
+            }
+            if Instant::now() >= deadline {
+                if let Some(mut child) = self.child.take() {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                return Err(anyhow::anyhow!(
+                    "bitcoind did not answer RPCs within {}s, killed it",
+                    timeout.as_secs()
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Ask the node to shut down over RPC and wait for it to exit.
+    pub fn stop_node(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        let Some(mut child) = self.child.take() else {
+            return Ok(());
+        };
+        if child.try_wait()?.is_some() {
+            return Ok(());
+        }
+
+        let stopped = self.rpc("stop");
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while stopped.is_ok() && Instant::now() < deadline {
+            if child.try_wait()?.is_some() {
+                return Ok(());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        let _ = child.kill();
+        let _ = child.wait();
+        stopped.with_context(|| "Failed to stop bitcoind over RPC, killed it instead")?;
+        Err(anyhow::anyhow!(
+            "bitcoind did not stop within {}s, killed it instead",
+            STOP_TIMEOUT.as_secs()
+        ))
+    }
+
+    fn rpc(&self, method: &str) -> Result<()> {
+        let output = Command::new(BITCOIN_CLI)
+            .arg(format!("-datadir={}", self.datadir.display()))
+            .arg(format!("-rpcport={}", RPC_PORT))
+            .arg(method)
+            .output()
+            .with_context(|| format!("Failed to execute {}", BITCOIN_CLI))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "bitcoin-cli {} failed with status {}: {}",
+                method,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(())
+    }
+
+    fn child_mut(&mut self) -> &mut Child {
+        self.child.as_mut().expect("only taken when stopping")
+    }
+}
+
+impl Drop for NodeHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::config::{Config, Metric};
+use crate::db;
+use crate::output;
+use crate::regression::{self, Regression};
+
+/// Which run outcomes should trigger a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    /// Every completed or failed run
+    Always,
+    /// Only runs that failed
+    Failure,
+    /// Only runs where a slowdown was detected
+    Regression,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Event {
+    Success,
+    Failure,
+    Regression,
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    event: Event,
+    /// Unique per run, so receivers can drop duplicate deliveries
+    run_id: &'a str,
+    commit: &'a str,
+    message: String,
+    regressions: Vec<Regression>,
+}
+
+impl NotifyOn {
+    fn allows(self, event: Event) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Failure => event == Event::Failure,
+            Self::Regression => event == Event::Regression,
+        }
+    }
+}
+
+/// Send notifications for run `run_id` to every configured sink, subject to
+/// `notify_on`, skipping sinks it was already delivered to. Delivery problems are
+/// reported but never fail the run.
+pub fn after_run(config: &Config, commit: &str, run_id: &str, outcome: &Result<Vec<i64>>) {
+    if config.webhook_url.is_none() && config.slack_webhook_url.is_none() {
+        return;
+    }
+
+    let notification = match build_notification(config, commit, run_id, outcome) {
+        Ok(Some(notification)) => notification,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Failed to prepare notification: {:?}", e);
+            return;
+        }
+    };
+    if !config.notify_on.allows(notification.event) {
+        return;
+    }
+
+    // Without the database deliveries can't be deduplicated, but are still made
+    let conn = db::open_db(&config.db_path)
+        .map_err(|e| eprintln!("Warning: not tracking notification delivery: {:#}", e))
+        .ok();
+    if let Some(url) = &config.webhook_url {
+        deliver_once(conn.as_ref(), run_id, "webhook", || {
+            post_json(url, &json!(notification))
+        });
+    }
+    if let Some(url) = &config.slack_webhook_url {
+        deliver_once(conn.as_ref(), run_id, "Slack", || {
+            let body = json!({ "text": notification.message, "run_id": run_id });
+            post_json(url, &body)
+        });
+    }
+}
+
+/// Deliver with `send` unless run `run_id`'s notification already reached `sink`.
+fn deliver_once(
+    conn: Option<&Connection>,
+    run_id: &str,
+    sink: &str,
+    send: impl FnOnce() -> Result<()>,
+) {
+    if let Some(conn) = conn {
+        match db::is_delivered(conn, run_id, sink) {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => eprintln!("Warning: {:#}", e),
+        }
+    }
+    if let Err(e) = send() {
+        eprintln!("Failed to deliver {} notification: {:?}", sink, e);
+        return;
+    }
+    if let Some(conn) = conn {
+        if let Err(e) = db::record_delivery(conn, run_id, sink) {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+}
+
+fn build_notification<'a>(
+    config: &Config,
+    commit: &'a str,
+    run_id: &'a str,
+    outcome: &Result<Vec<i64>>,
+) -> Result<Option<Notification<'a>>> {
+    let ids = match outcome {
+        Err(e) => {
+            return Ok(Some(Notification {
+                event: Event::Failure,
+                run_id,
+                commit,
+                message: format!("Benchmark of {} failed: {:#}", commit, e),
+                regressions: Vec::new(),
+            }))
+        }
+        // Nothing was stored, e.g. the run was skipped
+        Ok(ids) if ids.is_empty() => return Ok(None),
+        Ok(ids) => ids,
+    };
+
+    let conn = db::open_db(&config.db_path)?;
+    let regressions = regression::detect(
+        &conn,
+        ids,
+        config.comparison_metric,
+        config.regression_threshold_pct,
+    )?;
+    if regressions.is_empty() {
+        return Ok(Some(Notification {
+            event: Event::Success,
+            run_id,
+            commit,
+            message: format!("Benchmark of {} completed", commit),
+            regressions,
+        }));
+    }
+
+    let style = output::Style::new(config, true);
+    let mut message = format!("Regression detected at {}:", commit);
+    for r in &regressions {
+        let unit = style.unit_for([r.previous_mean, r.mean]);
+        let metric = match r.metric {
+            Metric::Mean => String::new(),
+            metric => format!("{} ", metric.column()),
+        };
+        message.push_str(&format!(
+            "\n{}: {}{} -> {} (+{:.1}%, confidence {:.2}) vs {}",
+            r.command,
+            metric,
+            style.duration_in(r.previous_mean, unit),
+            style.duration_in(r.mean, unit),
+            r.delta_pct,
+            r.confidence,
+            r.previous_commit
+        ));
+    }
+    if let Some(files) = regressions.iter().find_map(|r| r.src_files_changed) {
+        message.push_str(&format!("\nThis commit touched {} files in src/.", files));
+    }
+    Ok(Some(Notification {
+        event: Event::Regression,
+        run_id,
+        commit,
+        message,
+        regressions,
+    }))
+}
+
+fn post_json(url: &str, body: &serde_json::Value) -> Result<()> {
+    let mut child = Command::new("curl")
+        .args([
+            "-fsS",
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| "Failed to execute curl")?;
+
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(body.to_string().as_bytes())
+        .with_context(|| "Failed to write notification payload")?;
+
+    let status = child.wait().with_context(|| "Failed to wait for curl")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("curl failed with status {}", status));
+    }
+    Ok(())
+}
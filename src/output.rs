@@ -0,0 +1,234 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+use crate::config::Config;
+
+/// How read commands print their results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned tables for the terminal
+    Human,
+    /// A single JSON document on one line
+    Json,
+    /// A single indented JSON document
+    JsonPretty,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl Format {
+    pub fn is_json(self) -> bool {
+        matches!(self, Format::Json | Format::JsonPretty)
+    }
+}
+
+/// The unit human output shows timings in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    /// Milliseconds when every timing shown together is under a second
+    Auto,
+    /// Seconds
+    S,
+    /// Milliseconds
+    Ms,
+}
+
+/// How human output shows commit hashes and timings. JSON and CSV always carry
+/// full hashes and unrounded seconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    /// Characters commit hashes are shortened to at least, or `None` for full hashes
+    pub hash_len: Option<usize>,
+    /// Decimal places timings are rounded to
+    pub precision: usize,
+    pub unit: TimeUnit,
+    /// Zone stored UTC timestamps are shown in
+    pub timezone: Tz,
+}
+
+impl Style {
+    pub fn new(config: &Config, full_hash: bool) -> Self {
+        Style {
+            hash_len: (!full_hash).then_some(config.hash_length),
+            precision: config.precision,
+            unit: config.time_unit,
+            timezone: config.timezone,
+        }
+    }
+
+    /// A stored RFC 3339 timestamp in the configured zone, e.g.
+    /// "2024-10-01 02:00 CEST". Unparseable timestamps are shown as they are.
+    pub fn local_time(&self, timestamp: &str) -> String {
+        match DateTime::parse_from_rfc3339(timestamp) {
+            Ok(time) => time
+                .with_timezone(&self.timezone)
+                .format("%Y-%m-%d %H:%M %Z")
+                .to_string(),
+            Err(_) => timestamp.to_string(),
+        }
+    }
+
+    /// The unit to show `secs` in together, resolving `Auto` to seconds or milliseconds.
+    pub fn unit_for(&self, secs: impl IntoIterator<Item = f64>) -> TimeUnit {
+        match self.unit {
+            TimeUnit::Auto => {
+                let mut secs = secs.into_iter().peekable();
+                if secs.peek().is_some() && secs.all(|s| s.abs() < 1.0) {
+                    TimeUnit::Ms
+                } else {
+                    TimeUnit::S
+                }
+            }
+            unit => unit,
+        }
+    }
+
+    /// `secs` in `unit` at the configured precision, without a suffix.
+    pub fn number(&self, secs: f64, unit: TimeUnit) -> String {
+        let value = if unit == TimeUnit::Ms {
+            secs * 1000.0
+        } else {
+            secs
+        };
+        format!("{:.*}", self.precision, value)
+    }
+
+    /// `secs` in `unit` with its suffix, e.g. "1.234s" or "12.345ms".
+    pub fn duration_in(&self, secs: f64, unit: TimeUnit) -> String {
+        format!("{}{}", self.number(secs, unit), suffix(unit))
+    }
+
+    /// `secs` with its suffix, in the unit that suits it alone.
+    pub fn duration(&self, secs: f64) -> String {
+        self.duration_in(secs, self.unit_for([secs]))
+    }
+}
+
+/// The suffix for timings in `unit`, with `Auto` treated as seconds.
+pub fn suffix(unit: TimeUnit) -> &'static str {
+    if unit == TimeUnit::Ms {
+        "ms"
+    } else {
+        "s"
+    }
+}
+
+/// The output of a read command, printable in every `Format`.
+pub trait Render: Serialize {
+    /// Print as tables for the terminal, following `style`
+    fn print_human(&self, style: &Style);
+
+    /// The header followed by one row per record, always with full hashes
+    fn csv_rows(&self) -> Vec<Vec<String>>;
+}
+
+/// Print a command's output in `format`.
+pub fn render<T: Render>(output: &T, format: Format, style: &Style) -> Result<()> {
+    match format {
+        Format::Human => output.print_human(style),
+        Format::Json | Format::JsonPretty => print_json(output, format)?,
+        Format::Csv => {
+            for row in output.csv_rows() {
+                let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                println!("{}", fields.join(","));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A CSV cell for an optional value, empty when it is `None`.
+pub fn csv_value<T: Display>(value: Option<T>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// A logfmt line of `key=value` fields, quoting values with spaces, quotes or `=`
+/// in them, for log processors to pick fields out of.
+pub fn logfmt(fields: &[(&str, String)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() || value.contains([' ', '"', '=', '\n']) {
+                format!("{}={:?}", key, value)
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Print `value` as the JSON document for a command's output.
+pub fn print_json<T: Serialize>(value: &T, format: Format) -> Result<()> {
+    let json = to_json(value, format).with_context(|| "Failed to serialize output")?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Report a command failure on stderr, as `{ "error": "..." }` in JSON mode.
+pub fn print_error(error: &anyhow::Error, format: Format) {
+    if !format.is_json() {
+        eprintln!("Error: {:?}", error);
+        return;
+    }
+
+    let json = serde_json::json!({ "error": format!("{:#}", error) });
+    match to_json(&json, format) {
+        Ok(json) => eprintln!("{}", json),
+        Err(_) => eprintln!("Error: {:?}", error),
+    }
+}
+
+/// The length to show commit hashes at: at least `min_len`, extended until no two
+/// of `hashes` share a displayed prefix. `None` shows hashes in full.
+pub fn hash_length<'a>(hashes: impl IntoIterator<Item = &'a str>, min_len: Option<usize>) -> usize {
+    let mut hashes: Vec<&str> = hashes.into_iter().collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    let longest = hashes.iter().map(|hash| hash.len()).max().unwrap_or(0);
+    let Some(min_len) = min_len else {
+        return longest;
+    };
+
+    // Neighbours in sorted order share the longest prefixes
+    let shared = hashes
+        .windows(2)
+        .map(|pair| {
+            let (a, b) = (pair[0].as_bytes(), pair[1].as_bytes());
+            a.iter().zip(b).take_while(|(x, y)| x == y).count()
+        })
+        .max()
+        .unwrap_or(0);
+    min_len.max(shared + 1).min(longest)
+}
+
+/// Truncate `hash` to `len` characters. Labels that aren't hex, such as file paths
+/// and symbolic refs, are left as they are.
+pub fn short_hash(hash: &str, len: usize) -> &str {
+    if hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        hash.get(..len).unwrap_or(hash)
+    } else {
+        hash
+    }
+}
+
+fn to_json<T: Serialize>(value: &T, format: Format) -> serde_json::Result<String> {
+    if format == Format::JsonPretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{self, Path, PathBuf};
+
+use crate::config::Config;
+use crate::output::{Render, Style};
+
+/// The output of `paths`: where the configuration in effect puts things, absolute
+/// so wrapper scripts can use them from any directory. Unset paths are `null`.
+#[derive(Serialize)]
+pub struct Paths {
+    data_root: Option<PathBuf>,
+    repo_path: PathBuf,
+    db_path: PathBuf,
+    log_dir: Option<PathBuf>,
+    builds_dir: PathBuf,
+    temp_dir: PathBuf,
+    site_dir: Option<PathBuf>,
+    /// Host `builds_dir`, `datadir`, `storage`, `remote_repo_path`, `rr_trace_dir`
+    /// and `seed_datadirs` are on, this machine if unset
+    ssh_host: Option<String>,
+    remote_repo_path: Option<PathBuf>,
+    datadir: PathBuf,
+    storage: BTreeMap<String, PathBuf>,
+    rr_trace_dir: Option<PathBuf>,
+    /// Each scenario's `seed_datadir`, by scenario
+    seed_datadirs: BTreeMap<String, PathBuf>,
+}
+
+/// Resolve the paths of `config`. Paths on `ssh_host` are left as configured, as
+/// they can't be resolved here.
+pub fn resolve(config: &Config) -> Result<Paths> {
+    let remote = |path: &Path| {
+        if config.ssh_host.is_some() {
+            Ok(path.to_path_buf())
+        } else {
+            absolute(path)
+        }
+    };
+    Ok(Paths {
+        data_root: config.data_root.as_deref().map(absolute).transpose()?,
+        repo_path: absolute(&config.repo_path)?,
+        db_path: absolute(&config.db_path)?,
+        log_dir: config.log_dir.as_deref().map(absolute).transpose()?,
+        builds_dir: remote(&config.builds_dir())?,
+        temp_dir: absolute(&config.temp_dir())?,
+        site_dir: config.site_dir.as_deref().map(absolute).transpose()?,
+        ssh_host: config.ssh_host.clone(),
+        remote_repo_path: config.remote_repo_path.as_deref().map(remote).transpose()?,
+        datadir: remote(&config.datadir)?,
+        storage: config
+            .storage
+            .iter()
+            .map(|(name, path)| Ok((name.clone(), remote(path)?)))
+            .collect::<Result<_>>()?,
+        rr_trace_dir: config.rr_trace_dir.as_deref().map(remote).transpose()?,
+        seed_datadirs: config
+            .scenarios
+            .iter()
+            .filter_map(|(benchmark, scenario)| Some((benchmark, scenario.seed_datadir.as_ref()?)))
+            .map(|(benchmark, seed)| Ok((benchmark.name().to_string(), remote(seed)?)))
+            .collect::<Result<_>>()?,
+    })
+}
+
+impl Paths {
+    /// Each path by name, maps flattened to `storage.<name>` and the like, with
+    /// `None` for unset paths.
+    fn entries(&self) -> Vec<(String, Option<String>)> {
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(self) else {
+            return Vec::new();
+        };
+        let mut entries = Vec::new();
+        for (name, value) in fields {
+            match value {
+                serde_json::Value::Object(map) => {
+                    for (key, value) in map {
+                        let value = value.as_str().map(String::from);
+                        entries.push((format!("{}.{}", name, key), value));
+                    }
+                }
+                value => entries.push((name, value.as_str().map(String::from))),
+            }
+        }
+        entries
+    }
+}
+
+impl Render for Paths {
+    /// One path per line, `-` for unset paths.
+    fn print_human(&self, _style: &Style) {
+        let entries = self.entries();
+        let width = entries
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, path) in entries {
+            println!("{:<width$}  {}", name, path.as_deref().unwrap_or("-"));
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let mut rows = vec![vec!["name".to_string(), "path".to_string()]];
+        for (name, path) in self.entries() {
+            rows.push(vec![name, path.unwrap_or_default()]);
+        }
+        rows
+    }
+}
+
+/// `path` made absolute against the working directory, without requiring it to exist.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    path::absolute(path).with_context(|| format!("Failed to resolve {}", path.display()))
+}
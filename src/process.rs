@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Run `command`, passing its output through as it is produced while keeping the
+/// last `lines` lines of combined stdout and stderr for error reporting.
+pub fn run_with_output_tail(command: &mut Command, lines: usize) -> Result<(ExitStatus, String)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute {:?}", command.get_program()))?;
+
+    let tail = Arc::new(Mutex::new(VecDeque::with_capacity(lines)));
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let readers = [
+        spawn_reader(stdout, Arc::clone(&tail), lines, false),
+        spawn_reader(stderr, Arc::clone(&tail), lines, true),
+    ];
+
+    let status = child.wait().with_context(|| "Failed to wait for command")?;
+    for reader in readers {
+        let _ = reader.join();
+    }
+
+    let tail = tail.lock().expect("output readers have finished");
+    Ok((status, Vec::from(tail.clone()).join("\n")))
+}
+
+fn spawn_reader<R: Read + Send + 'static>(
+    reader: R,
+    tail: Arc<Mutex<VecDeque<String>>>,
+    lines: usize,
+    is_stderr: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(|line| line.ok()) {
+            if is_stderr {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+            let mut tail = tail.lock().expect("output reader panicked");
+            if tail.len() == lines {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    })
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reports sync progress by following bitcoind's `debug.log`, which it writes
+/// anyway, so the benchmarked command line stays the same with or without it.
+pub struct Watcher {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Watcher {
+    /// Start printing the height of each `UpdateTip` written to `debug_log` on stderr.
+    pub fn start(debug_log: PathBuf, stop_height: u64) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || follow(&debug_log, stop_height, &stop))
+        };
+        Watcher { stop, handle }
+    }
+
+    pub fn finish(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+        eprintln!();
+    }
+}
+
+fn follow(debug_log: &PathBuf, stop_height: u64, stop: &AtomicBool) {
+    let mut reader: Option<BufReader<File>> = None;
+    let mut position = 0;
+    let mut line = String::new();
+
+    while !stop.load(Ordering::Relaxed) {
+        // The prepare step wipes the datadir, so the log is recreated for every run
+        let len = debug_log.metadata().map(|m| m.len()).ok();
+        if len.is_none_or(|len| len < position) {
+            reader = None;
+            position = 0;
+        }
+        if reader.is_none() {
+            reader = File::open(debug_log).ok().map(BufReader::new);
+        }
+
+        if let Some(reader) = reader.as_mut() {
+            if reader.seek(SeekFrom::Start(position)).is_ok() {
+                line.clear();
+                while let Ok(read @ 1..) = reader.read_line(&mut line) {
+                    if !line.ends_with('\n') {
+                        // Partially written, read it again once it's complete
+                        break;
+                    }
+                    position += read as u64;
+                    if let Some(height) = update_tip_height(&line) {
+                        let pct = height as f64 / stop_height.max(1) as f64 * 100.0;
+                        eprint!("\rheight {}/{} ({:.1}%)", height, stop_height, pct);
+                    }
+                    line.clear();
+                }
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The new tip height logged by an `UpdateTip` line.
+pub fn update_tip_height(line: &str) -> Option<u64> {
+    let (_, rest) = line.split_once("UpdateTip: ")?;
+    rest.split_whitespace()
+        .find_map(|field| field.strip_prefix("height="))?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::config::Benchmark;
+
+/// `status` of an entry waiting to be benchmarked.
+const PENDING: &str = "pending";
+/// `status` of the entry the daemon's coordinator is benchmarking.
+const RUNNING: &str = "running";
+/// `status` of an entry whose benchmark completed.
+const DONE: &str = "done";
+/// `status` of an entry whose benchmark failed.
+const FAILED: &str = "failed";
+
+/// A commit waiting in the `queue` table to be benchmarked by the daemon.
+pub struct Entry {
+    pub id: i64,
+    pub commit: String,
+    /// Name of what to benchmark, `benchmark` when unset
+    scenario: Option<String>,
+    /// Overrides `stopatheight`
+    pub stopatheight: Option<u64>,
+    pub priority: i64,
+    /// Cron expression of the scheduled job that queued the entry, unset for
+    /// entries queued with `enqueue`
+    pub job: Option<String>,
+}
+
+impl Entry {
+    /// What to benchmark, `benchmark` when unset. Fails for a scenario this version
+    /// doesn't know, e.g. one queued by a newer version.
+    pub fn scenario(&self) -> Result<Option<Benchmark>> {
+        self.scenario
+            .as_deref()
+            .map(|name| {
+                serde_json::from_value(serde_json::Value::String(name.to_string())).with_context(
+                    || format!("Queue entry {} has unknown scenario `{}`", self.id, name),
+                )
+            })
+            .transpose()
+    }
+}
+
+/// Add `commit` to the queue, returning its id. Higher priorities run first.
+pub fn enqueue(
+    conn: &Connection,
+    commit: &str,
+    scenario: Option<Benchmark>,
+    stopatheight: Option<u64>,
+    priority: i64,
+    job: Option<&str>,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO queue (commit_hash, scenario, stopatheight, priority, job, status,
+                            enqueued_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            commit,
+            scenario.map(Benchmark::name),
+            stopatheight,
+            priority,
+            job,
+            PENDING,
+            Utc::now().to_rfc3339()
+        ],
+    )
+    .with_context(|| format!("Failed to queue {}", commit))?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Number of entries waiting to be benchmarked.
+pub fn pending(conn: &Connection) -> Result<usize> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM queue WHERE status = ?1",
+        [PENDING],
+        |row| row.get(0),
+    )
+    .with_context(|| "Failed to count queued commits")
+}
+
+/// Number of entries the scheduled job `job` queued that are waiting or running.
+pub fn active_for_job(conn: &Connection, job: &str) -> Result<usize> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM queue WHERE job = ?1 AND status IN (?2, ?3)",
+        params![job, PENDING, RUNNING],
+        |row| row.get(0),
+    )
+    .with_context(|| format!("Failed to count queued commits of job `{}`", job))
+}
+
+/// Mark the highest priority pending entry, oldest first among equals, as running
+/// and return it.
+pub fn claim_next(conn: &Connection) -> Result<Option<Entry>> {
+    let entry = conn
+        .query_row(
+            "SELECT id, commit_hash, scenario, stopatheight, priority, job FROM queue
+             WHERE status = ?1 ORDER BY priority DESC, id LIMIT 1",
+            [PENDING],
+            |row| {
+                Ok(Entry {
+                    id: row.get(0)?,
+                    commit: row.get(1)?,
+                    scenario: row.get(2)?,
+                    stopatheight: row.get(3)?,
+                    priority: row.get(4)?,
+                    job: row.get(5)?,
+                })
+            },
+        )
+        .optional()
+        .with_context(|| "Failed to read the queue")?;
+    if let Some(entry) = &entry {
+        set_status(conn, entry.id, RUNNING)?;
+    }
+    Ok(entry)
+}
+
+/// Record how the benchmark of entry `id` went.
+pub fn finish(conn: &Connection, id: i64, succeeded: bool) -> Result<()> {
+    set_status(conn, id, if succeeded { DONE } else { FAILED })
+}
+
+/// Put entries left running by a daemon that stopped mid-run back in the queue,
+/// returning how many there were.
+pub fn requeue_interrupted(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "UPDATE queue SET status = ?1 WHERE status = ?2",
+        [PENDING, RUNNING],
+    )
+    .with_context(|| "Failed to requeue interrupted commits")
+}
+
+fn set_status(conn: &Connection, id: i64, status: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE queue SET status = ?1 WHERE id = ?2",
+        params![status, id],
+    )
+    .with_context(|| format!("Failed to update queue entry {}", id))?;
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::config::Metric;
+use crate::db;
+
+/// A result that is slower than the baseline, or the previous run, of the same command.
+#[derive(Serialize)]
+pub struct Regression {
+    pub command: String,
+    pub commit: String,
+    /// The result's `metric`, named `mean` as it was before other metrics could be
+    /// chosen
+    pub mean: f64,
+    pub previous_commit: String,
+    pub previous_mean: f64,
+    /// Statistic of the runs that was compared
+    pub metric: Metric,
+    pub delta_pct: f64,
+    /// Confidence in the comparison, see `confidence`
+    pub confidence: f64,
+    /// Files the commit changed under `src/`, when recorded
+    pub src_files_changed: Option<u64>,
+}
+
+/// How far a result can be trusted, from 0 to 1: results of few runs, or whose runs
+/// vary a lot, score lower. A single run, whose variance is unknown, scores 0.5.
+pub fn confidence(runs: usize, mean: f64, stddev: Option<f64>) -> f64 {
+    let runs = runs.max(1) as f64;
+    let noise = match stddev {
+        // Every percent of variation knocks off roughly a tenth
+        Some(stddev) if runs > 1.0 && mean > 0.0 => 1.0 / (1.0 + stddev / mean * 10.0),
+        _ => 1.0,
+    };
+    runs / (runs + 1.0) * noise
+}
+
+/// The threshold a delta between results compared with `confidence` must exceed to
+/// count as a regression: `threshold_pct` widened in proportion to the lack of
+/// confidence, so e.g. a comparison of single runs needs 1.6 times the slowdown.
+pub fn threshold_for(threshold_pct: f64, confidence: f64) -> f64 {
+    threshold_pct * (FULL_CONFIDENCE / confidence.max(MIN_CONFIDENCE)).max(1.0)
+}
+
+/// Confidence at which the regression threshold applies unwidened, about that of
+/// hyperfine's default of 10 runs varying by 1%.
+const FULL_CONFIDENCE: f64 = 0.8;
+
+/// Floor on the confidence thresholds are widened for, so very noisy results can
+/// still be flagged for a large enough slowdown.
+const MIN_CONFIDENCE: f64 = 0.1;
+
+/// Compare each of the given rows against the promoted baseline for the same command
+/// and snapshot height, or the most recent earlier row when there is none,
+/// returning those whose `metric` grew by more than `threshold_pct`, widened for
+/// low confidence as `threshold_for` does.
+pub fn detect(
+    conn: &Connection,
+    ids: &[i64],
+    metric: Metric,
+    threshold_pct: f64,
+) -> Result<Vec<Regression>> {
+    Ok(compare_previous(conn, ids, metric, threshold_pct)?
+        .into_iter()
+        .filter_map(|comparison| {
+            let against = comparison.against.filter(|against| against.regression)?;
+            Some(Regression {
+                command: comparison.command,
+                commit: comparison.commit,
+                mean: comparison.value,
+                previous_commit: against.commit,
+                previous_mean: against.value,
+                metric,
+                delta_pct: against.delta_pct,
+                confidence: against.confidence,
+                src_files_changed: comparison.src_files_changed,
+            })
+        })
+        .collect())
+}
+
+/// A stored row and how it compares against the row [`detect`] judges it by.
+pub struct Comparison {
+    pub command: String,
+    pub commit: String,
+    /// The row's `metric`
+    pub value: f64,
+    pub src_files_changed: Option<u64>,
+    /// Absent for the first complete row of a command
+    pub against: Option<Against>,
+}
+
+/// The row a [`Comparison`] was made against.
+pub struct Against {
+    pub commit: String,
+    pub value: f64,
+    pub delta_pct: f64,
+    /// The regression threshold, widened for low confidence
+    pub threshold_pct: f64,
+    pub confidence: f64,
+    pub regression: bool,
+}
+
+/// Compare each of the given rows as [`detect`] does, keeping those that didn't
+/// regress too. Incomplete IBD runs are left out.
+pub fn compare_previous(
+    conn: &Connection,
+    ids: &[i64],
+    metric: Metric,
+    threshold_pct: f64,
+) -> Result<Vec<Comparison>> {
+    let mut comparisons = Vec::new();
+    let column = metric.column();
+
+    for &id in ids {
+        // Incomplete IBD runs are neither judged nor judged against
+        let current: Option<(String, Option<u64>, Option<u64>, Timing)> = conn
+            .query_row(
+                &format!(
+                    "SELECT command, snapshot_height, src_files_changed,
+                            commit_hash, {column}, stddev, exit_codes
+                     FROM benchmarks WHERE id = ?1 AND status IS NOT ?2"
+                ),
+                params![id, db::STATUS_INCOMPLETE],
+                |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        Timing::from_row(row, 3)?,
+                    ))
+                },
+            )
+            .optional()
+            .with_context(|| format!("Failed to read benchmark result {}", id))?;
+        let Some((command, snapshot, src_files_changed, timing)) = current else {
+            continue;
+        };
+
+        let previous: Option<Timing> = conn
+            .query_row(
+                &format!(
+                    "SELECT commit_hash, {column}, stddev, exit_codes FROM benchmarks
+                     WHERE command = ?1 AND snapshot_height IS ?2
+                       AND id <> ?3 AND {column} IS NOT NULL AND status IS NOT ?4
+                       AND (is_baseline = 1 OR id < ?3)
+                     ORDER BY is_baseline DESC, id DESC LIMIT 1"
+                ),
+                params![command, snapshot, id, db::STATUS_INCOMPLETE],
+                |row| Timing::from_row(row, 0),
+            )
+            .optional()
+            .with_context(|| "Failed to query previous benchmark result")?;
+
+        let against = previous.map(|previous| {
+            let confidence = timing.confidence.min(previous.confidence);
+            let delta_pct = (timing.value - previous.value) / previous.value * 100.0;
+            let threshold_pct = threshold_for(threshold_pct, confidence);
+            Against {
+                commit: previous.commit,
+                value: previous.value,
+                delta_pct,
+                threshold_pct,
+                confidence,
+                regression: delta_pct > threshold_pct,
+            }
+        });
+        comparisons.push(Comparison {
+            command,
+            commit: timing.commit,
+            value: timing.value,
+            src_files_changed,
+            against,
+        });
+    }
+
+    Ok(comparisons)
+}
+
+/// A stored row's timing, as regressions are judged on.
+struct Timing {
+    commit: String,
+    /// The compared metric
+    value: f64,
+    confidence: f64,
+}
+
+impl Timing {
+    /// Read `commit_hash`, the compared metric, `stddev` and `exit_codes` from `row`,
+    /// starting at column `first`.
+    fn from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Self> {
+        let value = row.get(first + 1)?;
+        let exit_codes: Option<String> = row.get(first + 3)?;
+        // One exit code is recorded per run
+        let runs = exit_codes
+            .and_then(|codes| serde_json::from_str::<Vec<Option<i32>>>(&codes).ok())
+            .map_or(1, |codes| codes.len());
+        Ok(Timing {
+            commit: row.get(first)?,
+            value,
+            confidence: confidence(runs, value, row.get(first + 2)?),
+        })
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::compare::{self, Base};
+use crate::config::{Config, Metric};
+use crate::db;
+use crate::git;
+use crate::output::{self, Render, Style};
+use crate::toolchain::Toolchain;
+
+/// A Markdown-ready summary of recently benchmarked commits, for pasting into
+/// issues and PRs.
+#[derive(Serialize)]
+pub struct Report {
+    /// When the earliest and latest of the summarized results were stored
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Statistic the results were compared by, and are shown by
+    pub metric: Metric,
+    pub threshold_pct: f64,
+    /// Newest first, in git topological order where the repository has the commits
+    pub commits: Vec<CommitSummary>,
+}
+
+#[derive(Serialize)]
+pub struct CommitSummary {
+    pub commit: String,
+    pub commit_alias: Option<String>,
+    /// Link to the pull request the commit merged, if any
+    pub pr_url: Option<String>,
+    /// The parent the deltas are against, if it was benchmarked
+    pub base: Option<String>,
+    /// Compilers and CMake the commit was built with, if recorded
+    pub toolchain: Option<Toolchain>,
+    pub results: Vec<ResultSummary>,
+}
+
+#[derive(Serialize)]
+pub struct ResultSummary {
+    pub command: String,
+    pub mean: Option<f64>,
+    /// The result's `metric`
+    pub value: Option<f64>,
+    /// Change in `metric` relative to the parent, in percent
+    pub delta_pct: Option<f64>,
+    pub regression: bool,
+}
+
+/// Summarize the latest results of the `limit` most recently benchmarked commits,
+/// each compared against its parent as `compare` would.
+pub fn build(conn: &Connection, limit: usize, config: &Config) -> Result<Report> {
+    let threshold_pct = config.regression_threshold_pct;
+    let mut commits = Vec::new();
+    let mut latest = Vec::new();
+    for commit in db::recent_commits(conn, limit)? {
+        let results = db::latest_results(conn, &commit)?;
+        let base = match compare::resolve_base(conn, &commit, None) {
+            Ok(Base::Stored(base)) => Some(base),
+            Ok(Base::Unbenchmarked(_)) | Err(_) => None,
+        };
+        let base_results = match &base {
+            Some(base) => db::latest_results(conn, base)?,
+            None => Vec::new(),
+        };
+
+        let comparison = compare::Report::new(
+            &commit,
+            &results,
+            base.as_deref().unwrap_or_default(),
+            &base_results,
+            config.comparison_metric,
+            threshold_pct,
+        );
+        let summaries = results
+            .iter()
+            .map(|result| {
+                let compared = comparison
+                    .comparisons
+                    .iter()
+                    .find(|c| std::ptr::eq(c.commit, result));
+                ResultSummary {
+                    command: result.command.clone(),
+                    mean: result.mean,
+                    value: result.value(config.comparison_metric),
+                    delta_pct: compared.map(|c| c.delta_pct),
+                    regression: compared.is_some_and(|c| c.regression),
+                }
+            })
+            .collect();
+        commits.push(CommitSummary {
+            commit_alias: results.iter().find_map(|r| r.commit_alias.clone()),
+            pr_url: results
+                .iter()
+                .find_map(|r| r.pr_number)
+                .map(|pr| format!("{}{}", config.pr_url, pr)),
+            commit: commit.clone(),
+            base,
+            toolchain: results
+                .iter()
+                .filter_map(|r| r.toolchain.as_deref())
+                .find_map(|toolchain| serde_json::from_str(toolchain).ok()),
+            results: summaries,
+        });
+        latest.extend(results);
+    }
+
+    if let Err(e) = git::sort_topo(&config.repo_path, &mut commits, |c| &c.commit) {
+        eprintln!(
+            "Warning: Reporting commits in the order they were benchmarked: {:#}",
+            e
+        );
+    }
+
+    let (from, to) = db::stored_between(conn, &latest.iter().collect::<Vec<_>>())?;
+    Ok(Report {
+        from,
+        to,
+        metric: config.comparison_metric,
+        threshold_pct,
+        commits,
+    })
+}
+
+impl Report {
+    /// The report as Markdown, with commit hashes and timings shown as `style` says.
+    pub fn markdown(&self, style: &Style) -> String {
+        let mut hashes: Vec<&str> = self.commits.iter().map(|c| c.commit.as_str()).collect();
+        hashes.extend(self.commits.iter().filter_map(|c| c.base.as_deref()));
+        let hash_len = output::hash_length(hashes, style.hash_len);
+        let unit = style.unit_for(
+            self.commits
+                .iter()
+                .flat_map(|c| &c.results)
+                .filter_map(|r| r.value),
+        );
+        // Linked to the pull request the commit merged, if any
+        let label = |commit: &CommitSummary| {
+            let name = commit
+                .commit_alias
+                .as_deref()
+                .unwrap_or_else(|| output::short_hash(&commit.commit, hash_len));
+            match &commit.pr_url {
+                Some(url) => format!("[`{}`]({})", name, url),
+                None => format!("`{}`", name),
+            }
+        };
+
+        let mut md = String::from("## Benchmark report\n\n");
+        if self.commits.is_empty() {
+            md.push_str("No results stored.\n");
+            return md;
+        }
+        md.push_str(&format!("Latest results of {} commits", self.commits.len()));
+        if let (Some(from), Some(to)) = (&self.from, &self.to) {
+            md.push_str(&format!(
+                ", benchmarked between {} and {}",
+                style.local_time(from),
+                style.local_time(to)
+            ));
+        }
+        md.push_str(".\n\n");
+
+        let regressions: Vec<(&CommitSummary, &ResultSummary)> = self
+            .commits
+            .iter()
+            .flat_map(|c| {
+                c.results
+                    .iter()
+                    .filter(|r| r.regression)
+                    .map(move |r| (c, r))
+            })
+            .collect();
+        if !regressions.is_empty() {
+            md.push_str(&format!(
+                "> [!WARNING]\n> {} slower than their parent by more than {}%:\n",
+                plural(regressions.len(), "result"),
+                self.threshold_pct
+            ));
+            for (commit, result) in &regressions {
+                md.push_str(&format!(
+                    "> - {} `{}`: {:+.1}%\n",
+                    label(commit),
+                    escape(&result.command),
+                    result.delta_pct.unwrap_or_default()
+                ));
+            }
+            md.push('\n');
+        }
+
+        let heading = match self.metric {
+            Metric::Mean => "Mean",
+            Metric::Median => "Median",
+            Metric::Min => "Min",
+        };
+        md.push_str(&format!(
+            "| Commit | Command | {} ({}) | Δ vs parent |\n",
+            heading,
+            output::suffix(unit)
+        ));
+        md.push_str("|---|---|---:|---:|\n");
+        for commit in &self.commits {
+            for result in &commit.results {
+                md.push_str(&format!(
+                    "| {} | `{}` | {} | {}{} |\n",
+                    label(commit),
+                    escape(&result.command),
+                    result
+                        .value
+                        .map_or("-".to_string(), |v| style.number(v, unit)),
+                    result
+                        .delta_pct
+                        .map_or("-".to_string(), |d| format!("{:+.1}%", d)),
+                    if result.regression { " ⚠️" } else { "" }
+                ));
+            }
+        }
+
+        // Each toolchain with the commits built with it, in order of first use
+        let mut toolchains: Vec<(&Toolchain, Vec<&CommitSummary>)> = Vec::new();
+        for commit in &self.commits {
+            let Some(toolchain) = &commit.toolchain else {
+                continue;
+            };
+            match toolchains.iter_mut().find(|(t, _)| *t == toolchain) {
+                Some((_, commits)) => commits.push(commit),
+                None => toolchains.push((toolchain, vec![commit])),
+            }
+        }
+        match toolchains.as_slice() {
+            [] => {}
+            [(toolchain, _)] => md.push_str(&format!("\nBuilt with {}.\n", toolchain.summary())),
+            _ => {
+                md.push_str("\nBuilt with differing toolchains:\n");
+                for (toolchain, commits) in &toolchains {
+                    let labels: Vec<String> = commits.iter().map(|c| label(c)).collect();
+                    md.push_str(&format!(
+                        "- {}: {}\n",
+                        toolchain.summary(),
+                        labels.join(", ")
+                    ));
+                }
+            }
+        }
+        md
+    }
+}
+
+impl Render for Report {
+    fn print_human(&self, style: &Style) {
+        print!("{}", self.markdown(style));
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "commit",
+            "commit_alias",
+            "base",
+            "command",
+            "mean",
+            "metric",
+            "value",
+            "delta_pct",
+            "regression",
+            "toolchain",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for commit in &self.commits {
+            for result in &commit.results {
+                rows.push(vec![
+                    commit.commit.clone(),
+                    output::csv_value(commit.commit_alias.as_deref()),
+                    output::csv_value(commit.base.as_deref()),
+                    result.command.clone(),
+                    output::csv_value(result.mean),
+                    self.metric.column().to_string(),
+                    output::csv_value(result.value),
+                    output::csv_value(result.delta_pct),
+                    result.regression.to_string(),
+                    commit
+                        .toolchain
+                        .as_ref()
+                        .map_or(String::new(), Toolchain::summary),
+                ]);
+            }
+        }
+        rows
+    }
+}
+
+/// Keep a command from breaking out of its table cell.
+fn escape(command: &str) -> String {
+    command.replace('|', "\\|")
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
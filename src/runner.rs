@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::Config;
+
+/// Where the git, build and hyperfine steps of a run execute: this machine, or a
+/// benchmark machine reached over SSH.
+pub trait Runner {
+    /// A command running `program` with `args` in the repository checkout.
+    fn command(&self, program: &str, args: &[&str]) -> Command;
+
+    /// Copy `path`, written by a command, to `local` on this machine. A remote
+    /// machine's copy is removed once fetched, so runs don't pile up files there.
+    fn fetch_file(&self, path: &Path, local: &Path) -> Result<()>;
+
+    /// Whether commands run on this machine, so its load, CPU and kernel log
+    /// describe the benchmark.
+    fn is_local(&self) -> bool;
+}
+
+/// Runs commands on this machine.
+pub struct Local {
+    repo_path: PathBuf,
+}
+
+impl Local {
+    pub fn new(repo_path: &Path) -> Self {
+        Local {
+            repo_path: repo_path.to_path_buf(),
+        }
+    }
+}
+
+impl Runner for Local {
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut command = Command::new(program);
+        command.args(args).current_dir(&self.repo_path);
+        command
+    }
+
+    fn fetch_file(&self, path: &Path, local: &Path) -> Result<()> {
+        if path != local {
+            fs::copy(path, local).with_context(|| {
+                format!("Failed to copy {} to {}", path.display(), local.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+/// Runs commands on `host` with `ssh`, in its checkout at `repo_path`. Authentication
+/// is left to the SSH configuration, e.g. keys in `~/.ssh/config`.
+pub struct Ssh {
+    host: String,
+    repo_path: PathBuf,
+}
+
+impl Runner for Ssh {
+    fn command(&self, program: &str, args: &[&str]) -> Command {
+        let mut script = format!("cd {} &&", quote(&self.repo_path.to_string_lossy()));
+        for arg in std::iter::once(&program).chain(args) {
+            script.push(' ');
+            script.push_str(&quote(arg));
+        }
+        let mut command = Command::new("ssh");
+        // Fail instead of prompting, as nobody may be there to answer
+        command.args(["-o", "BatchMode=yes", &self.host, "--", &script]);
+        command
+    }
+
+    fn fetch_file(&self, path: &Path, local: &Path) -> Result<()> {
+        let status = Command::new("scp")
+            .args(["-q", "-o", "BatchMode=yes"])
+            .arg(format!("{}:{}", self.host, path.display()))
+            .arg(local)
+            .status()
+            .with_context(|| "Failed to execute scp")?;
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "Copying {} from {} failed with status {}",
+                path.display(),
+                self.host,
+                status
+            ));
+        }
+        let path = path.to_string_lossy();
+        match self.command("rm", &["-f", &path]).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!(
+                "Warning: removing {} from {} failed with status {}",
+                path, self.host, status
+            ),
+            Err(e) => eprintln!(
+                "Warning: failed to remove {} from {}: {}",
+                path, self.host, e
+            ),
+        }
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// The runner `config` selects: SSH when `ssh_host` is set, this machine otherwise.
+pub fn for_config(config: &Config) -> Box<dyn Runner> {
+    match &config.ssh_host {
+        Some(host) => Box::new(Ssh {
+            host: host.clone(),
+            repo_path: config
+                .remote_repo_path
+                .clone()
+                .unwrap_or_else(|| config.repo_path.clone()),
+        }),
+        None => Box::new(Local::new(&config.repo_path)),
+    }
+}
+
+/// `arg` quoted for a POSIX shell.
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::bitcoind;
+use crate::config::Config;
+use crate::runner::Runner;
+
+/// File in a seed datadir recording the height it was synced to.
+const SEED_HEIGHT_FILE: &str = "bench_seed_height";
+
+/// The shell copying the configured scenario's seed datadir into the wiped
+/// `datadir`, if it has one. The seed's log and height marker are left out, so a
+/// run's `debug.log` only holds its own lines.
+pub fn restore_command(config: &Config) -> Option<String> {
+    let seed = config.scenario()?.seed_datadir.as_ref()?;
+    let datadir = config.datadir.display();
+    Some(format!(
+        "cp -a {}/. {}/ && rm -f {}/debug.log {}/{}",
+        seed.display(),
+        datadir,
+        datadir,
+        datadir,
+        SEED_HEIGHT_FILE
+    ))
+}
+
+/// Bring the configured scenario's seed datadir to its `require_sync_height` by
+/// syncing it with the bitcoind at `bitcoind`, and return the height the seed
+/// holds, if known. A seed synced below that height continues from where it is,
+/// one synced past it is wiped and synced again from genesis.
+pub fn ensure(config: &Config, runner: &dyn Runner, bitcoind: &str) -> Result<Option<u64>> {
+    let Some(scenario) = config.scenario() else {
+        return Ok(None);
+    };
+    let seed = scenario
+        .seed_datadir
+        .as_ref()
+        .expect("scenarios returned by Config::scenario have a seed");
+    let synced = synced_height(runner, seed);
+    let Some(required) = scenario.require_sync_height else {
+        return Ok(synced);
+    };
+    if synced == Some(required) {
+        println!(
+            "Seed datadir at {} is synced to height {}",
+            seed.display(),
+            required
+        );
+        return Ok(synced);
+    }
+    if synced.is_none() && !is_empty(runner, seed) {
+        anyhow::bail!(
+            "{} is not empty but was not synced by the benchmark, so its height is \
+             unknown; wipe it to seed it from genesis",
+            seed.display()
+        );
+    }
+
+    let mut steps = vec![format!("mkdir -p {}", seed.display())];
+    match synced {
+        Some(height) if height > required => {
+            println!(
+                "Seed datadir at {} is synced past height {}, syncing it again from genesis",
+                seed.display(),
+                required
+            );
+            steps.push(format!("rm -Rf {}/*", seed.display()));
+        }
+        Some(height) => println!(
+            "Syncing seed datadir at {} from height {} to {}",
+            seed.display(),
+            height,
+            required
+        ),
+        None => println!(
+            "Syncing seed datadir at {} to height {}",
+            seed.display(),
+            required
+        ),
+    }
+    let seed_config = Config {
+        datadir: seed.clone(),
+        stopatheight: required,
+        ..config.clone()
+    };
+    steps.push(bitcoind::command_using(&seed_config, bitcoind));
+    steps.push(format!(
+        "echo {} > {}/{}",
+        required,
+        seed.display(),
+        SEED_HEIGHT_FILE
+    ));
+    let status = runner
+        .command(&config.shell, &["-c", &steps.join(" && ")])
+        .status()
+        .with_context(|| "Failed to execute bitcoind to sync the seed datadir")?;
+    if !status.success() {
+        anyhow::bail!(
+            "Syncing seed datadir {} to height {} failed with {}",
+            seed.display(),
+            required,
+            status
+        );
+    }
+    Ok(Some(required))
+}
+
+/// The height recorded in `seed`, if it was synced by [`ensure`].
+fn synced_height(runner: &dyn Runner, seed: &Path) -> Option<u64> {
+    let output = runner
+        .command("cat", &[&seed.join(SEED_HEIGHT_FILE).to_string_lossy()])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Whether `seed` is missing or has nothing in it.
+fn is_empty(runner: &dyn Runner, seed: &Path) -> bool {
+    runner
+        .command("ls", &["-A", &seed.to_string_lossy()])
+        .output()
+        .map_or(true, |output| output.stdout.is_empty())
+}
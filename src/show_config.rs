@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::config::{Config, Source};
+use crate::output::{Render, Style};
+
+/// The output of `show-config`.
+#[derive(Serialize)]
+pub struct Effective {
+    /// Every field, with `null` for those that are unset
+    config: serde_json::Map<String, serde_json::Value>,
+    /// Where each field's value came from
+    sources: BTreeMap<String, Source>,
+    /// Set fields as TOML, for printing in a form the config file accepts
+    #[serde(skip)]
+    toml: toml::Table,
+}
+
+/// Describe `config`, with `sources` as returned by `Config::load_with_sources`.
+/// Fields missing from `sources` are defaults.
+pub fn build(config: &Config, mut sources: BTreeMap<String, Source>) -> Result<Effective> {
+    let serde_json::Value::Object(fields) =
+        serde_json::to_value(config).with_context(|| "Failed to serialize configuration")?
+    else {
+        anyhow::bail!("Configuration did not serialize to an object");
+    };
+    for key in fields.keys() {
+        sources.entry(key.clone()).or_insert(Source::Default);
+    }
+    let toml =
+        toml::Table::try_from(config).with_context(|| "Failed to serialize configuration")?;
+    Ok(Effective {
+        config: fields,
+        sources,
+        toml,
+    })
+}
+
+impl Effective {
+    fn source(&self, key: &str) -> Source {
+        self.sources.get(key).copied().unwrap_or(Source::Default)
+    }
+}
+
+impl Render for Effective {
+    /// TOML with each line commented with its source. Unset fields are commented out.
+    fn print_human(&self, _style: &Style) {
+        for key in self.config.keys() {
+            let source = self.source(key).name();
+            match self.toml.get(key) {
+                Some(value) => println!("{} = {}  # {}", key, value, source),
+                None => println!("# {} is not set  # {}", key, source),
+            }
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = ["key", "value", "source"];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for (key, value) in &self.config {
+            let source = self.source(key).name().to_string();
+            let value = match value {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            rows.push(vec![key.clone(), value, source]);
+        }
+        rows
+    }
+}
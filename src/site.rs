@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use rusqlite::Connection;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::db;
+use crate::output::Style;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 240.0;
+const MARGIN: f64 = 40.0;
+
+/// Every stored mean of one command, oldest first.
+struct Series {
+    command: String,
+    /// Seconds since the epoch the result was stored at, and its mean
+    points: Vec<(i64, f64)>,
+    latest_commit: String,
+    /// When the latest result was stored, as RFC 3339
+    latest_at: String,
+}
+
+/// Render a chart of each command's mean over time, and an `index.html` listing
+/// them, into `site_dir`, then sync it to `site_s3_url` if set. Returns how many
+/// charts were written; nothing is done without a `site_dir`. Timings and times in
+/// the index are shown in `style`.
+pub fn publish(config: &Config, conn: &Connection, style: &Style) -> Result<usize> {
+    let Some(site_dir) = &config.site_dir else {
+        return Ok(0);
+    };
+    fs::create_dir_all(site_dir)
+        .with_context(|| format!("Failed to create site_dir at {}", site_dir.display()))?;
+
+    let series = load(conn)?;
+    let mut index = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Benchmark results</title>\n</head>\n<body>\n<h1>Benchmark results</h1>\n",
+    );
+    if series.is_empty() {
+        index.push_str("<p>No results stored.</p>\n");
+    }
+    for (i, series) in series.iter().enumerate() {
+        let chart = format!("chart-{}.svg", i);
+        let path = site_dir.join(&chart);
+        fs::write(&path, svg(series))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let (_, latest) = series.points[series.points.len() - 1];
+        index.push_str(&format!(
+            "<h2><code>{}</code></h2>\n<p>{} runs, latest {} at <code>{}</code> on {}</p>\n\
+             <img src=\"{}\" alt=\"Mean over time\">\n",
+            escape(&series.command),
+            series.points.len(),
+            style.duration(latest),
+            escape(&series.latest_commit),
+            escape(&style.local_time(&series.latest_at)),
+            chart
+        ));
+    }
+    index.push_str("</body>\n</html>\n");
+    let index_path = site_dir.join("index.html");
+    fs::write(&index_path, index)
+        .with_context(|| format!("Failed to write {}", index_path.display()))?;
+
+    if let Some(url) = &config.site_s3_url {
+        upload(site_dir, url)?;
+    }
+    Ok(series.len())
+}
+
+/// Completed results, including those stored before statuses were, grouped by
+/// command in the order commands were first stored.
+fn load(conn: &Connection) -> Result<Vec<Series>> {
+    let mut stmt = conn.prepare(
+        "SELECT command, created_at, mean, commit_hash FROM benchmarks
+         WHERE mean IS NOT NULL AND created_at IS NOT NULL AND coalesce(status, ?1) = ?1
+         ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map([db::STATUS_OK], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .with_context(|| "Failed to read results to chart")?;
+
+    let mut series: Vec<Series> = Vec::new();
+    for (command, created_at, mean, commit) in rows {
+        let Ok(time) = DateTime::parse_from_rfc3339(&created_at) else {
+            continue;
+        };
+        let point = (time.timestamp(), mean);
+        match series.iter_mut().find(|s| s.command == command) {
+            Some(s) => {
+                s.points.push(point);
+                s.latest_commit = commit;
+                s.latest_at = created_at;
+            }
+            None => series.push(Series {
+                command,
+                points: vec![point],
+                latest_commit: commit,
+                latest_at: created_at,
+            }),
+        }
+    }
+    Ok(series)
+}
+
+/// A line chart of `series`, time along the x axis and mean up the y axis.
+fn svg(series: &Series) -> String {
+    let (first, last) = (series.points[0].0, series.points[series.points.len() - 1].0);
+    let means = series.points.iter().map(|&(_, mean)| mean);
+    let min = means.clone().fold(f64::INFINITY, f64::min);
+    let max = means.fold(f64::NEG_INFINITY, f64::max);
+    let x = |time: i64| {
+        let span = (last - first).max(1) as f64;
+        MARGIN + (time - first) as f64 / span * (WIDTH - 2.0 * MARGIN)
+    };
+    let y = |mean: f64| {
+        let span = (max - min).max(f64::EPSILON);
+        HEIGHT - MARGIN - (mean - min) / span * (HEIGHT - 2.0 * MARGIN)
+    };
+    let points: Vec<String> = series
+        .points
+        .iter()
+        .map(|&(time, mean)| format!("{:.1},{:.1}", x(time), y(mean)))
+        .collect();
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         font-family=\"sans-serif\" font-size=\"11\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <text x=\"4\" y=\"{top}\">{max:.3}s</text>\n\
+         <text x=\"4\" y=\"{bottom}\">{min:.3}s</text>\n\
+         <polyline fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\" points=\"{points}\"/>\n\
+         </svg>\n",
+        w = WIDTH,
+        h = HEIGHT,
+        top = MARGIN,
+        bottom = HEIGHT - MARGIN,
+        max = max,
+        min = min,
+        points = points.join(" ")
+    )
+}
+
+/// Mirror `site_dir` to `url`, e.g. `s3://bucket/prefix`, with the AWS CLI, which
+/// takes its credentials from the usual environment and config files.
+fn upload(site_dir: &Path, url: &str) -> Result<()> {
+    let status = Command::new("aws")
+        .args(["s3", "sync", "--delete", "--only-show-errors"])
+        .arg(site_dir)
+        .arg(url)
+        .status()
+        .with_context(|| "Failed to run aws")?;
+    if !status.success() {
+        return Err(anyhow::anyhow!(
+            "Uploading {} to {} failed with {}",
+            site_dir.display(),
+            url,
+            status
+        ));
+    }
+    Ok(())
+}
+
+/// Keep `text` from being read as HTML markup.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,158 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::db::StoredResult;
+use crate::output::{self, Render, Style};
+
+/// How much one command's mean varies between runs, to judge whether it is stable
+/// enough to gate on.
+#[derive(Serialize)]
+pub struct Stability {
+    pub command: String,
+    pub snapshot_height: Option<u64>,
+    pub runs: usize,
+    /// Commits this command was benchmarked at more than once
+    pub repeated_commits: usize,
+    /// Average coefficient of variation between runs of the same commit, in percent
+    pub repeat_cv_pct: Option<f64>,
+    /// Coefficient of variation across the most recent runs, whatever their commit,
+    /// in percent. Includes genuine changes between commits, so only an upper bound.
+    pub recent_cv_pct: Option<f64>,
+    /// Whether the noise alone could exceed the regression threshold
+    pub noisy: bool,
+}
+
+/// The output of `stability`.
+#[derive(Serialize)]
+pub struct Report {
+    pub threshold_pct: f64,
+    pub commands: Vec<Stability>,
+}
+
+/// Aggregate `results`, oldest first, per command and snapshot height, taking the
+/// recent variation over the last `recent` runs of each.
+pub fn build(results: &[StoredResult], recent: usize, threshold_pct: f64) -> Report {
+    let mut groups: BTreeMap<(&str, Option<u64>), Vec<&StoredResult>> = BTreeMap::new();
+    for result in results.iter().filter(|r| r.mean.is_some()) {
+        groups
+            .entry((&result.command, result.snapshot_height))
+            .or_default()
+            .push(result);
+    }
+
+    let commands = groups
+        .into_iter()
+        .map(|((command, snapshot_height), runs)| {
+            let mut by_commit: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+            for run in &runs {
+                by_commit
+                    .entry(&run.commit_hash)
+                    .or_default()
+                    .extend(run.mean);
+            }
+            let repeats: Vec<f64> = by_commit
+                .values()
+                .filter(|means| means.len() > 1)
+                .filter_map(|means| cv_pct(means))
+                .collect();
+            let repeat_cv_pct =
+                (!repeats.is_empty()).then(|| repeats.iter().sum::<f64>() / repeats.len() as f64);
+
+            let recent_means: Vec<f64> = runs
+                .iter()
+                .rev()
+                .take(recent)
+                .filter_map(|r| r.mean)
+                .collect();
+            let recent_cv_pct = cv_pct(&recent_means);
+
+            Stability {
+                command: command.to_string(),
+                snapshot_height,
+                runs: runs.len(),
+                repeated_commits: repeats.len(),
+                repeat_cv_pct,
+                recent_cv_pct,
+                noisy: repeat_cv_pct
+                    .or(recent_cv_pct)
+                    .is_some_and(|cv| cv > threshold_pct),
+            }
+        })
+        .collect();
+    Report {
+        threshold_pct,
+        commands,
+    }
+}
+
+/// Sample standard deviation as a percentage of the mean, `None` for fewer than
+/// two values.
+fn cv_pct(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    Some(variance.sqrt() / mean * 100.0)
+}
+
+impl Render for Report {
+    fn print_human(&self, _style: &Style) {
+        if self.commands.is_empty() {
+            println!("No timed results stored");
+            return;
+        }
+
+        let pct = |cv: Option<f64>| cv.map_or("-".to_string(), |cv| format!("{:.2}%", cv));
+        println!(
+            "{:>6}  {:>9}  {:>10}  {:>10}  {:<6}  command",
+            "runs", "repeated", "repeat cv", "recent cv", ""
+        );
+        for stability in &self.commands {
+            println!(
+                "{:>6}  {:>9}  {:>10}  {:>10}  {:<6}  {}{}",
+                stability.runs,
+                stability.repeated_commits,
+                pct(stability.repeat_cv_pct),
+                pct(stability.recent_cv_pct),
+                if stability.noisy { "noisy" } else { "" },
+                stability.command,
+                stability
+                    .snapshot_height
+                    .map_or(String::new(), |height| format!(" (snapshot {})", height))
+            );
+        }
+        if self.commands.iter().any(|s| s.noisy) {
+            println!(
+                "\nNoisy commands vary by more than the {}% regression threshold between runs",
+                self.threshold_pct
+            );
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = [
+            "command",
+            "snapshot_height",
+            "runs",
+            "repeated_commits",
+            "repeat_cv_pct",
+            "recent_cv_pct",
+            "noisy",
+        ];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for stability in &self.commands {
+            rows.push(vec![
+                stability.command.clone(),
+                output::csv_value(stability.snapshot_height),
+                stability.runs.to_string(),
+                stability.repeated_commits.to_string(),
+                output::csv_value(stability.repeat_cv_pct),
+                output::csv_value(stability.recent_cv_pct),
+                stability.noisy.to_string(),
+            ]);
+        }
+        rows
+    }
+}
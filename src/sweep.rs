@@ -0,0 +1,105 @@
+use serde::Serialize;
+
+use crate::output::{self, Render, Style};
+
+/// How one `-dbcache` value of a sweep went.
+#[derive(Serialize)]
+pub struct Point {
+    /// Megabytes of UTXO cache
+    pub dbcache: u64,
+    /// Mean of the run, if it completed
+    pub mean: Option<f64>,
+    /// Whether bitcoind was killed for running out of memory
+    pub oom: bool,
+    /// Why the run failed, if it failed for any other reason
+    pub error: Option<String>,
+}
+
+/// The output of `dbcache-sweep`.
+#[derive(Serialize)]
+pub struct Summary {
+    pub commit: String,
+    /// Smallest value that completed with no larger value running out of memory
+    pub min_viable_dbcache: Option<u64>,
+    /// Smallest value first
+    pub points: Vec<Point>,
+}
+
+/// Summarize the sweep of `commit` made of `points`.
+pub fn summarize(commit: String, mut points: Vec<Point>) -> Summary {
+    points.sort_by_key(|point| point.dbcache);
+    let largest_oom = points.iter().filter(|p| p.oom).map(|p| p.dbcache).max();
+    let min_viable_dbcache = points
+        .iter()
+        .filter(|p| p.mean.is_some() && largest_oom.is_none_or(|oom| p.dbcache > oom))
+        .map(|p| p.dbcache)
+        .next();
+    Summary {
+        commit,
+        min_viable_dbcache,
+        points,
+    }
+}
+
+impl Summary {
+    fn min_viable_mean(&self) -> Option<f64> {
+        let min_viable = self.min_viable_dbcache?;
+        self.points
+            .iter()
+            .find(|p| p.dbcache == min_viable)
+            .and_then(|p| p.mean)
+    }
+}
+
+impl Render for Summary {
+    fn print_human(&self, style: &Style) {
+        let hash_len = output::hash_length([self.commit.as_str()], style.hash_len);
+        println!(
+            "dbcache sweep of {}",
+            output::short_hash(&self.commit, hash_len)
+        );
+        let unit = style.unit_for(self.points.iter().filter_map(|p| p.mean));
+        let base = self.min_viable_mean();
+        println!(
+            "{:>12}  {:>12}  {:>12}",
+            "dbcache (MB)",
+            format!("mean ({})", output::suffix(unit)),
+            "vs minimum"
+        );
+        for point in &self.points {
+            let (mean, delta) = match (point.mean, point.oom) {
+                (_, true) => ("OOM".to_string(), String::new()),
+                (Some(mean), false) => (
+                    style.number(mean, unit),
+                    base.map_or(String::new(), |base| {
+                        format!("{:+.1}%", (mean - base) / base * 100.0)
+                    }),
+                ),
+                (None, false) => ("failed".to_string(), String::new()),
+            };
+            println!("{:>12}  {:>12}  {:>12}", point.dbcache, mean, delta);
+            if let Some(error) = &point.error {
+                println!("{:>12}  error: {}", "", error);
+            }
+        }
+        match self.min_viable_dbcache {
+            Some(dbcache) => println!("Minimum viable dbcache: {} MB", dbcache),
+            None => println!("No dbcache value completed without running out of memory"),
+        }
+    }
+
+    fn csv_rows(&self) -> Vec<Vec<String>> {
+        let header = ["commit", "dbcache", "mean", "oom", "error"];
+        let mut rows = vec![header.map(String::from).to_vec()];
+        for point in &self.points {
+            rows.push(vec![
+                self.commit.clone(),
+                point.dbcache.to_string(),
+                output::csv_value(point.mean),
+                point.oom.to_string(),
+                point.error.clone().unwrap_or_default(),
+            ]);
+        }
+        rows
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use sysinfo::System;
+
+/// A snapshot of how busy the machine is at a point in time.
+pub struct LoadSample {
+    /// 1-minute load average
+    pub load_avg: f64,
+    cpu_times: Option<CpuTimes>,
+    /// Bytes of swap in use
+    pub swap_used: u64,
+    swap_counters: Option<SwapCounters>,
+}
+
+/// Pages swapped in and out since boot, from `/proc/vmstat`.
+#[derive(Clone, Copy)]
+struct SwapCounters {
+    pages_in: u64,
+    pages_out: u64,
+}
+
+/// Aggregate CPU time counters from `/proc/stat`, in clock ticks.
+#[derive(Clone, Copy)]
+struct CpuTimes {
+    steal: u64,
+    total: u64,
+}
+
+/// Number of CPUs available to this process, portable across Linux and macOS.
+pub fn cpu_count() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// This machine's hostname, which results are attributed to.
+pub fn hostname() -> Option<String> {
+    System::host_name()
+}
+
+pub fn sample_load() -> LoadSample {
+    let mut system = System::new();
+    system.refresh_memory();
+    LoadSample {
+        load_avg: System::load_average().one,
+        cpu_times: read_cpu_times(),
+        swap_used: system.used_swap(),
+        swap_counters: read_swap_counters(),
+    }
+}
+
+/// Pages swapped in and out between two samples, if known.
+pub fn swapped_pages(start: &LoadSample, end: &LoadSample) -> Option<(u64, u64)> {
+    let (start, end) = (start.swap_counters?, end.swap_counters?);
+    Some((
+        end.pages_in.saturating_sub(start.pages_in),
+        end.pages_out.saturating_sub(start.pages_out),
+    ))
+}
+
+/// Percentage of CPU time stolen by the hypervisor between two samples, if known.
+pub fn steal_pct(start: &LoadSample, end: &LoadSample) -> Option<f64> {
+    let (start, end) = (start.cpu_times?, end.cpu_times?);
+    let total = end.total.checked_sub(start.total)?;
+    if total == 0 {
+        return None;
+    }
+    let steal = end.steal.saturating_sub(start.steal);
+    Some(steal as f64 / total as f64 * 100.0)
+}
+
+#[cfg(target_os = "linux")]
+fn read_cpu_times() -> Option<CpuTimes> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let fields: Vec<u64> = stat
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .map(|f| f.parse().ok())
+        .collect::<Option<_>>()?;
+    // user nice system idle iowait irq softirq steal [guest guest_nice]
+    // guest time is already included in user/nice, so only sum the first eight
+    let steal = *fields.get(7)?;
+    let total = fields.iter().take(8).sum();
+    Some(CpuTimes { steal, total })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_cpu_times() -> Option<CpuTimes> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn read_swap_counters() -> Option<SwapCounters> {
+    let vmstat = std::fs::read_to_string("/proc/vmstat").ok()?;
+    let counter = |name: &str| {
+        vmstat.lines().find_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            (key == name).then(|| value.trim().parse().ok()).flatten()
+        })
+    };
+    Some(SwapCounters {
+        pages_in: counter("pswpin")?,
+        pages_out: counter("pswpout")?,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_swap_counters() -> Option<SwapCounters> {
+    None
+}
+
+/// The cpufreq scaling governor setting of one CPU.
+#[derive(Clone)]
+pub struct CpuGovernor {
+    path: PathBuf,
+    pub governor: String,
+}
+
+/// The scaling governor of every CPU. Empty when frequency scaling isn't exposed,
+/// as in most VMs.
+#[cfg(target_os = "linux")]
+pub fn cpu_governors() -> Vec<CpuGovernor> {
+    let Ok(entries) = std::fs::read_dir("/sys/devices/system/cpu") else {
+        return Vec::new();
+    };
+    let mut governors: Vec<CpuGovernor> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path().join("cpufreq/scaling_governor");
+            let governor = std::fs::read_to_string(&path).ok()?.trim().to_string();
+            Some(CpuGovernor { path, governor })
+        })
+        .collect();
+    governors.sort_by(|a, b| a.path.cmp(&b.path));
+    governors
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn cpu_governors() -> Vec<CpuGovernor> {
+    Vec::new()
+}
+
+/// Switch every CPU to `governor`. Requires root.
+pub fn set_cpu_governors(cpus: &[CpuGovernor], governor: &str) -> Result<()> {
+    for cpu in cpus {
+        std::fs::write(&cpu.path, governor)
+            .with_context(|| format!("Failed to write to {}", cpu.path.display()))?;
+    }
+    Ok(())
+}
+
+/// Put every CPU back on the governor it had when `cpus` was read.
+pub fn restore_cpu_governors(cpus: &[CpuGovernor]) -> Result<()> {
+    for cpu in cpus {
+        std::fs::write(&cpu.path, &cpu.governor)
+            .with_context(|| format!("Failed to write to {}", cpu.path.display()))?;
+    }
+    Ok(())
+}
+
+/// The distinct governors in use, comma separated, or `None` if unknown.
+pub fn governor_summary(cpus: &[CpuGovernor]) -> Option<String> {
+    let mut governors: Vec<&str> = cpus.iter().map(|cpu| cpu.governor.as_str()).collect();
+    governors.sort_unstable();
+    governors.dedup();
+    if governors.is_empty() {
+        None
+    } else {
+        Some(governors.join(","))
+    }
+}
+
+/// The CPUs a list like `2-5,7` names, as `taskset -c` and `isolcpus` take them.
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in list.split(',').map(str::trim) {
+        let cpu = |s: &str| {
+            s.parse::<usize>()
+                .with_context(|| format!("`{}` is not a CPU number", s))
+        };
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (cpu(first)?, cpu(last)?);
+                if first > last {
+                    anyhow::bail!("CPU range `{}` is backwards", part);
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(cpu(part)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// The CPUs the kernel keeps the scheduler off, from its `isolcpus` parameter, or
+/// `None` if that can't be read.
+#[cfg(target_os = "linux")]
+pub fn isolated_cpus() -> Option<Vec<usize>> {
+    let list = std::fs::read_to_string("/sys/devices/system/cpu/isolated").ok()?;
+    let list = list.trim();
+    if list.is_empty() {
+        return Some(Vec::new());
+    }
+    parse_cpu_list(list).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn isolated_cpus() -> Option<Vec<usize>> {
+    None
+}
+
+/// How many times the kernel log says bitcoind was killed for running out of
+/// memory, or `None` if it can't be read, which usually needs root.
+#[cfg(target_os = "linux")]
+pub fn oom_kills() -> Option<usize> {
+    let output = std::process::Command::new("dmesg").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // e.g. "Out of memory: Killed process 1234 (bitcoind) total-vm:..."
+    let kills = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains("Killed process") && line.contains("(bitcoind)"))
+        .count();
+    Some(kills)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn oom_kills() -> Option<usize> {
+    None
+}
+
+/// Flush dirty pages and drop the page, dentry and inode caches. Requires root.
+#[cfg(target_os = "linux")]
+pub fn drop_caches() -> Result<()> {
+    std::process::Command::new("sync")
+        .status()
+        .with_context(|| "Failed to execute sync")?;
+    std::fs::write("/proc/sys/vm/drop_caches", "3")
+        .with_context(|| "Failed to write to /proc/sys/vm/drop_caches")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_caches() -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Dropping page caches is only supported on Linux"
+    ))
+}
@@ -0,0 +1,180 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// What the sampler saw over the course of a benchmark.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Summary {
+    /// Whether the CPUs throttled, by the kernel's throttle counters or else by
+    /// most of them running below base frequency; `None` if neither is exposed
+    pub throttled: Option<bool>,
+    /// Hottest thermal zone reading, in degrees Celsius
+    pub max_temp_c: Option<f64>,
+    /// Lowest frequency any CPU ran at, in MHz
+    pub min_freq_mhz: Option<f64>,
+}
+
+/// Samples CPU temperature and frequency in the background while a benchmark runs.
+pub struct Sampler {
+    stop: Arc<AtomicBool>,
+    summary: Arc<Mutex<Summary>>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Sampler {
+    /// Start sampling every `interval`. The throttle counters are read now, so
+    /// only throttling during the benchmark counts. Frequencies are judged on
+    /// `cpus`, the CPUs the benchmark is pinned to, or every CPU if `None`.
+    pub fn start(interval: Duration, cpus: Option<Vec<usize>>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let summary = Arc::new(Mutex::new(Summary::default()));
+        let handle = {
+            let (stop, summary) = (Arc::clone(&stop), Arc::clone(&summary));
+            thread::spawn(move || sample(interval, cpus.as_deref(), &stop, &summary))
+        };
+        Sampler {
+            stop,
+            summary,
+            handle,
+        }
+    }
+
+    pub fn finish(self) -> Summary {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+        let summary = *self.summary.lock().expect("sampler thread panicked");
+        summary
+    }
+}
+
+fn sample(interval: Duration, cpus: Option<&[usize]>, stop: &AtomicBool, summary: &Mutex<Summary>) {
+    let throttle_start = throttle_count();
+    loop {
+        let reading = read(cpus);
+        {
+            let mut summary = summary.lock().expect("sampler lock poisoned");
+            summary.max_temp_c = max(summary.max_temp_c, reading.max_temp_c);
+            summary.min_freq_mhz = min(summary.min_freq_mhz, reading.min_freq_mhz);
+            let throttled = match (throttle_start, throttle_count()) {
+                (Some(start), Some(now)) => Some(now > start || reading.below_base),
+                _ => reading.base_known.then_some(reading.below_base),
+            };
+            if let Some(throttled) = throttled {
+                summary.throttled = Some(summary.throttled.unwrap_or(false) || throttled);
+            }
+        }
+
+        // Sleep in short steps so finishing doesn't wait out a long interval
+        let mut slept = Duration::ZERO;
+        while slept < interval {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let step = (interval - slept).min(Duration::from_millis(200));
+            thread::sleep(step);
+            slept += step;
+        }
+    }
+}
+
+fn max(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    [a, b].into_iter().flatten().reduce(f64::max)
+}
+
+fn min(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    [a, b].into_iter().flatten().reduce(f64::min)
+}
+
+#[derive(Default)]
+struct Reading {
+    max_temp_c: Option<f64>,
+    min_freq_mhz: Option<f64>,
+    /// Whether most of the judged CPUs that expose a base frequency ran below it.
+    /// Idle cores of intel_pstate clock down below base, so a single one doesn't
+    /// count as throttling.
+    below_base: bool,
+    /// Whether any judged CPU exposes its base frequency
+    base_known: bool,
+}
+
+#[cfg(target_os = "linux")]
+fn read(cpus: Option<&[usize]>) -> Reading {
+    let mut reading = Reading::default();
+
+    // Zone temperatures are in millidegrees
+    for zone in sysfs_entries("/sys/class/thermal", "thermal_zone") {
+        if let Some(temp) = read_number(&zone.join("temp")) {
+            reading.max_temp_c = max(reading.max_temp_c, Some(temp / 1000.0));
+        }
+    }
+
+    // Frequencies are in kHz. `base_frequency` is only exposed by intel_pstate.
+    let (mut with_base, mut below_base) = (0, 0);
+    for cpu in sysfs_entries("/sys/devices/system/cpu", "cpu") {
+        let Some(current) = read_number(&cpu.join("cpufreq/scaling_cur_freq")) else {
+            continue;
+        };
+        reading.min_freq_mhz = min(reading.min_freq_mhz, Some(current / 1000.0));
+        let judged = cpus.is_none_or(|cpus| cpu_number(&cpu).is_some_and(|n| cpus.contains(&n)));
+        if let (true, Some(base)) = (judged, read_number(&cpu.join("cpufreq/base_frequency"))) {
+            with_base += 1;
+            if current < base {
+                below_base += 1;
+            }
+        }
+    }
+    reading.base_known = with_base > 0;
+    reading.below_base = below_base * 2 > with_base;
+    reading
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read(_cpus: Option<&[usize]>) -> Reading {
+    Reading::default()
+}
+
+/// Total thermal throttle events the kernel has counted across every CPU.
+#[cfg(target_os = "linux")]
+fn throttle_count() -> Option<u64> {
+    let counts: Vec<u64> = sysfs_entries("/sys/devices/system/cpu", "cpu")
+        .iter()
+        .filter_map(|cpu| {
+            let count = read_number(&cpu.join("thermal_throttle/core_throttle_count"))?;
+            Some(count as u64)
+        })
+        .collect();
+    (!counts.is_empty()).then(|| counts.iter().sum())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn throttle_count() -> Option<u64> {
+    None
+}
+
+/// Entries of `dir` named `prefix` followed by a number, e.g. `cpu0`.
+#[cfg(target_os = "linux")]
+fn sysfs_entries(dir: &str, prefix: &str) -> Vec<std::path::PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            let suffix = name.to_str()?.strip_prefix(prefix)?;
+            (!suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit())).then(|| entry.path())
+        })
+        .collect()
+}
+
+/// The number of a sysfs CPU entry, e.g. 3 for `cpu3`.
+#[cfg(target_os = "linux")]
+fn cpu_number(cpu: &std::path::Path) -> Option<usize> {
+    cpu.file_name()?.to_str()?.strip_prefix("cpu")?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn read_number(path: &std::path::Path) -> Option<f64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use crate::runner::Runner;
+
+/// Versions of the tools a commit was built with, each the first line of its
+/// `--version` output, e.g. `cc (Ubuntu 13.2.0-23ubuntu4) 13.2.0`. Stored as JSON
+/// so a change in performance can be told apart from a toolchain upgrade.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Toolchain {
+    pub cc: Option<String>,
+    pub cxx: Option<String>,
+    pub cmake: Option<String>,
+}
+
+impl Toolchain {
+    /// The toolchain the build through `runner` uses. Like CMake, prefers the
+    /// compilers named by `CC` and `CXX`.
+    pub fn detect(runner: &dyn Runner, shell: &str) -> Self {
+        Toolchain {
+            cc: version(runner, shell, "${CC:-cc}"),
+            cxx: version(runner, shell, "${CXX:-c++}"),
+            cmake: version(runner, shell, "cmake"),
+        }
+    }
+
+    /// The toolchain as stored, `None` if no tool could be found.
+    pub fn to_json(&self) -> Option<String> {
+        let found = self.cc.is_some() || self.cxx.is_some() || self.cmake.is_some();
+        found
+            .then_some(self)
+            .and_then(|toolchain| serde_json::to_string(toolchain).ok())
+    }
+
+    /// One line naming each tool's version, e.g. for a report.
+    pub fn summary(&self) -> String {
+        [("cc", &self.cc), ("c++", &self.cxx), ("cmake", &self.cmake)]
+            .iter()
+            .filter_map(|(name, version)| version.as_ref().map(|v| format!("{}: {}", name, v)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// First line of what `tool --version` prints, with `tool` expanded by `shell`.
+fn version(runner: &dyn Runner, shell: &str, tool: &str) -> Option<String> {
+    let script = format!("{} --version", tool);
+    let output = runner.command(shell, &["-c", &script]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next()?.trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
@@ -0,0 +1,348 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::output::Style;
+
+/// Browse the `limit` most recently stored results interactively. Read-only.
+#[cfg(feature = "tui")]
+pub fn run(conn: &Connection, limit: usize, style: &Style) -> Result<()> {
+    let results = crate::db::recent_results(conn, limit, None)?;
+    let mut terminal = ratatui::init();
+    let outcome = app::App::new(conn, results, style).run(&mut terminal);
+    ratatui::restore();
+    outcome
+}
+
+#[cfg(not(feature = "tui"))]
+pub fn run(_conn: &Connection, _limit: usize, _style: &Style) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "The results browser requires building with `--features tui`"
+    ))
+}
+
+#[cfg(feature = "tui")]
+mod app {
+    use anyhow::Result;
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Modifier, Style};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Sparkline};
+    use ratatui::{DefaultTerminal, Frame};
+    use rusqlite::Connection;
+
+    use crate::db::{self, StoredResult};
+    use crate::list;
+    use crate::output;
+
+    pub struct App<'a> {
+        conn: &'a Connection,
+        /// Newest first
+        results: Vec<StoredResult>,
+        hash_len: usize,
+        style: output::Style,
+        /// The unit means are listed in
+        unit: output::TimeUnit,
+        /// Only runs whose command contains this are listed
+        filter: String,
+        editing_filter: bool,
+        /// Indices into `results` of the listed runs
+        visible: Vec<usize>,
+        list: ListState,
+        /// Runs marked for comparison, at most two, in the order they were marked
+        marked: Vec<usize>,
+        /// Columns of the selected run, queried again only when the selection changes
+        details: Option<(i64, Vec<(String, String)>)>,
+        details_scroll: u16,
+    }
+
+    impl<'a> App<'a> {
+        pub fn new(
+            conn: &'a Connection,
+            results: Vec<StoredResult>,
+            style: &output::Style,
+        ) -> Self {
+            let hash_len = output::hash_length(
+                results.iter().map(|r| r.commit_hash.as_str()),
+                style.hash_len,
+            );
+            let unit = style.unit_for(results.iter().filter_map(|r| r.mean));
+            let mut app = App {
+                conn,
+                results,
+                hash_len,
+                style: *style,
+                unit,
+                filter: String::new(),
+                editing_filter: false,
+                visible: Vec::new(),
+                list: ListState::default(),
+                marked: Vec::new(),
+                details: None,
+                details_scroll: 0,
+            };
+            app.apply_filter();
+            app
+        }
+
+        pub fn run(mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+            loop {
+                self.load_details()?;
+                terminal.draw(|frame| self.draw(frame))?;
+                let Event::Key(key) = event::read()? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+
+                if self.editing_filter {
+                    match key.code {
+                        KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                        KeyCode::Backspace => {
+                            self.filter.pop();
+                            self.apply_filter();
+                        }
+                        KeyCode::Char(c) => {
+                            self.filter.push(c);
+                            self.apply_filter();
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                    KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                    KeyCode::PageDown => self.list.scroll_down_by(10),
+                    KeyCode::PageUp => self.list.scroll_up_by(10),
+                    KeyCode::Home | KeyCode::Char('g') => self.list.select_first(),
+                    KeyCode::End | KeyCode::Char('G') => self.list.select_last(),
+                    KeyCode::Char(' ') => self.toggle_mark(),
+                    KeyCode::Char('/') => self.editing_filter = true,
+                    KeyCode::Char('J') => {
+                        self.details_scroll = self.details_scroll.saturating_add(1)
+                    }
+                    KeyCode::Char('K') => {
+                        self.details_scroll = self.details_scroll.saturating_sub(1)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn apply_filter(&mut self) {
+            self.visible = (0..self.results.len())
+                .filter(|&i| self.results[i].command.contains(&self.filter))
+                .collect();
+            self.list.select((!self.visible.is_empty()).then_some(0));
+        }
+
+        /// Index into `results` of the selected run. The list state may point past
+        /// the end until it is next drawn.
+        fn selected(&self) -> Option<usize> {
+            let selected = self.list.selected()?;
+            let last = self.visible.len().checked_sub(1)?;
+            Some(self.visible[selected.min(last)])
+        }
+
+        fn toggle_mark(&mut self) {
+            let Some(selected) = self.selected() else {
+                return;
+            };
+            if let Some(pos) = self.marked.iter().position(|&i| i == selected) {
+                self.marked.remove(pos);
+            } else {
+                self.marked.push(selected);
+                if self.marked.len() > 2 {
+                    self.marked.remove(0);
+                }
+            }
+        }
+
+        fn load_details(&mut self) -> Result<()> {
+            let Some(selected) = self.selected() else {
+                self.details = None;
+                return Ok(());
+            };
+            let id = self.results[selected].id;
+            if self
+                .details
+                .as_ref()
+                .is_none_or(|(loaded, _)| *loaded != id)
+            {
+                self.details = Some((id, db::result_columns(self.conn, id)?));
+                self.details_scroll = 0;
+            }
+            Ok(())
+        }
+
+        fn label(&self, result: &'a StoredResult) -> &'a str {
+            result
+                .commit_alias
+                .as_deref()
+                .unwrap_or_else(|| output::short_hash(&result.commit_hash, self.hash_len))
+        }
+
+        fn draw(&mut self, frame: &mut Frame) {
+            let [list_area, bottom, help_area] = Layout::vertical([
+                Constraint::Percentage(50),
+                Constraint::Min(8),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+            let [details_area, right] =
+                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(bottom);
+            let [sparkline_area, compare_area] =
+                Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(right);
+
+            let items: Vec<ListItem> = self
+                .visible
+                .iter()
+                .map(|&i| {
+                    let result = &self.results[i];
+                    let mark = if self.marked.contains(&i) { "*" } else { " " };
+                    ListItem::new(format!(
+                        "{} {:>5}  {:<12}  {:>10}  {:<14}  {}",
+                        mark,
+                        result.id,
+                        self.label(result),
+                        result
+                            .mean
+                            .map_or("-".to_string(), |m| self.style.duration_in(m, self.unit)),
+                        list::status_label(result),
+                        result.command
+                    ))
+                })
+                .collect();
+            let title = if self.filter.is_empty() {
+                format!("Runs ({})", self.visible.len())
+            } else {
+                format!("Runs matching `{}` ({})", self.filter, self.visible.len())
+            };
+            let list = List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, list_area, &mut self.list);
+
+            let details: Vec<Line> = self
+                .details
+                .iter()
+                .flat_map(|(_, columns)| columns)
+                .map(|(name, value)| match name.as_str() {
+                    "created_at" => format!("{}: {}", name, self.style.local_time(value)),
+                    _ => format!("{}: {}", name, value),
+                })
+                .map(Line::from)
+                .collect();
+            frame.render_widget(
+                Paragraph::new(details)
+                    .scroll((self.details_scroll, 0))
+                    .block(Block::bordered().title("Details")),
+                details_area,
+            );
+
+            self.draw_sparkline(frame, sparkline_area);
+            frame.render_widget(
+                Paragraph::new(self.comparison()).block(Block::bordered().title("Compare")),
+                compare_area,
+            );
+
+            let help = if self.editing_filter {
+                format!("filter: {}_  (enter to finish)", self.filter)
+            } else {
+                "↑/↓ select  space mark for comparison  / filter by command  J/K scroll details  q quit"
+                    .to_string()
+            };
+            frame.render_widget(Paragraph::new(help), help_area);
+        }
+
+        /// Mean over time of the selected run's command, scaled between its slowest
+        /// and fastest runs so small changes stay visible.
+        fn draw_sparkline(&self, frame: &mut Frame, area: ratatui::layout::Rect) {
+            let Some(selected) = self.selected().map(|i| &self.results[i]) else {
+                frame.render_widget(Block::bordered().title("Mean over time"), area);
+                return;
+            };
+            let means: Vec<f64> = self
+                .results
+                .iter()
+                .rev()
+                .filter(|r| {
+                    r.command == selected.command && r.snapshot_height == selected.snapshot_height
+                })
+                .filter_map(|r| r.mean)
+                .collect();
+            let min = means.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = means.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let range = (max - min).max(f64::EPSILON);
+            let data: Vec<u64> = means
+                .iter()
+                .map(|mean| ((mean - min) / range * 100.0) as u64 + 1)
+                .collect();
+
+            let title = if means.is_empty() {
+                "Mean over time".to_string()
+            } else {
+                let unit = self.style.unit_for([min, max]);
+                format!(
+                    "Mean over time: {} runs, {} to {}",
+                    means.len(),
+                    self.style.duration_in(min, unit),
+                    self.style.duration_in(max, unit)
+                )
+            };
+            frame.render_widget(
+                Sparkline::default()
+                    .data(&data)
+                    .max(101)
+                    .block(Block::bordered().title(title)),
+                area,
+            );
+        }
+
+        fn comparison(&self) -> Vec<Line<'_>> {
+            let [base, other] = self.marked[..] else {
+                return vec![Line::from("Mark two runs with space to compare them")];
+            };
+            let (base, other) = (&self.results[base], &self.results[other]);
+            let unit = self
+                .style
+                .unit_for([base.mean, other.mean].into_iter().flatten());
+            let mean = |result: &StoredResult| {
+                result
+                    .mean
+                    .map_or("-".to_string(), |m| self.style.duration_in(m, unit))
+            };
+            let mut lines = vec![
+                Line::from(format!(
+                    "{} run {}: {}",
+                    self.label(base),
+                    base.id,
+                    mean(base)
+                )),
+                Line::from(format!(
+                    "{} run {}: {}",
+                    self.label(other),
+                    other.id,
+                    mean(other)
+                )),
+            ];
+            if let (Some(base_mean), Some(mean)) = (base.mean, other.mean) {
+                lines.push(Line::from(format!(
+                    "Change: {:+.1}%",
+                    (mean - base_mean) / base_mean * 100.0
+                )));
+            }
+            if base.command != other.command || base.snapshot_height != other.snapshot_height {
+                lines.push(Line::from(
+                    "Warning: the runs timed different commands and are not comparable",
+                ));
+            }
+            lines
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use serde::de::DeserializeOwned;
+
+use crate::Parameters;
+
+/// JSON columns every row is checked for.
+const JSON_COLUMNS: &[&str] = &["times", "exit_codes", "parameters"];
+
+/// Every hyperfine version results were recorded with, oldest first.
+pub fn hyperfine_versions(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT hyperfine_version FROM benchmarks WHERE hyperfine_version IS NOT NULL
+         GROUP BY hyperfine_version ORDER BY MIN(id)",
+    )?;
+    let versions = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()
+        .with_context(|| "Failed to read hyperfine versions")?;
+    Ok(versions)
+}
+
+/// A JSON column that no longer deserializes into the type it was written from.
+pub struct InvalidColumn {
+    pub id: i64,
+    pub column: &'static str,
+    pub error: String,
+}
+
+/// Check that the JSON columns of every row still parse. NULL columns, as stored
+/// for build failures, are accepted.
+pub fn find_invalid(conn: &Connection) -> Result<Vec<InvalidColumn>> {
+    let mut stmt = conn.prepare("SELECT id, times, exit_codes, parameters FROM benchmarks")?;
+    let mut rows = stmt.query([])?;
+
+    let mut invalid = Vec::new();
+    while let Some(row) = rows
+        .next()
+        .with_context(|| "Failed to read benchmark rows")?
+    {
+        let id: i64 = row.get(0)?;
+        let columns: [Option<String>; 3] = [row.get(1)?, row.get(2)?, row.get(3)?];
+        let checks = [
+            check::<Vec<f64>>(columns[0].as_deref()),
+            check::<Vec<Option<i32>>>(columns[1].as_deref()),
+            check::<Option<Parameters>>(columns[2].as_deref()),
+        ];
+        for (column, check) in JSON_COLUMNS.iter().zip(checks) {
+            if let Err(e) = check {
+                invalid.push(InvalidColumn {
+                    id,
+                    column,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(invalid)
+}
+
+/// Set each invalid column to NULL so readers skip it instead of failing.
+pub fn fix(conn: &Connection, invalid: &[InvalidColumn]) -> Result<()> {
+    for column in invalid {
+        conn.execute(
+            &format!(
+                "UPDATE benchmarks SET {} = NULL WHERE id = ?1",
+                column.column
+            ),
+            [column.id],
+        )
+        .with_context(|| format!("Failed to clear {} of row {}", column.column, column.id))?;
+    }
+    Ok(())
+}
+
+fn check<T: DeserializeOwned>(json: Option<&str>) -> serde_json::Result<()> {
+    match json {
+        Some(json) => serde_json::from_str::<T>(json).map(|_| ()),
+        None => Ok(()),
+    }
+}